@@ -0,0 +1,44 @@
+/* The C API of stash, built with the `capi` feature, see src/capi.rs. */
+
+#ifndef STASH_H
+#define STASH_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct stash_store stash_store;
+
+/* The message of the last failure on this thread, NULL if nothing failed yet. */
+const char *stash_last_error(void);
+
+/* The local store in data_dir, or the configured store if it's NULL. NULL on failure. */
+stash_store *stash_open(const char *data_dir);
+void stash_close(stash_store *store);
+
+/* Pushes len bytes as the newest stash of name, "" for the unnamed stack. On
+ * success returns 0 and sets *id, if id isn't NULL, to the new stash's id, to be
+ * freed with stash_free_string. -1 on failure. */
+int stash_push(const stash_store *store, const char *name, const uint8_t *data, size_t len,
+               char **id);
+
+/* Removes the stash with the id, like "logs:1", and sets *data and *len to its
+ * contents, to be freed with stash_free_bytes. Returns 0 if it was popped, 1 if it
+ * doesn't exist and -1 on failure. */
+int stash_pop(const stash_store *store, const char *id, uint8_t **data, size_t *len);
+
+/* The stashes, newest first, as a JSON array of
+ * {"id", "name", "index", "size", "created", "locked"} objects. NULL on failure. */
+char *stash_list(const stash_store *store);
+
+void stash_free_string(char *string);
+void stash_free_bytes(uint8_t *data, size_t len);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
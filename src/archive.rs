@@ -0,0 +1,85 @@
+use camino::Utf8Path as Path;
+use eyre::Result;
+use fs_err::PathExt;
+use serde::{Deserialize, Serialize};
+
+use crate::meta::Meta;
+use crate::storage::{Data, StorageBackend};
+
+/// How a stash's contents are packed, if they aren't just a file's bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Archive {
+    /// A directory as an uncompressed tar archive, with the directory's
+    /// contents at its root
+    Tar,
+}
+
+/// Pushes the directory `dir` as a tar archive.
+pub fn push_dir(store: &dyn StorageBackend, name: &str, meta: Meta, dir: &Path) -> Result<Data> {
+    let meta = Meta {
+        archive: Some(Archive::Tar),
+        ..meta
+    };
+    let (mut reader, writer) = std::io::pipe()?;
+    std::thread::scope(|scope| {
+        // the archive is streamed into the stash instead of being built in memory first
+        let packer = scope.spawn(move || -> Result<()> {
+            let mut builder = tar::Builder::new(writer);
+            builder.append_dir_all(".", dir)?;
+            builder.finish()?;
+            Ok(())
+        });
+        let pushed = crate::push(store, name, &meta, &mut reader);
+        // unblocks the packer if the push failed before reading everything
+        drop(reader);
+        packer.join().unwrap()?;
+        pushed
+    })
+}
+
+/// The size of the archive `push_dir` makes of `dir`, for checking a quota before
+/// pushing it: a 512 byte header per entry, the contents padded to 512 bytes and
+/// two empty blocks at the end. Long paths take another header, which isn't counted.
+pub fn archive_size(dir: &Path) -> Result<u64> {
+    fn entries(dir: &std::path::Path) -> Result<u64> {
+        let mut size = 0;
+        for entry in dir.fs_err_read_dir()? {
+            let path = entry?.path();
+            // symlinks are followed, like `append_dir_all` does
+            let metadata = fs_err::metadata(&path)?;
+            size += 512;
+            size += match metadata.is_dir() {
+                true => entries(&path)?,
+                false => metadata.len().div_ceil(512) * 512,
+            };
+        }
+        Ok(size)
+    }
+    Ok(512 + entries(dir.as_std_path())? + 2 * 512)
+}
+
+/// Writes the stash back to `path`, unpacking it if it's an archive.
+/// Refuses to overwrite anything already there.
+pub fn restore(
+    store: &dyn StorageBackend,
+    stash: &Data,
+    archive: Option<Archive>,
+    path: &Path,
+) -> Result<()> {
+    let mut contents = store.reader(stash)?;
+    match archive {
+        Some(Archive::Tar) => {
+            fs_err::create_dir(path)?;
+            tar::Archive::new(contents).unpack(path)?;
+        }
+        None => {
+            let mut file = fs_err::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(path)?;
+            std::io::copy(&mut contents, &mut file)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,176 @@
+//! Snapshots of a profile's data dir, and rolling it back to one.
+//!
+//! A backup dir holds one snapshot per backup, named after the time it was taken.
+//! Files that haven't changed since the previous snapshot are hard links to its
+//! copy, so every snapshot is complete but only changes take up space.
+//!
+//! The data dir of the default profile holds those of the other profiles in
+//! `profiles`, they are left out and left alone.
+
+use std::io::Write;
+
+use camino::{Utf8Path as Path, Utf8PathBuf as PathBuf};
+use eyre::{eyre, Result};
+use fs_err::PathExt;
+
+/// Half-written files that aren't worth keeping, the other profiles, and what
+/// restores replaced.
+const SKIPPED: &[&str] = &["tmp", "profiles", REPLACED];
+/// Where `restore` moves the stashes it replaces, within the data dir.
+const REPLACED: &str = "before-restore";
+/// Marks a snapshot that isn't complete yet.
+const PARTIAL: &str = ".partial";
+
+/// Takes a snapshot of the data dir `root`. If `to` is a file or ends in `.tar.zst`,
+/// it's written there as a zstd-compressed tar archive, otherwise it's added to
+/// the backup dir `to`. Returns the path of the snapshot.
+pub fn backup(root: &Path, to: &Path) -> Result<PathBuf> {
+    if to.is_file() || to.as_str().ends_with(".tar.zst") {
+        write_archive(root, to)?;
+        return Ok(to.to_owned());
+    }
+
+    fs_err::create_dir_all(to)?;
+    let previous = latest_snapshot(to)?;
+    let snapshot = unused(to.join(timestamp()?));
+    let partial = PathBuf::from(format!("{snapshot}{PARTIAL}"));
+    fs_err::create_dir(&partial)?;
+    copy_tree(root, &partial, previous.as_deref())?;
+    fs_err::rename(partial, &snapshot)?;
+    Ok(snapshot)
+}
+
+/// Replaces the contents of the data dir `root` with a snapshot: `from` itself, the
+/// newest one in the backup dir `from` or the archive `from`. What was replaced
+/// is kept in `before-restore` of the data dir, its path is returned.
+pub fn restore(root: &Path, from: &Path) -> Result<PathBuf> {
+    let staging = root
+        .join("tmp")
+        .join(format!("restore.{}", std::process::id()));
+    if staging.exists() {
+        fs_err::remove_dir_all(&staging)?;
+    }
+    fs_err::create_dir_all(&staging)?;
+    if from.is_file() {
+        let archive = zstd::Decoder::new(fs_err::File::open(from)?)?;
+        tar::Archive::new(archive).unpack(&staging)?;
+    } else {
+        let snapshot = match from.join("meta").is_dir() {
+            true => from.to_owned(),
+            false => latest_snapshot(from)?.ok_or_else(|| eyre!("no snapshots in {from}"))?,
+        };
+        // copied without links, so changing the store doesn't change the snapshot
+        copy_tree(&snapshot, &staging, None)?;
+    }
+
+    let old = unused(root.join(REPLACED).join(timestamp()?));
+    fs_err::create_dir_all(&old)?;
+    for name in entries(root)? {
+        fs_err::rename(root.join(&name), old.join(&name))?;
+    }
+    // snapshots from before they were left out may have other profiles in them
+    for name in entries(&staging)? {
+        fs_err::rename(staging.join(&name), root.join(&name))?;
+    }
+    fs_err::remove_dir_all(&staging)?;
+    Ok(old)
+}
+
+/// The files and dirs in `dir` that belong to its profile's stashes.
+fn entries(dir: &Path) -> Result<Vec<String>> {
+    let mut names = vec![];
+    for entry in dir.as_std_path().fs_err_read_dir()? {
+        let name = entry?.file_name().to_string_lossy().into_owned();
+        if !SKIPPED.contains(&name.as_str()) {
+            names.push(name);
+        }
+    }
+    Ok(names)
+}
+
+fn timestamp() -> Result<String> {
+    let format =
+        time::macros::format_description!("[year]-[month]-[day]T[hour]-[minute]-[second]Z");
+    Ok(time::OffsetDateTime::now_utc().format(&format)?)
+}
+
+/// `path`, or with a number appended if that's taken, e.g. by a backup in the
+/// same second.
+fn unused(path: PathBuf) -> PathBuf {
+    let taken = |path: &Path| path.exists() || Path::new(&format!("{path}{PARTIAL}")).exists();
+    let mut candidate = path.clone();
+    let mut n = 1;
+    while taken(&candidate) {
+        candidate = PathBuf::from(format!("{path}-{n}"));
+        n += 1;
+    }
+    candidate
+}
+
+/// The newest complete snapshot in a backup dir.
+/// Their names are timestamps, so the newest sorts last.
+fn latest_snapshot(dir: &Path) -> Result<Option<PathBuf>> {
+    let mut latest = None;
+    for entry in dir.as_std_path().fs_err_read_dir()? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if entry.file_type()?.is_dir() && !name.ends_with(PARTIAL) {
+            latest = latest.max(Some(name));
+        }
+    }
+    Ok(latest.map(|name| dir.join(name)))
+}
+
+/// Copies the data dir `from` into `to`, linking to the file in `previous` instead
+/// if it has the same size and modification time.
+fn copy_tree(from: &Path, to: &Path, previous: Option<&Path>) -> Result<()> {
+    for entry in from.as_std_path().fs_err_read_dir()? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if from.join(&name).is_dir() {
+            if SKIPPED.contains(&name.as_str()) {
+                continue;
+            }
+            fs_err::create_dir(to.join(&name))?;
+            let previous = previous.map(|previous| previous.join(&name));
+            copy_tree(&from.join(&name), &to.join(&name), previous.as_deref())?;
+            continue;
+        }
+
+        let metadata = entry.metadata()?;
+        let unchanged = previous
+            .map(|previous| previous.join(&name))
+            .and_then(|previous| Some((fs_err::metadata(&previous).ok()?, previous)))
+            .filter(|(old, _)| {
+                old.len() == metadata.len() && old.modified().ok() == metadata.modified().ok()
+            });
+        match unchanged {
+            Some((_, previous)) => fs_err::hard_link(previous, to.join(&name))?,
+            None => {
+                fs_err::copy(from.join(&name), to.join(&name))?;
+                // what the next snapshot compares against
+                fs_err::File::open(to.join(&name))?
+                    .file()
+                    .set_modified(metadata.modified()?)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn write_archive(root: &Path, path: &Path) -> Result<()> {
+    let file = std::io::BufWriter::new(fs_err::File::create(path)?);
+    let mut builder = tar::Builder::new(zstd::Encoder::new(file, 0)?);
+    for entry in root.as_std_path().fs_err_read_dir()? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let path = entry.path();
+        match entry.file_type()?.is_dir() {
+            true if SKIPPED.iter().any(|skipped| name == *skipped) => {}
+            true => builder.append_dir_all(&name, &path)?,
+            false => builder.append_path_with_name(&path, &name)?,
+        }
+    }
+    builder.into_inner()?.finish()?.flush()?;
+    Ok(())
+}
@@ -0,0 +1,90 @@
+use std::time::{Duration, Instant};
+
+use eyre::{eyre, Result};
+
+use crate::meta::Meta;
+use crate::storage::{Data, StorageBackend};
+
+/// Times `iterations` rounds of push, list, show and pop against the store and
+/// prints mean and percentile latencies for each.
+///
+/// The stashes go to a name of their own and are popped again at the end, so the
+/// store is left as it was. The event log is bypassed to not flood it.
+pub fn run(store: &dyn StorageBackend, iterations: usize, size: usize) -> Result<()> {
+    let name = format!("bench/{}", std::process::id());
+    let contents = vec![b'x'; size];
+    let existing = store.names()?.len();
+
+    let mut push = vec![];
+    for _ in 0..iterations {
+        push.push(time(|| {
+            store.create(&name, &Meta::default(), &mut contents.as_slice())?;
+            Ok(())
+        })?);
+    }
+
+    let mut list = vec![];
+    for _ in 0..iterations {
+        list.push(time(|| Data::detect(store).map(drop))?);
+    }
+
+    let mut show = vec![];
+    for _ in 0..iterations {
+        show.push(time(|| {
+            let stash = store
+                .newest(&name)?
+                .ok_or_else(|| eyre!("benchmark stash disappeared"))?;
+            std::io::copy(&mut store.reader(&stash)?, &mut std::io::sink())?;
+            Ok(())
+        })?);
+    }
+
+    let mut pop = vec![];
+    for _ in 0..iterations {
+        pop.push(time(|| {
+            let stash = store
+                .newest(&name)?
+                .ok_or_else(|| eyre!("benchmark stash disappeared"))?;
+            std::io::copy(&mut store.reader(&stash)?, &mut std::io::sink())?;
+            store.remove(&stash)?;
+            store.purge(&stash)
+        })?);
+    }
+
+    println!("{iterations} iterations, {size} byte stashes, {existing} other stashes in the store");
+    println!("op      ops/s     mean      p50       p99");
+    for (op, latencies) in [("push", push), ("list", list), ("show", show), ("pop", pop)] {
+        print_stats(op, latencies);
+    }
+    Ok(())
+}
+
+fn time(op: impl FnOnce() -> Result<()>) -> Result<Duration> {
+    let start = Instant::now();
+    op()?;
+    Ok(start.elapsed())
+}
+
+fn print_stats(op: &str, mut latencies: Vec<Duration>) {
+    if latencies.is_empty() {
+        return;
+    }
+    latencies.sort();
+    let total: Duration = latencies.iter().sum();
+    let mean = total / latencies.len() as u32;
+    let percentile = |p: usize| latencies[(latencies.len() - 1) * p / 100];
+    println!(
+        "{op:<7} {:<9.0} {:<9} {:<9} {}",
+        latencies.len() as f64 / total.as_secs_f64(),
+        format_latency(mean),
+        format_latency(percentile(50)),
+        format_latency(percentile(99)),
+    );
+}
+
+fn format_latency(latency: Duration) -> String {
+    match latency.as_micros() {
+        micros @ 0..=9999 => format!("{micros}µs"),
+        _ => format!("{}ms", latency.as_millis()),
+    }
+}
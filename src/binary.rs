@@ -0,0 +1,54 @@
+use std::io::{Read, Write};
+
+/// How much of the beginning is looked at to tell text from binary data.
+pub const SNIFF_LEN: usize = 8192;
+
+/// Guesses from the beginning of some contents whether they are binary data,
+/// meaning they contain a null byte or more than a tenth isn't valid UTF-8.
+pub fn is_binary(start: &[u8]) -> bool {
+    if start.contains(&0) {
+        return true;
+    }
+    let invalid: usize = start.utf8_chunks().map(|chunk| chunk.invalid().len()).sum();
+    invalid * 10 > start.len()
+}
+
+/// Writes `reader` as an xxd-style hex dump, 16 bytes per line.
+pub fn hex_dump(reader: &mut dyn Read, out: &mut dyn Write) -> std::io::Result<()> {
+    let mut reader = std::io::BufReader::new(reader);
+    let mut offset = 0;
+    let mut line = [0; 16];
+    loop {
+        // fill the line completely, reads may return less
+        let mut len = 0;
+        while len < line.len() {
+            match reader.read(&mut line[len..])? {
+                0 => break,
+                n => len += n,
+            }
+        }
+        if len == 0 {
+            return Ok(());
+        }
+
+        write!(out, "{offset:08x}:")?;
+        for (i, pair) in line.chunks(2).enumerate() {
+            write!(out, " ")?;
+            for (j, byte) in pair.iter().enumerate() {
+                match i * 2 + j < len {
+                    true => write!(out, "{byte:02x}")?,
+                    false => write!(out, "  ")?,
+                }
+            }
+        }
+        let text = line[..len]
+            .iter()
+            .map(|&byte| match byte.is_ascii_graphic() || byte == b' ' {
+                true => byte as char,
+                false => '.',
+            })
+            .collect::<String>();
+        writeln!(out, "  {text}")?;
+        offset += len;
+    }
+}
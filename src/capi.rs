@@ -0,0 +1,192 @@
+//! The C API of the `capi` feature, declared in `include/stash.h`, for editors,
+//! window managers and other programs that would rather not spawn `stash` for
+//! every call. Build the shared library with
+//!
+//! ```sh
+//! cargo rustc --lib --release --features capi --crate-type cdylib
+//! ```
+//!
+//! Functions that can fail return a negative number or `NULL` and leave a message
+//! for `stash_last_error`. Whatever they hand out is freed with the matching
+//! `stash_free_*`.
+
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, CStr, CString};
+
+use eyre::{eyre, Result};
+
+use crate::embed::Embedded;
+use crate::stash_client::Summary;
+
+/// An open store, `stash_store` in C.
+pub struct Store(Embedded);
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_error(error: eyre::Report) {
+    // there are no nul bytes in our messages, but the paths in them could have some
+    let message = error.to_string().replace('\0', "\\0");
+    LAST_ERROR.with(|last| *last.borrow_mut() = CString::new(message).ok());
+}
+
+/// # Safety
+/// `string` must be `NULL` or a nul-terminated string.
+unsafe fn optional_str<'a>(string: *const c_char) -> Result<Option<&'a str>> {
+    if string.is_null() {
+        return Ok(None);
+    }
+    let string = CStr::from_ptr(string)
+        .to_str()
+        .map_err(|_| eyre!("strings must be valid UTF-8"))?;
+    Ok(Some(string))
+}
+
+/// The message of the last failure on this thread, `NULL` if nothing failed yet.
+/// It stays valid until the next failing call on the thread.
+#[no_mangle]
+pub extern "C" fn stash_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(std::ptr::null(), |message| message.as_ptr())
+    })
+}
+
+/// Opens the local store in `data_dir`, or the configured store if it's `NULL`.
+/// Returns `NULL` on failure.
+///
+/// # Safety
+/// `data_dir` must be `NULL` or a nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn stash_open(data_dir: *const c_char) -> *mut Store {
+    let store = optional_str(data_dir).and_then(Embedded::open);
+    match store {
+        Ok(store) => Box::into_raw(Box::new(Store(store))),
+        Err(e) => {
+            set_error(e);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// # Safety
+/// `store` must be `NULL` or from `stash_open`, and not used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn stash_close(store: *mut Store) {
+    if !store.is_null() {
+        drop(Box::from_raw(store));
+    }
+}
+
+/// Pushes the `len` bytes at `data` as the newest stash of `name`, `""` for the
+/// unnamed stack. Returns 0 and, if `id` isn't `NULL`, sets it to the id of the
+/// new stash, to be freed with `stash_free_string`. Returns -1 on failure.
+///
+/// # Safety
+/// `store` must be from `stash_open`, `name` a nul-terminated string, `data`
+/// valid for `len` bytes and `id` `NULL` or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn stash_push(
+    store: *const Store,
+    name: *const c_char,
+    data: *const u8,
+    len: usize,
+    id: *mut *mut c_char,
+) -> c_int {
+    let contents = match len {
+        0 => &[],
+        _ => std::slice::from_raw_parts(data, len),
+    };
+    let pushed = optional_str(name).and_then(|name| {
+        let name = name.ok_or_else(|| eyre!("the name mustn't be NULL"))?;
+        (*store).0.push(name, contents)
+    });
+    match pushed {
+        Ok(pushed) => {
+            if !id.is_null() {
+                *id = CString::new(pushed).unwrap().into_raw();
+            }
+            0
+        }
+        Err(e) => {
+            set_error(e);
+            -1
+        }
+    }
+}
+
+/// Removes the stash with the id, like `logs:1`, and hands out its contents: sets
+/// `data` to them, to be freed with `stash_free_bytes`, and `len` to their
+/// length. Returns 0 if it was popped, 1 if it doesn't exist and -1 on failure.
+/// Secret and encrypted stashes are only popped by `stash pop`.
+///
+/// # Safety
+/// `store` must be from `stash_open`, `id` a nul-terminated string and `data`
+/// and `len` valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn stash_pop(
+    store: *const Store,
+    id: *const c_char,
+    data: *mut *mut u8,
+    len: *mut usize,
+) -> c_int {
+    let popped = optional_str(id).and_then(|id| {
+        let id = id.ok_or_else(|| eyre!("the id mustn't be NULL"))?;
+        (*store).0.pop(id)
+    });
+    match popped {
+        Ok(Some(contents)) => {
+            *len = contents.len();
+            *data = Box::into_raw(contents.into_boxed_slice()).cast();
+            0
+        }
+        Ok(None) => 1,
+        Err(e) => {
+            set_error(e);
+            -1
+        }
+    }
+}
+
+/// The stashes, newest first, as a JSON array like `GET /stashes` of `stash
+/// serve` responds with. To be freed with `stash_free_string`, `NULL` on failure.
+///
+/// # Safety
+/// `store` must be from `stash_open`.
+#[no_mangle]
+pub unsafe extern "C" fn stash_list(store: *const Store) -> *mut c_char {
+    let list = (*store)
+        .0
+        .list()
+        .and_then(|stashes| stashes.iter().map(Summary::new).collect::<Result<Vec<_>>>())
+        .and_then(|summaries| Ok(serde_json::to_string(&summaries)?));
+    match list {
+        // JSON escapes nul bytes
+        Ok(list) => CString::new(list).unwrap().into_raw(),
+        Err(e) => {
+            set_error(e);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// # Safety
+/// `string` must be `NULL` or from this library, and not used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn stash_free_string(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+/// # Safety
+/// `data` and `len` must be `NULL` and 0 or from `stash_pop`, and `data` not used
+/// afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn stash_free_bytes(data: *mut u8, len: usize) {
+    if !data.is_null() {
+        drop(Box::from_raw(std::ptr::slice_from_raw_parts_mut(data, len)));
+    }
+}
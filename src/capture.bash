@@ -0,0 +1,36 @@
+# Records the output of every command for `stash last-output`, from
+# `stash shell-init bash --capture`. It goes through a pipe to `stash capture`
+# on its way to the terminal, so the programs in STASH_CAPTURE_IGNORE that need
+# the terminal itself are left alone. Uses the DEBUG trap.
+export STASH_CAPTURE_DIR="${XDG_RUNTIME_DIR:-${TMPDIR:-/tmp}}/stash-capture-$$"
+: "${STASH_CAPTURE_IGNORE:=vi vim nvim nano emacs less more man top htop ssh tmux screen fzf stash}"
+
+# the pid of `stash capture` while a command runs
+__stash_capturing=
+__stash_at_prompt=
+
+__stash_preexec() {
+    # completion functions run at the prompt too
+    [ -n "$__stash_at_prompt" ] && [ -z "$COMP_LINE" ] || return
+    __stash_at_prompt=
+    local program=${BASH_COMMAND%% *}
+    case " $STASH_CAPTURE_IGNORE " in *" $program "*) return ;; esac
+    local line
+    line=$(HISTTIMEFORMAT= builtin history 1)
+    [[ $line =~ ^\ *[0-9]+\*?\ +(.*)$ ]] && line=${BASH_REMATCH[1]} || line=$BASH_COMMAND
+    exec {__stash_out}>&1 {__stash_err}>&2 > >(command stash capture -- "$line") 2>&1
+    __stash_capturing=$!
+}
+
+__stash_precmd() {
+    if [ -n "$__stash_capturing" ]; then
+        exec 1>&"$__stash_out" 2>&"$__stash_err" {__stash_out}>&- {__stash_err}>&-
+        # so that all of the output is shown before the prompt
+        wait "$__stash_capturing" 2>/dev/null
+        __stash_capturing=
+    fi
+    __stash_at_prompt=1
+}
+
+trap '__stash_preexec' DEBUG
+PROMPT_COMMAND="${PROMPT_COMMAND:+$PROMPT_COMMAND;}__stash_precmd"
@@ -0,0 +1,62 @@
+use std::io::Read;
+
+use eyre::{eyre, Result};
+use sha2::{Digest, Sha256};
+
+use crate::storage::{Data, StorageBackend};
+
+/// Hashes everything read through it.
+pub struct HashingReader<R> {
+    reader: R,
+    hasher: Sha256,
+    read: u64,
+}
+
+impl<R: Read> HashingReader<R> {
+    pub fn new(reader: R) -> HashingReader<R> {
+        HashingReader {
+            reader,
+            hasher: Sha256::new(),
+            read: 0,
+        }
+    }
+
+    pub fn bytes_read(&self) -> u64 {
+        self.read
+    }
+
+    /// The SHA-256 of what was read, hex encoded.
+    pub fn sha256(self) -> String {
+        format!("{:x}", self.hasher.finalize())
+    }
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.reader.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.read += n as u64;
+        Ok(n)
+    }
+}
+
+pub fn sha256(reader: impl Read) -> Result<String> {
+    let mut reader = HashingReader::new(reader);
+    std::io::copy(&mut reader, &mut std::io::sink())?;
+    Ok(reader.sha256())
+}
+
+/// Fails if the stash's contents don't match the checksum recorded when it was
+/// pushed. Stashes from before checksums were recorded pass.
+pub fn verify(store: &dyn StorageBackend, stash: &Data) -> Result<()> {
+    let Some(expected) = store.meta(stash)?.sha256 else {
+        return Ok(());
+    };
+    match sha256(store.reader(stash)?)? == expected {
+        true => Ok(()),
+        false => Err(eyre!(
+            "Stash {} doesn't match its checksum, it's corrupted or truncated",
+            stash.id()
+        )),
+    }
+}
@@ -0,0 +1,60 @@
+use std::io::{Read, Write};
+use std::process::{Command, Stdio};
+
+use eyre::{eyre, Result};
+
+/// The text on the system clipboard.
+pub fn read() -> Result<String> {
+    Ok(arboard::Clipboard::new()?.get_text()?)
+}
+
+/// Puts `contents` on the system clipboard.
+///
+/// On Linux the clipboard's contents are served by the process that set them,
+/// so that's left to a copy of ourselves in the background, see `serve`.
+pub fn write(contents: Vec<u8>) -> Result<()> {
+    let text =
+        String::from_utf8(contents).map_err(|_| eyre!("only text can go to the clipboard"))?;
+    if cfg!(target_os = "linux") {
+        let mut server = Command::new(std::env::current_exe()?)
+            .arg("serve-clipboard")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        server.stdin.take().unwrap().write_all(text.as_bytes())?;
+        // it reports back once it's connected, or exits with an error
+        let mut ready = [0];
+        if server.stdout.take().unwrap().read(&mut ready)? == 0 {
+            let mut error = String::new();
+            server.stderr.take().unwrap().read_to_string(&mut error)?;
+            server.wait()?;
+            // just the message of its error report
+            let error = error.lines().next().unwrap_or_default();
+            return Err(eyre!(
+                "couldn't set the clipboard: {}",
+                error.trim_start_matches("Error: ")
+            ));
+        }
+        return Ok(());
+    }
+    arboard::Clipboard::new()?.set_text(text)?;
+    Ok(())
+}
+
+/// Sets the clipboard to the text on stdin and keeps serving it until something
+/// else is copied. Stdout gets a line as soon as the clipboard could be opened.
+pub fn serve() -> Result<()> {
+    let mut text = String::new();
+    std::io::stdin().read_to_string(&mut text)?;
+    let mut clipboard = arboard::Clipboard::new()?;
+    println!();
+    #[cfg(target_os = "linux")]
+    {
+        use arboard::SetExtLinux;
+        clipboard.set().wait().text(text)?;
+    }
+    #[cfg(not(target_os = "linux"))]
+    clipboard.set_text(text)?;
+    Ok(())
+}
@@ -0,0 +1,114 @@
+use std::io::{BufRead, BufReader, Read, Write};
+
+use eyre::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::storage::Finish;
+
+/// Compressed formats that can be recognized by their magic bytes. Compressing
+/// them again wouldn't make them any smaller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    Gzip,
+    Zstd,
+    Png,
+    Jpeg,
+}
+
+impl Format {
+    pub fn detect(bytes: &[u8]) -> Option<Format> {
+        if bytes.starts_with(&[0x1f, 0x8b]) {
+            Some(Format::Gzip)
+        } else if bytes.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(Format::Zstd)
+        } else if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+            Some(Format::Png)
+        } else if bytes.starts_with(&[0xff, 0xd8, 0xff]) {
+            Some(Format::Jpeg)
+        } else {
+            None
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Format::Gzip => "gzip",
+            Format::Zstd => "zstd",
+            Format::Png => "png",
+            Format::Jpeg => "jpeg",
+        }
+    }
+}
+
+/// If `reader` yields a gzip or zstd stream, returns a reader of the decompressed
+/// contents. Anything else is passed through unchanged. `format` is what was
+/// detected on push, if known, otherwise it's detected here.
+pub fn decompressed<'a>(
+    reader: Box<dyn Read + 'a>,
+    format: Option<Format>,
+) -> Result<Box<dyn Read + 'a>> {
+    let mut reader = BufReader::new(reader);
+    let format = match format {
+        Some(format) => Some(format),
+        None => Format::detect(reader.fill_buf()?),
+    };
+    let reader: Box<dyn Read> = match format {
+        Some(Format::Gzip) => Box::new(flate2::bufread::MultiGzDecoder::new(reader)),
+        Some(Format::Zstd) => Box::new(zstd::Decoder::with_buffer(reader)?),
+        Some(Format::Png | Format::Jpeg) | None => Box::new(reader),
+    };
+    Ok(reader)
+}
+
+/// How a stash is encoded at rest, decoded again by the storage backend on reading.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Encoding {
+    Zstd,
+}
+
+impl Encoding {
+    pub fn decoder<'a>(self, reader: Box<dyn Read + 'a>) -> Result<Box<dyn Read + 'a>> {
+        match self {
+            Encoding::Zstd => Ok(Box::new(zstd::Decoder::new(reader)?)),
+        }
+    }
+
+    /// Each encoder writes a complete zstd frame once finished. Appending to a
+    /// stash adds another frame, which decodes as the concatenation.
+    pub fn encoder<'a>(self, writer: Box<dyn Finish + 'a>) -> Result<Box<dyn Finish + 'a>> {
+        match self {
+            Encoding::Zstd => Ok(Box::new(ZstdEncoder(Some(zstd::Encoder::new(writer, 0)?)))),
+        }
+    }
+}
+
+/// A zstd encoder that's finished on drop unless `finish` was called.
+struct ZstdEncoder<'a>(Option<zstd::Encoder<'static, Box<dyn Finish + 'a>>>);
+
+impl Write for ZstdEncoder<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.as_mut().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.as_mut().unwrap().flush()
+    }
+}
+
+impl Finish for ZstdEncoder<'_> {
+    fn finish(mut self: Box<Self>) -> std::io::Result<()> {
+        self.0.take().unwrap().finish()?.finish()
+    }
+}
+
+impl Drop for ZstdEncoder<'_> {
+    fn drop(&mut self) {
+        if let Some(encoder) = self.0.take() {
+            if let Err(e) = encoder.finish() {
+                eprintln!("Warning: couldn't finish compressing stash: {e}");
+            }
+        }
+    }
+}
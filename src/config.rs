@@ -0,0 +1,258 @@
+use std::collections::BTreeMap;
+
+use camino::Utf8Path as Path;
+use eyre::{eyre, Result};
+use serde::Deserialize;
+
+use crate::events::Kind;
+use crate::publish::Service;
+use crate::{ListFormat, SortKey, TimeFormat};
+
+/// Settings from `config.toml` in the config dir. Everything is optional.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Where stashes are kept, unless given with `--backend`
+    pub backend: Backend,
+    /// Where the `local` backend keeps stashes unless given `--data-dir` or `STASH_DIR`,
+    /// the platform's data dir if not set
+    pub data_dir: Option<String>,
+    pub s3: S3,
+    /// What a bare `stash` without a subcommand does
+    pub default_action: DefaultAction,
+    /// Whether `push` stores stashes zstd-compressed without being given `--compress`
+    pub compress: bool,
+    /// Whether `push` stores stashes encrypted without being given `--encrypt`
+    pub encrypt: bool,
+    /// How long pushed stashes are kept unless given `push --expire`, e.g. `30d`.
+    /// Forever if not set.
+    pub expire: Option<String>,
+    /// How many days popped and dropped stashes can still be restored, 7 if not set
+    pub trash_days: Option<u64>,
+    pub list: List,
+    /// Whether `show` highlights syntax on a terminal
+    pub color: Color,
+    /// The color theme for syntax highlighting, e.g. `InspiredGitHub` for light
+    /// terminals. `base16-ocean.dark` if not set.
+    pub theme: Option<String>,
+    pub diff: Diff,
+    /// Limits for the stacks of individual names, by name
+    pub quota: BTreeMap<String, Quota>,
+    /// Limits for all stashes together, the oldest of any name are evicted first
+    pub total_quota: Quota,
+    /// Given as `[[webhook]]` tables
+    pub webhook: Vec<Webhook>,
+    pub mail: Mail,
+    pub publish: Publish,
+    pub serve: Serve,
+}
+
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DefaultAction {
+    /// List when stdin is a terminal, push when it's piped
+    #[default]
+    Auto,
+    Push,
+    List,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, clap::ArgEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// Files in the data dir
+    #[default]
+    Local,
+    /// A bucket of an S3-compatible object store, set up in `[s3]`
+    S3,
+}
+
+/// The bucket of the `s3` backend. Everyone using the same bucket and prefix
+/// shares their stashes.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct S3 {
+    pub bucket: Option<String>,
+    /// For example `https://minio.example.com:9000`, AWS in `region` if not set
+    pub endpoint: Option<String>,
+    /// `us-east-1` if not set
+    pub region: Option<String>,
+    /// Put in front of every key, e.g. `team/`, to keep the stashes in a part of the bucket
+    pub prefix: String,
+    /// The credentials are taken from `AWS_ACCESS_KEY_ID` and `AWS_SECRET_ACCESS_KEY`
+    /// if they aren't set here
+    pub access_key_id: Option<String>,
+    pub secret_access_key: Option<String>,
+}
+
+/// Defaults for the options of `list`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct List {
+    pub sort: Option<SortKey>,
+    pub format: Option<ListFormat>,
+    pub time: Option<TimeFormat>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Color {
+    #[default]
+    Auto,
+    /// As if `show` was always given `--plain`
+    Never,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Diff {
+    /// Command `diff` runs instead of printing a unified diff, see `diff --tool`
+    pub tool: Option<String>,
+}
+
+/// Once a push goes over one of the limits, the oldest stashes of the name are
+/// deleted until it's back within them.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Quota {
+    pub max_count: Option<usize>,
+    /// Total size of all stashes of the name, in bytes
+    pub max_size: Option<u64>,
+}
+
+impl Quota {
+    pub fn is_empty(&self) -> bool {
+        self.max_count.is_none() && self.max_size.is_none()
+    }
+}
+
+/// A URL that gets every event it's interested in as a json POST.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Webhook {
+    pub url: String,
+    /// For example `["push", "pop", "clear"]`, every event if left out
+    #[serde(default)]
+    pub events: Vec<Kind>,
+}
+
+/// How `mail` sends its mails.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Mail {
+    /// Sender address, unless given with `mail --from`
+    pub from: Option<String>,
+    /// The local sendmail is used if this isn't set
+    pub smtp_host: Option<String>,
+    pub smtp_user: Option<String>,
+    pub smtp_password: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Publish {
+    /// Where `publish` uploads to unless given with `--to`
+    pub service: Option<Service>,
+    /// A token with the gist scope, for publishing to GitHub gists
+    pub github_token: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Serve {
+    /// The bearer token `serve` expects, unless given with `--token`.
+    /// A random one is generated for each run if neither is set.
+    pub token: Option<String>,
+    /// The largest body a POST may have, in bytes, 100 MiB if not set
+    pub max_body: Option<u64>,
+    /// How many requests a client may make per minute, by IP address.
+    /// Unlimited if not set.
+    pub requests_per_minute: Option<u32>,
+    /// How many requests are handled at once, those beyond it are turned away.
+    /// 16 if not set.
+    pub max_connections: Option<usize>,
+}
+
+impl Config {
+    /// A missing file is the same as an empty one.
+    pub fn load(path: &Path) -> Result<Config> {
+        match fs_err::read_to_string(path) {
+            Ok(toml) => toml::from_str(&toml).map_err(|e| eyre!("invalid config {path}: {e}")),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// The value of a setting in the config file, `None` if it isn't set there.
+/// Strings are given as they are, everything else as TOML.
+pub fn get(path: &Path, key: &str) -> Result<Option<String>> {
+    let document = load_document(path)?;
+    let mut item = document.as_item();
+    for key in parse_key(key)? {
+        match item.get(key.get()) {
+            Some(inner) => item = inner,
+            None => return Ok(None),
+        }
+    }
+    Ok(match item {
+        toml_edit::Item::None => None,
+        toml_edit::Item::Value(toml_edit::Value::String(string)) => Some(string.value().clone()),
+        toml_edit::Item::Value(value) => Some(value.to_string().trim().to_owned()),
+        // with its subtables, which it doesn't print itself
+        toml_edit::Item::Table(table) => Some(
+            toml_edit::DocumentMut::from(table.clone())
+                .to_string()
+                .trim_end()
+                .to_owned(),
+        ),
+        toml_edit::Item::ArrayOfTables(_) => Some(item.to_string().trim_end().to_owned()),
+    })
+}
+
+/// Sets a setting in the config file, keeping the rest of it as it is, comments
+/// included.
+pub fn set(path: &Path, key: &str, value: &str) -> Result<()> {
+    let mut document = load_document(path)?;
+    let value = value
+        .parse::<toml_edit::Value>()
+        .unwrap_or_else(|_| value.into());
+    let keys = parse_key(key)?;
+    let (last, tables) = keys.split_last().expect("a parsed key isn't empty");
+    let mut table = document.as_table_mut() as &mut dyn toml_edit::TableLike;
+    for key in tables {
+        let item = table.entry(key.get()).or_insert_with(|| {
+            // so there's no empty `[quota]` above `[quota."logs/app"]`
+            let mut table = toml_edit::Table::new();
+            table.set_implicit(true);
+            toml_edit::Item::Table(table)
+        });
+        table = item
+            .as_table_like_mut()
+            .ok_or_else(|| eyre!("{} in {path} isn't a table", key.get()))?;
+    }
+    table.insert(last.get(), toml_edit::value(value));
+
+    let toml = document.to_string();
+    toml::from_str::<Config>(&toml).map_err(|e| eyre!("not setting {key}: {e}"))?;
+    if let Some(dir) = path.parent() {
+        fs_err::create_dir_all(dir)?;
+    }
+    fs_err::write(path, toml)?;
+    Ok(())
+}
+
+fn load_document(path: &Path) -> Result<toml_edit::DocumentMut> {
+    let toml = match fs_err::read_to_string(path) {
+        Ok(toml) => toml,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e.into()),
+    };
+    toml.parse()
+        .map_err(|e| eyre!("invalid config {path}: {e}"))
+}
+
+/// Splits a dotted key like `quota."logs/app".max_count`.
+fn parse_key(key: &str) -> Result<Vec<toml_edit::Key>> {
+    toml_edit::Key::parse(key).map_err(|e| eyre!("invalid key {key:?}: {e}"))
+}
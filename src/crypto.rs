@@ -0,0 +1,91 @@
+use std::io::{Read, Write};
+use std::sync::OnceLock;
+
+use age::secrecy::SecretString;
+use eyre::{eyre, Result};
+
+use crate::storage::Finish;
+
+/// Environment variable the passphrase is taken from instead of asking for it.
+const PASSPHRASE_VAR: &str = "STASH_PASSPHRASE";
+
+/// Asked for at most once per run.
+static PASSPHRASE: OnceLock<String> = OnceLock::new();
+
+/// The passphrase from `$STASH_PASSPHRASE`, or else from the terminal.
+/// `confirm` has it typed in twice, for encrypting with a new one.
+fn passphrase(confirm: bool) -> Result<SecretString> {
+    if let Some(passphrase) = PASSPHRASE.get() {
+        return Ok(SecretString::from(passphrase.clone()));
+    }
+    let passphrase = match std::env::var(PASSPHRASE_VAR) {
+        Ok(passphrase) => passphrase,
+        Err(_) => {
+            let mut prompt = dialoguer::Password::new().with_prompt("Passphrase");
+            if confirm {
+                prompt = prompt.with_confirmation("Repeat passphrase", "Passphrases don't match");
+            }
+            prompt.interact().map_err(|e| {
+                eyre!("couldn't read passphrase, set ${PASSPHRASE_VAR} instead: {e}")
+            })?
+        }
+    };
+    let _ = PASSPHRASE.set(passphrase.clone());
+    Ok(SecretString::from(passphrase))
+}
+
+/// Fails unless the passphrase can be had without asking for it, for encrypting
+/// where nobody is at the terminal to type it in.
+pub fn ensure_unattended() -> Result<()> {
+    match PASSPHRASE.get().is_some() || std::env::var_os(PASSPHRASE_VAR).is_some() {
+        true => Ok(()),
+        false => Err(eyre!(
+            "encrypting here needs the passphrase in ${PASSPHRASE_VAR}, there's no one to ask for it"
+        )),
+    }
+}
+
+/// Encrypts what's written with age, using a passphrase.
+/// The encryption is completed when the writer is finished.
+pub fn encryptor<'a>(writer: Box<dyn Finish + 'a>) -> Result<Box<dyn Finish + 'a>> {
+    let encryptor = age::Encryptor::with_user_passphrase(passphrase(true)?);
+    Ok(Box::new(Finishing(Some(encryptor.wrap_output(writer)?))))
+}
+
+pub fn decryptor<'a>(reader: Box<dyn Read + 'a>) -> Result<Box<dyn Read + 'a>> {
+    let decryptor = age::Decryptor::new(reader)?;
+    let identity = age::scrypt::Identity::new(passphrase(false)?);
+    let reader = decryptor
+        .decrypt(std::iter::once(&identity as &dyn age::Identity))
+        .map_err(|e| eyre!("couldn't decrypt stash: {e}"))?;
+    Ok(Box::new(reader))
+}
+
+/// An age stream that is finished on drop unless `finish` was called.
+struct Finishing<W: Finish>(Option<age::stream::StreamWriter<W>>);
+
+impl<W: Finish> Write for Finishing<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.as_mut().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.as_mut().unwrap().flush()
+    }
+}
+
+impl<W: Finish> Finish for Finishing<W> {
+    fn finish(mut self: Box<Self>) -> std::io::Result<()> {
+        Box::new(self.0.take().unwrap().finish()?).finish()
+    }
+}
+
+impl<W: Finish> Drop for Finishing<W> {
+    fn drop(&mut self) {
+        if let Some(stream) = self.0.take() {
+            if let Err(e) = stream.finish() {
+                eprintln!("Warning: couldn't finish encrypting stash: {e}");
+            }
+        }
+    }
+}
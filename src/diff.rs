@@ -0,0 +1,54 @@
+use std::io::Read;
+use std::process::Command;
+
+use eyre::{eyre, Result};
+
+use crate::storage::{Data, StorageBackend};
+
+/// Prints a unified diff from `old` to `new`.
+pub fn print(store: &dyn StorageBackend, old: &Data, new: &Data) -> Result<()> {
+    let (old_contents, new_contents) = (read(store, old)?, read(store, new)?);
+    let (old_text, new_text) = (
+        String::from_utf8_lossy(&old_contents),
+        String::from_utf8_lossy(&new_contents),
+    );
+    let diff = similar::TextDiff::from_lines(&old_text, &new_text);
+    print!(
+        "{}",
+        diff.unified_diff()
+            .header(&old.id().to_string(), &new.id().to_string())
+    );
+    Ok(())
+}
+
+/// Writes both stashes to temporary files and runs `tool` on them, as in
+/// `meld old new`. `tool` is split on whitespace, so it may carry arguments.
+pub fn run_tool(store: &dyn StorageBackend, tool: &str, old: &Data, new: &Data) -> Result<()> {
+    let dir = tempdir::TempDir::new("stash-diff")?;
+    let mut paths = vec![];
+    for (side, stash) in [("old", old), ("new", new)] {
+        // the id in the filename is what the tool shows as the title
+        let filename = format!("{side}-{}", stash.id()).replace('/', "_");
+        let path = dir.path().join(filename);
+        fs_err::write(&path, read(store, stash)?)?;
+        paths.push(path);
+    }
+
+    let mut words = tool.split_whitespace();
+    let program = words
+        .next()
+        .ok_or_else(|| eyre!("empty diff tool command"))?;
+    // diff tools exit with 1 if the files differ, so the status is of no use
+    Command::new(program)
+        .args(words)
+        .args(&paths)
+        .status()
+        .map_err(|e| eyre!("couldn't run diff tool {program}: {e}"))?;
+    Ok(())
+}
+
+fn read(store: &dyn StorageBackend, stash: &Data) -> Result<Vec<u8>> {
+    let mut contents = vec![];
+    store.reader(stash)?.read_to_end(&mut contents)?;
+    Ok(contents)
+}
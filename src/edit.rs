@@ -0,0 +1,50 @@
+use std::process::Command;
+
+use eyre::{eyre, Result};
+
+use crate::secret::{Copy, SecretBuf};
+use crate::storage::{Data, StorageBackend};
+
+/// Opens a copy of the stash in `$VISUAL` or `$EDITOR` and replaces the stash with
+/// the edited copy once the editor exits successfully. The copy of a secret stash
+/// is only in memory, see `secret::Copy`.
+pub fn edit(store: &dyn StorageBackend, stash: &Data) -> Result<()> {
+    let contents = SecretBuf::read_from(&mut store.reader(stash)?)?;
+
+    // keeping the extension lets the editor pick the right highlighting
+    let meta = store.meta(stash)?;
+    let extension = meta
+        .filename
+        .as_deref()
+        .and_then(|filename| filename.rsplit_once('.'))
+        .map(|(_, extension)| extension)
+        .or(meta.lang.as_deref());
+    let mut filename = stash.id().to_string().replace('/', "_");
+    if let Some(extension) = extension {
+        filename = format!("{filename}.{extension}");
+    }
+    let copy = Copy::new(meta.secret, "stash-edit", &filename, &contents, 0o600)?;
+
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_owned());
+    // through the shell, so that the editor command can contain arguments
+    let status = Command::new("sh")
+        .args(["-c", &format!("{editor} \"$@\""), "sh"])
+        .arg(copy.path())
+        .status()
+        .map_err(|e| eyre!("couldn't run editor {editor}: {e}"))?;
+    if !status.success() {
+        return Err(eyre!(
+            "editor {editor} failed with {status}, stash left unchanged"
+        ));
+    }
+
+    let edited = copy.read()?;
+    if *edited == *contents {
+        eprintln!("Stash {} unchanged", stash.id());
+        return Ok(());
+    }
+    store.replace(stash, &mut &*edited)?;
+    crate::events::record(store, crate::events::Kind::Modify, Some(stash))
+}
@@ -0,0 +1,112 @@
+//! The store for programs embedding stash through the C API or the Python
+//! module, with what they need of it. It's the store the CLI uses without
+//! options, or the local one in a given dir. Changes are logged and sent to the
+//! webhooks like the CLI's, to those of the first store a process opens.
+
+use std::io::Read;
+
+use camino::Utf8PathBuf as PathBuf;
+use eyre::{eyre, Result};
+
+use crate::compression::Encoding;
+use crate::config::{self, Config};
+use crate::meta::Meta;
+use crate::stash_id::StashId;
+use crate::storage::{Data, StorageBackend};
+
+pub struct Embedded {
+    store: Box<dyn StorageBackend>,
+    config: Config,
+}
+
+impl Embedded {
+    /// The local store in `data_dir` if given, else the configured one.
+    pub fn open(data_dir: Option<&str>) -> Result<Embedded> {
+        let proj_dirs = directories::ProjectDirs::from("", "", "stash")
+            .ok_or_else(|| eyre!("couldn't get project dirs"))?;
+        let config_path = PathBuf::from_path_buf(proj_dirs.config_dir().join("config.toml"))
+            .map_err(|_| eyre!("non-utf8 config dir path"))?;
+        let mut config = Config::load(&config_path)?;
+        crate::events::set_webhooks(std::mem::take(&mut config.webhook));
+        let (backend, data_dir) = match (data_dir, config.data_dir.take()) {
+            (Some(data_dir), _) => (config::Backend::Local, data_dir.into()),
+            (None, Some(data_dir)) => (config.backend, data_dir.into()),
+            (None, None) => (
+                config.backend,
+                PathBuf::from_path_buf(proj_dirs.data_dir().to_owned())
+                    .map_err(|_| eyre!("non-utf8 data dir path"))?,
+            ),
+        };
+        let store = crate::profile::Stores {
+            backend,
+            data_dir: &data_dir,
+            s3: &config.s3,
+        }
+        .open(crate::profile::DEFAULT)?;
+        Ok(Embedded { store, config })
+    }
+
+    /// Pushes `contents` as the newest stash of `name` and returns its id, with
+    /// the configured compression, encryption and expiry like `stash serve`.
+    /// Encrypting takes the passphrase from `$STASH_PASSPHRASE`.
+    pub fn push(&self, name: &str, contents: &[u8]) -> Result<String> {
+        let meta = Meta {
+            encoding: self.config.compress.then_some(Encoding::Zstd),
+            encrypted: self.config.encrypt,
+            expires: crate::expiry(None, self.config.expire.as_deref())?,
+            ..Meta::captured()
+        };
+        if meta.encrypted {
+            crate::crypto::ensure_unattended()?;
+        }
+        let store = &*self.store;
+        let stash = crate::push(store, name, &meta, &mut &*contents)?;
+        crate::quota::enforce(store, &self.config, &stash, None)?;
+        Ok(stash.id().to_string())
+    }
+
+    /// Removes the stash and returns its contents, `None` if it doesn't exist.
+    /// Secret and encrypted stashes are only popped by `stash pop`.
+    pub fn pop(&self, id: &str) -> Result<Option<Vec<u8>>> {
+        let store = &*self.store;
+        let id: StashId = id.parse().unwrap();
+        let Some(stash) = Data::get(store, &id)? else {
+            return Ok(None);
+        };
+        if crate::withheld(store, &stash, false)? || store.meta(&stash)?.encrypted {
+            return Err(eyre!(
+                "Stash {} is secret or encrypted, it's only popped by `stash pop`",
+                stash.id()
+            ));
+        }
+        crate::ensure_unlocked(&stash)?;
+        let mut contents = vec![];
+        store.reader(&stash)?.read_to_end(&mut contents)?;
+        store.remove(&stash)?;
+        crate::events::record(store, crate::events::Kind::Pop, Some(&stash))?;
+        Ok(Some(contents))
+    }
+
+    /// All stashes, newest first.
+    pub fn list(&self) -> Result<Vec<Data>> {
+        Data::detect(&*self.store)
+    }
+
+    /// The stashes with a line matching `regex`, newest first, like `stash grep -l`.
+    /// Secret and encrypted stashes never match.
+    #[cfg(feature = "python")]
+    pub fn search(&self, regex: &str) -> Result<Vec<Data>> {
+        let store = &*self.store;
+        let regex = regex::bytes::Regex::new(regex)?;
+        let mut found = vec![];
+        for stash in Data::detect(store)? {
+            if crate::withheld(store, &stash, false)? || store.meta(&stash)?.encrypted {
+                continue;
+            }
+            if crate::grep::matches(store, &stash, &regex)? {
+                found.push(stash);
+            }
+        }
+        Ok(found)
+    }
+}
@@ -0,0 +1,109 @@
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime};
+
+use eyre::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::config::Webhook;
+use crate::storage::{Data, StorageBackend};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+
+// set once from the config at startup, so not every caller of `record` needs it
+static WEBHOOKS: OnceLock<Vec<Webhook>> = OnceLock::new();
+
+pub fn set_webhooks(webhooks: Vec<Webhook>) {
+    let _ = WEBHOOKS.set(webhooks);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    Push,
+    Append,
+    Pop,
+    Drop,
+    Touch,
+    Rename,
+    Modify,
+    Clear,
+    Restore,
+    Expire,
+    /// Deleted for good to stay within a quota
+    Evict,
+}
+
+/// A change to the store, written to the event log as one line of json.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Event {
+    pub time: String,
+    pub event: Kind,
+    /// The stash's id at the time of the event
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// The checksum of the contents after the event, unknown for stashes that
+    /// were removed by it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+}
+
+pub fn record(store: &dyn StorageBackend, kind: Kind, stash: Option<&Data>) -> Result<()> {
+    let event = Event {
+        time: crate::format_time(SystemTime::now())?,
+        event: kind,
+        id: stash.map(|stash| stash.id().to_string()),
+        name: stash.map(|stash| stash.name.clone()),
+        size: stash.map(|stash| stash.size),
+        // a removed stash's metadata may be gone already
+        sha256: stash
+            .and_then(|stash| store.meta(stash).ok())
+            .and_then(|meta| meta.sha256),
+    };
+    store.log_event(&serde_json::to_string(&event)?)?;
+    notify_webhooks(&event);
+    Ok(())
+}
+
+/// A webhook that can't be reached doesn't fail the change that triggered it.
+fn notify_webhooks(event: &Event) {
+    let webhooks = WEBHOOKS.get().map_or(&[][..], Vec::as_slice);
+    let interested = webhooks
+        .iter()
+        .filter(|webhook| webhook.events.is_empty() || webhook.events.contains(&event.event));
+    for webhook in interested {
+        let agent: ureq::Agent = ureq::Agent::config_builder()
+            .timeout_global(Some(WEBHOOK_TIMEOUT))
+            .build()
+            .into();
+        if let Err(e) = agent.post(&webhook.url).send_json(event) {
+            eprintln!("Couldn't notify webhook {}: {e}", webhook.url);
+        }
+    }
+}
+
+/// Prints the event log and, with `follow`, keeps printing new events as they happen.
+pub fn print(store: &dyn StorageBackend, follow: bool) -> Result<()> {
+    use std::io::Write;
+
+    let stdout = std::io::stdout();
+    let mut offset = 0;
+    // only complete lines are printed, a partially written one waits for the next round
+    loop {
+        let new = store.events_since(offset)?;
+        let complete = new.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+        let mut stdout = stdout.lock();
+        stdout.write_all(&new[..complete])?;
+        stdout.flush()?;
+        offset += complete as u64;
+
+        if !follow {
+            return Ok(());
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
@@ -0,0 +1,54 @@
+use std::process::{Command, ExitStatus};
+
+use eyre::{eyre, Result};
+
+use crate::secret::{Copy, SecretBuf};
+use crate::storage::{Data, StorageBackend};
+
+/// What to run a stashed script with.
+pub enum Interpreter {
+    /// The script itself, going by its `#!` line, or `sh` without one
+    Script,
+    /// `$SHELL`, falling back to `sh`
+    Shell,
+    Program(String),
+}
+
+/// Runs the stash as a script from a temporary, executable copy, which is only
+/// in memory for secret stashes, see `secret::Copy`.
+pub fn exec(
+    store: &dyn StorageBackend,
+    stash: &Data,
+    interpreter: Interpreter,
+    args: &[String],
+) -> Result<ExitStatus> {
+    let contents = SecretBuf::read_from(&mut store.reader(stash)?)?;
+    let secret = store.meta(stash)?.secret;
+
+    // the name shows up in error messages of the script, e.g. as `$0`
+    let filename = stash.id().to_string().replace('/', "_");
+    // read-only, so that it can be executed from memory
+    let copy = Copy::new(secret, "stash-exec", &filename, &contents, 0o500)?;
+    let path = copy.path();
+
+    let mut command = match interpreter {
+        Interpreter::Script if contents.starts_with(b"#!") => Command::new(path),
+        Interpreter::Script => with_interpreter("sh", path),
+        Interpreter::Shell => {
+            let shell = std::env::var("SHELL").unwrap_or_else(|_| "sh".to_owned());
+            with_interpreter(&shell, path)
+        }
+        Interpreter::Program(program) => with_interpreter(&program, path),
+    };
+    let status = command
+        .args(args)
+        .status()
+        .map_err(|e| eyre!("couldn't run {}: {e}", stash.id()))?;
+    Ok(status)
+}
+
+fn with_interpreter(program: &str, script: &std::path::Path) -> Command {
+    let mut command = Command::new(program);
+    command.arg(script);
+    command
+}
@@ -0,0 +1,169 @@
+//! Export and import of stashes as a zstd-compressed tar archive, for moving them
+//! to another store.
+//!
+//! Stash number `n`, counted from the oldest, is stored as `n.json` with its name,
+//! state and metadata, followed by `n` with its contents.
+
+use std::collections::BTreeSet;
+use std::io::{Read, Write};
+
+use eyre::{eyre, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::meta::Meta;
+use crate::storage::{Data, StorageBackend};
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    name: String,
+    created: String,
+    locked: bool,
+    meta: Meta,
+}
+
+/// Writes the stashes of the given names (or all of them) oldest first, so an
+/// import rebuilds every stack in the same order, except for the `known` ones by
+/// name and checksum, which the importing side already has.
+/// The contents are written decoded, also those of encrypted stashes. Secret ones
+/// are left out without `force`.
+pub fn export(
+    store: &dyn StorageBackend,
+    names: &[String],
+    force: bool,
+    known: &BTreeSet<(String, String)>,
+    out: &mut dyn Write,
+) -> Result<()> {
+    let selected = Data::detect(store)?
+        .into_iter()
+        .rev()
+        .filter(|stash| names.is_empty() || names.contains(&stash.name))
+        .collect();
+    let selected = crate::without_withheld(store, selected, force)?;
+
+    let is_known =
+        |stash: &Data, sha256: &String| known.contains(&(stash.name.clone(), sha256.clone()));
+    let mut builder = tar::Builder::new(zstd::Encoder::new(out, 0)?);
+    let mut number = 0;
+    for stash in &selected {
+        let mut meta = store.meta(stash)?;
+        // before reading it, that's what takes long
+        if meta
+            .sha256
+            .as_ref()
+            .is_some_and(|sha256| is_known(stash, sha256))
+        {
+            continue;
+        }
+        let created = stash.created.duration_since(std::time::UNIX_EPOCH)?;
+        // the header needs the size, which is only known for the decoded
+        // contents once they're read
+        let mut contents = vec![];
+        store.reader(stash)?.read_to_end(&mut contents)?;
+        // for stashes from before checksums, so an import can tell them apart
+        let sha256 = match meta.sha256.take() {
+            Some(sha256) => sha256,
+            None => crate::checksum::sha256(contents.as_slice())?,
+        };
+        if is_known(stash, &sha256) {
+            continue;
+        }
+        meta.sha256 = Some(sha256);
+        let entry = Entry {
+            name: stash.name.clone(),
+            created: crate::format_time(stash.created)?,
+            locked: stash.locked,
+            meta,
+        };
+        append(
+            &mut builder,
+            &format!("{number}.json"),
+            created.as_secs(),
+            &serde_json::to_vec(&entry)?,
+        )?;
+        append(
+            &mut builder,
+            &number.to_string(),
+            created.as_secs(),
+            &contents,
+        )?;
+        number += 1;
+    }
+    builder.into_inner()?.finish()?.flush()?;
+    Ok(())
+}
+
+fn append(
+    builder: &mut tar::Builder<impl Write>,
+    path: &str,
+    mtime: u64,
+    data: &[u8],
+) -> Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o600);
+    header.set_mtime(mtime);
+    builder.append_data(&mut header, path, data)?;
+    Ok(())
+}
+
+/// The name and checksum of every stash, which is how `import` tells stashes
+/// apart. Encrypted stashes from before checksums are left out.
+pub fn checksums(store: &dyn StorageBackend) -> Result<BTreeSet<(String, String)>> {
+    let mut checksums = BTreeSet::new();
+    for stash in Data::detect(store)? {
+        let meta = store.meta(&stash)?;
+        let sha256 = match meta.sha256 {
+            Some(sha256) => sha256,
+            // decrypting would ask for the passphrase
+            None if meta.encrypted => continue,
+            None => crate::checksum::sha256(store.reader(&stash)?)?,
+        };
+        checksums.insert((stash.name, sha256));
+    }
+    Ok(checksums)
+}
+
+/// Pushes the stashes of an archive written by `export` on top of the existing
+/// ones, in order. Stashes that are already in the store with the same name and
+/// checksum are skipped, so importing the same archive twice does no harm.
+pub fn import(store: &dyn StorageBackend, input: impl Read) -> Result<()> {
+    let present = checksums(store)?;
+
+    let mut archive = tar::Archive::new(zstd::Decoder::new(input)?);
+    let mut entries = archive.entries()?;
+    let (mut imported, mut skipped) = (0, 0);
+    while let Some(entry) = entries.next() {
+        let mut entry = entry?;
+        let path = entry.path()?.to_string_lossy().into_owned();
+        if !path.ends_with(".json") {
+            return Err(eyre!("expected stash metadata in the archive, got {path}"));
+        }
+        let mut json = vec![];
+        entry.read_to_end(&mut json)?;
+        let Entry {
+            name, locked, meta, ..
+        } = serde_json::from_slice(&json).map_err(|e| eyre!("{path}: {e}"))?;
+        let mut contents = entries
+            .next()
+            .ok_or_else(|| eyre!("the archive ends without the contents for {path}"))??;
+
+        let duplicate = meta
+            .sha256
+            .as_ref()
+            .is_some_and(|sha256| present.contains(&(name.clone(), sha256.clone())));
+        if duplicate {
+            skipped += 1;
+            continue;
+        }
+        let stash = crate::push(store, &name, &meta, &mut contents)?;
+        if locked {
+            store.set_locked(&stash, true)?;
+        }
+        imported += 1;
+    }
+    eprintln!("Imported {imported} stash(es)");
+    if skipped > 0 {
+        eprintln!("Skipped {skipped} already in the store");
+    }
+    Ok(())
+}
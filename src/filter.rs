@@ -0,0 +1,59 @@
+use std::io::Write;
+use std::process::{Command, ExitStatus, Stdio};
+
+use eyre::{eyre, Result};
+
+use crate::storage::{Data, StorageBackend};
+
+/// Feeds the stash to `command` and writes its output to `out`, or with
+/// `in_place` makes the output the new contents of the stash.
+/// The stash is only replaced if the command succeeds.
+pub fn filter(
+    store: &dyn StorageBackend,
+    stash: &Data,
+    command: &[String],
+    in_place: bool,
+) -> Result<ExitStatus> {
+    let (program, args) = command
+        .split_first()
+        .ok_or_else(|| eyre!("no command to filter through"))?;
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| eyre!("couldn't run {program}: {e}"))?;
+    let mut child_stdin = child.stdin.take().unwrap();
+    let mut child_stdout = child.stdout.take().unwrap();
+
+    let output = std::thread::scope(|scope| -> Result<Vec<u8>> {
+        // the stash is fed from a thread, the command may only start writing
+        // output once it has read all of its input
+        let input = scope.spawn(move || -> Result<()> {
+            match std::io::copy(&mut store.reader(stash)?, &mut child_stdin) {
+                // the command doesn't need all of its input, like `head`
+                Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => Ok(()),
+                result => result.map(drop).map_err(Into::into),
+            }
+        });
+        let mut output = vec![];
+        match in_place {
+            true => std::io::copy(&mut child_stdout, &mut output)?,
+            false => {
+                let mut stdout = std::io::stdout().lock();
+                let copied = std::io::copy(&mut child_stdout, &mut stdout)?;
+                stdout.flush()?;
+                copied
+            }
+        };
+        input.join().unwrap()?;
+        Ok(output)
+    })?;
+
+    let status = child.wait()?;
+    if in_place && status.success() {
+        store.replace(stash, &mut output.as_slice())?;
+        crate::events::record(store, crate::events::Kind::Modify, Some(stash))?;
+    }
+    Ok(status)
+}
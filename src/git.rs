@@ -0,0 +1,107 @@
+//! Stashing the uncommitted changes of a git repo as a patch, to apply them
+//! later or in another repo.
+
+use std::process::{Command, Stdio};
+
+use eyre::{eyre, Result};
+
+use crate::meta::{self, Meta};
+use crate::storage::{Data, StorageBackend};
+
+/// Pushes the changes to tracked files since the last commit, staged or not,
+/// and the untracked files that aren't ignored if `untracked` is set.
+pub fn push(
+    store: &dyn StorageBackend,
+    name: &str,
+    message: Option<String>,
+    untracked: bool,
+) -> Result<Data> {
+    let repo = toplevel()?;
+    let mut patch = git(&repo, &["diff", "--binary", "HEAD"])?;
+    if untracked {
+        let files = git(&repo, &["ls-files", "-z", "--others", "--exclude-standard"])?;
+        for file in files
+            .split(|&byte| byte == 0)
+            .filter(|file| !file.is_empty())
+        {
+            let file = String::from_utf8_lossy(file);
+            // exits with 1 because there are differences
+            let output = command(
+                &repo,
+                &["diff", "--binary", "--no-index", "--", "/dev/null", &file],
+            )
+            .output()
+            .map_err(|e| eyre!("couldn't run git: {e}"))?;
+            if output.status.code() != Some(1) {
+                return Err(eyre!("git diff of {file} failed with {}", output.status));
+            }
+            patch.extend(output.stdout);
+        }
+    }
+    if patch.is_empty() {
+        return Err(eyre!("No changes in {repo} to stash"));
+    }
+
+    let meta = Meta {
+        message,
+        lang: Some("diff".to_owned()),
+        git: Some(meta::Git {
+            branch: Some(text(git(&repo, &["branch", "--show-current"])?))
+                .filter(|branch| !branch.is_empty()),
+            commit: text(git(&repo, &["rev-parse", "HEAD"])?),
+            repo,
+        }),
+        ..Meta::captured()
+    };
+    crate::push(store, name, &meta, &mut patch.as_slice())
+}
+
+/// Applies the stash to the repo the current directory is in with `git apply`,
+/// leaving the repo as it was if it doesn't apply cleanly. With `three_way`, it's
+/// merged instead where it doesn't, leaving conflict markers to resolve.
+pub fn apply(store: &dyn StorageBackend, stash: &Data, three_way: bool) -> Result<()> {
+    let repo = toplevel()?;
+    let mut command = command(&repo, &["apply"]);
+    if three_way {
+        command.arg("--3way");
+    }
+    let mut child = command
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|e| eyre!("couldn't run git: {e}"))?;
+    let copied = std::io::copy(&mut store.reader(stash)?, &mut child.stdin.take().unwrap());
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(eyre!("git apply of {} failed with {status}", stash.id()));
+    }
+    copied?;
+    Ok(())
+}
+
+/// The root of the repo the current directory is in.
+fn toplevel() -> Result<String> {
+    git(".", &["rev-parse", "--show-toplevel"]).map(text)
+}
+
+fn command(repo: &str, args: &[&str]) -> Command {
+    let mut command = Command::new("git");
+    command.arg("-C").arg(repo).args(args);
+    command
+}
+
+/// The output of a git command, which has to succeed.
+fn git(repo: &str, args: &[&str]) -> Result<Vec<u8>> {
+    let output = command(repo, args)
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|e| eyre!("couldn't run git: {e}"))?;
+    if !output.status.success() {
+        return Err(eyre!("git {} failed with {}", args[0], output.status));
+    }
+    Ok(output.stdout)
+}
+
+/// Output that's a single line.
+fn text(output: Vec<u8>) -> String {
+    String::from_utf8_lossy(&output).trim().to_owned()
+}
@@ -0,0 +1,105 @@
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Write};
+
+use eyre::Result;
+use regex::bytes::Regex;
+
+use crate::storage::{Data, StorageBackend};
+
+/// Lines to print around each match.
+#[derive(Clone, Copy, Default)]
+pub struct Context {
+    pub before: usize,
+    pub after: usize,
+}
+
+/// Prints the lines of `stashes` that match `regex` like grep does for several
+/// files: matches as `id:line`, context lines as `id-line` and, if there is
+/// context, `--` between groups of lines that aren't adjacent.
+/// With `ids_only`, only the id of each stash with a match is printed.
+///
+/// Returns whether anything matched.
+pub fn grep(
+    store: &dyn StorageBackend,
+    stashes: &[Data],
+    regex: &Regex,
+    context: Context,
+    ids_only: bool,
+) -> Result<bool> {
+    let mut out = std::io::BufWriter::new(std::io::stdout().lock());
+    let mut any_match = false;
+    // the stash and line number of the last printed line, to know when to separate
+    let mut last_printed: Option<(usize, usize)> = None;
+
+    for (i, stash) in stashes.iter().enumerate() {
+        let id = stash.id();
+        let mut before = VecDeque::<Vec<u8>>::with_capacity(context.before);
+        // context lines still due after the last match
+        let mut after = 0;
+        let mut stash_matched = false;
+
+        for (n, line) in BufReader::new(store.reader(stash)?)
+            .split(b'\n')
+            .enumerate()
+        {
+            let line = line?;
+            if regex.is_match(&line) {
+                stash_matched = true;
+                if ids_only {
+                    break;
+                }
+                let first = n - before.len();
+                let with_context = context.before > 0 || context.after > 0;
+                if with_context
+                    && last_printed.is_some_and(|last| last != (i, first.wrapping_sub(1)))
+                {
+                    writeln!(out, "--")?;
+                }
+                for line in before.drain(..) {
+                    print_line(&mut out, &id, '-', &line)?;
+                }
+                print_line(&mut out, &id, ':', &line)?;
+                last_printed = Some((i, n));
+                after = context.after;
+            } else if after > 0 {
+                print_line(&mut out, &id, '-', &line)?;
+                last_printed = Some((i, n));
+                after -= 1;
+            } else if context.before > 0 {
+                if before.len() == context.before {
+                    before.pop_front();
+                }
+                before.push_back(line);
+            }
+        }
+
+        if stash_matched && ids_only {
+            writeln!(out, "{id}")?;
+        }
+        any_match |= stash_matched;
+    }
+    out.flush()?;
+    Ok(any_match)
+}
+
+/// Whether any line of the stash matches `regex`.
+pub fn matches(store: &dyn StorageBackend, stash: &Data, regex: &Regex) -> Result<bool> {
+    for line in BufReader::new(store.reader(stash)?).split(b'\n') {
+        if regex.is_match(&line?) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+fn print_line(
+    out: &mut impl Write,
+    id: &impl std::fmt::Display,
+    separator: char,
+    line: &[u8],
+) -> Result<()> {
+    write!(out, "{id}{separator}")?;
+    out.write_all(line)?;
+    out.write_all(b"\n")?;
+    Ok(())
+}
@@ -0,0 +1,89 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::sync::OnceLock;
+
+use eyre::{eyre, Result};
+use syntect::easy::HighlightLines;
+use syntect::highlighting::ThemeSet;
+use syntect::parsing::{SyntaxReference, SyntaxSet};
+
+const DEFAULT_THEME: &str = "base16-ocean.dark";
+
+// set once from the config at startup, like the webhooks of `events`
+static THEME: OnceLock<String> = OnceLock::new();
+
+/// Uses the syntect theme of that name instead of the default one.
+pub fn set_theme(theme: Option<String>) -> Result<()> {
+    let Some(theme) = theme else {
+        return Ok(());
+    };
+    let themes = ThemeSet::load_defaults();
+    if !themes.themes.contains_key(&theme) {
+        let known: Vec<_> = themes.themes.keys().map(String::as_str).collect();
+        return Err(eyre!(
+            "unknown theme {theme:?}, expected one of {}",
+            known.join(", ")
+        ));
+    }
+    let _ = THEME.set(theme);
+    Ok(())
+}
+
+/// Copies `reader` to `out` with terminal color codes for syntax highlighting.
+///
+/// The language is `lang` if given, else guessed from the extension of
+/// `filename` and then from the first line, e.g. a shebang. If none of them
+/// lead to a known language, the contents are copied as they are.
+pub fn highlight(
+    lang: Option<&str>,
+    filename: Option<&str>,
+    reader: &mut dyn Read,
+    out: &mut dyn Write,
+) -> Result<()> {
+    let syntaxes = SyntaxSet::load_defaults_newlines();
+    let mut reader = BufReader::new(reader);
+    let mut line = vec![];
+    reader.read_until(b'\n', &mut line)?;
+
+    let extension = filename
+        .and_then(|filename| filename.rsplit_once('.'))
+        .map(|(_, extension)| extension);
+    let syntax = lang
+        .and_then(|lang| syntaxes.find_syntax_by_token(lang))
+        .or_else(|| extension.and_then(|extension| syntaxes.find_syntax_by_extension(extension)))
+        .or_else(|| syntaxes.find_syntax_by_first_line(&String::from_utf8_lossy(&line)));
+    let Some(syntax) = syntax else {
+        out.write_all(&line)?;
+        std::io::copy(&mut reader, out)?;
+        return Ok(());
+    };
+
+    write_highlighted(&syntaxes, syntax, &mut reader, line, out)
+}
+
+fn write_highlighted(
+    syntaxes: &SyntaxSet,
+    syntax: &SyntaxReference,
+    reader: &mut dyn BufRead,
+    mut line: Vec<u8>,
+    out: &mut dyn Write,
+) -> Result<()> {
+    let themes = ThemeSet::load_defaults();
+    let mut highlighter = HighlightLines::new(
+        syntax,
+        &themes.themes[THEME.get().map_or(DEFAULT_THEME, String::as_str)],
+    );
+    while !line.is_empty() {
+        let text = String::from_utf8_lossy(&line);
+        let ranges = highlighter.highlight_line(&text, syntaxes)?;
+        write!(
+            out,
+            "{}",
+            syntect::util::as_24_bit_terminal_escaped(&ranges, false)
+        )?;
+        line.clear();
+        reader.read_until(b'\n', &mut line)?;
+    }
+    // back to the terminal's colors
+    write!(out, "\x1b[0m")?;
+    Ok(())
+}
@@ -0,0 +1,45 @@
+use camino::Utf8PathBuf as PathBuf;
+use eyre::{eyre, Result};
+
+use crate::meta::Meta;
+use crate::storage::StorageBackend;
+
+/// Pushes every file matching `pattern`, in path order.
+///
+/// The name comes from `name_template`, in which `{filename}` and `{stem}` are
+/// replaced with the file's name with and without extension. Without a template,
+/// the files go to the unnamed stack.
+pub fn import_files(
+    store: &dyn StorageBackend,
+    pattern: &str,
+    name_template: Option<&str>,
+) -> Result<()> {
+    let mut paths = vec![];
+    for path in glob::glob(pattern)? {
+        let path = PathBuf::from_path_buf(path?)
+            .map_err(|path| eyre!("path is not valid utf-8: {}", path.display()))?;
+        if path.is_file() {
+            paths.push(path);
+        }
+    }
+    if paths.is_empty() {
+        return Err(eyre!("no files match {pattern}"));
+    }
+
+    for path in paths {
+        let filename = path.file_name().unwrap_or_default();
+        let name = match name_template {
+            Some(template) => template
+                .replace("{filename}", filename)
+                .replace("{stem}", path.file_stem().unwrap_or_default()),
+            None => String::new(),
+        };
+        let meta = Meta {
+            filename: Some(filename.to_owned()),
+            ..Meta::captured()
+        };
+        let stash = crate::push(store, &name, &meta, &mut fs_err::File::open(&path)?)?;
+        eprintln!("Stashed {path} as {}", stash.id());
+    }
+    Ok(())
+}
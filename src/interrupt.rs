@@ -0,0 +1,87 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use camino::{Utf8Path as Path, Utf8PathBuf as PathBuf};
+use eyre::Result;
+
+/// Temporary files to delete if we're interrupted while they exist.
+static TEMP_FILES: Mutex<Vec<PathBuf>> = Mutex::new(vec![]);
+/// Set while a pager runs, which handles Ctrl-C itself.
+static PAGER_RUNNING: AtomicBool = AtomicBool::new(false);
+/// Set if we were interrupted while the pager ran.
+static DEFERRED: AtomicBool = AtomicBool::new(false);
+
+/// Makes Ctrl-C and SIGTERM delete the registered temporary files before exiting,
+/// instead of leaving them behind half-written.
+///
+/// Exiting from the handler means whatever the main thread was doing is never
+/// finished, so e.g. a `pop` that is interrupted while printing doesn't get to
+/// delete the stash.
+///
+/// While a pager runs, Ctrl-C is for the pager, e.g. to stop `less` searching, so
+/// we only exit once it's closed.
+pub fn install_handler() -> Result<()> {
+    ctrlc::set_handler(|| match PAGER_RUNNING.load(Ordering::SeqCst) {
+        true => DEFERRED.store(true, Ordering::SeqCst),
+        false => exit_interrupted(),
+    })?;
+    Ok(())
+}
+
+fn exit_interrupted() -> ! {
+    let temp_files = TEMP_FILES.lock().unwrap_or_else(|e| e.into_inner());
+    for path in temp_files.iter() {
+        let _ = fs_err::remove_file(path);
+    }
+    std::process::exit(130);
+}
+
+/// Held for as long as the pager runs, see `install_handler`. Dropping it exits
+/// if we were interrupted in the meantime.
+pub struct PagerRunning(());
+
+impl PagerRunning {
+    pub fn new() -> PagerRunning {
+        PAGER_RUNNING.store(true, Ordering::SeqCst);
+        PagerRunning(())
+    }
+}
+
+impl Drop for PagerRunning {
+    fn drop(&mut self) {
+        PAGER_RUNNING.store(false, Ordering::SeqCst);
+        if DEFERRED.swap(false, Ordering::SeqCst) {
+            exit_interrupted();
+        }
+    }
+}
+
+/// A temporary file that is deleted when this is dropped, or when we're interrupted.
+/// Moving it into place before that is up to the owner.
+pub struct TempFile {
+    path: PathBuf,
+}
+
+impl TempFile {
+    /// Registers `path`, which should be created only after this.
+    pub fn new(path: PathBuf) -> TempFile {
+        let mut temp_files = TEMP_FILES.lock().unwrap_or_else(|e| e.into_inner());
+        temp_files.push(path.clone());
+        TempFile { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        // already gone if it was renamed into place
+        let _ = fs_err::remove_file(&self.path);
+        let mut temp_files = TEMP_FILES.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(i) = temp_files.iter().position(|path| *path == self.path) {
+            temp_files.swap_remove(i);
+        }
+    }
+}
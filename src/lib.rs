@@ -0,0 +1,2961 @@
+//! The `stash` command line tool, and the parts of it that other programs can
+//! use without running it, like `stash_client` for the API of `stash serve`.
+
+mod archive;
+mod backup;
+mod bench;
+mod binary;
+#[cfg(feature = "capi")]
+pub mod capi;
+mod checksum;
+mod clipboard;
+mod compression;
+mod config;
+mod crypto;
+mod diff;
+mod edit;
+#[cfg(any(feature = "capi", feature = "python"))]
+mod embed;
+mod events;
+mod exec;
+mod export;
+mod filter;
+mod git;
+mod grep;
+mod highlight;
+mod import_files;
+mod interrupt;
+mod mail;
+mod menu;
+mod meta;
+mod ndjson;
+mod pager;
+mod profile;
+mod publish;
+#[cfg(feature = "python")]
+mod python;
+mod quota;
+mod remote;
+mod render;
+mod rpc;
+mod s3;
+mod secret;
+mod sed;
+mod serve;
+mod shell;
+pub mod stash_client;
+mod stash_id;
+mod stats;
+mod storage;
+mod sync;
+mod tmux;
+mod tree;
+mod tui;
+mod watch;
+mod wormhole;
+mod wrap;
+
+use std::io::{BufRead, IsTerminal, Read, Write};
+
+use camino::Utf8PathBuf as PathBuf;
+use clap::{CommandFactory, Parser};
+use eyre::{eyre, Result};
+
+use compression::Encoding;
+use meta::Meta;
+use stash_id::StashId;
+use storage::{Data, StorageBackend};
+
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct Args {
+    /// Keep stashes in the runtime dir (usually a tmpfs) instead of the data dir.
+    /// They never touch persistent storage and are gone after a reboot.
+    #[clap(long, global = true)]
+    ephemeral: bool,
+    /// Keep stashes in this directory instead of the data dir. `data_dir` in the
+    /// config if not given, ignored with `--ephemeral`.
+    #[clap(long, global = true, env = "STASH_DIR", value_name = "PATH")]
+    data_dir: Option<PathBuf>,
+    /// Where stashes are kept, `backend` in the config if not given
+    #[clap(long, global = true, arg_enum, value_name = "BACKEND")]
+    backend: Option<config::Backend>,
+    /// Use the stashes of this profile, which are kept apart from those of other
+    /// profiles. `default` are the stashes without a profile.
+    #[clap(long, global = true, env = "STASH_PROFILE", default_value = profile::DEFAULT)]
+    profile: String,
+    /// Run the command against `stash serve` at this URL instead of the local store,
+    /// e.g. `http://host:7877`. Only list, show, pop, drop and push work like that.
+    #[clap(long, global = true, env = "STASH_REMOTE", value_name = "URL")]
+    remote: Option<String>,
+    /// The token of the --remote server. Taken from the URL's fragment if not given,
+    /// like in the link `serve` prints, or `serve.token` in the config.
+    #[clap(long, global = true, env = "STASH_REMOTE_TOKEN", value_name = "TOKEN")]
+    remote_token: Option<String>,
+    /// Without one, stashes stdin if it's piped and lists stashes otherwise.
+    /// Can be changed with `default_action` in the config.
+    #[clap(subcommand)]
+    command: Option<Subcommand>,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum Subcommand {
+    List {
+        /// Show stashes as a tree grouped by `/`-separated name components
+        #[clap(long)]
+        tree: bool,
+        #[clap(flatten)]
+        filter: ListFilter,
+        #[clap(flatten)]
+        order: ListOrder,
+        /// Print only the ids, each terminated by a NUL byte, for `xargs -0`
+        #[clap(short = '0', long, conflicts_with = "tree")]
+        null: bool,
+        /// How to print creation times in the text format: `relative` ages like
+        /// "3 hours ago", `local` timestamps in the local time zone or `rfc3339`.
+        /// `list.time` in the config if not given, and `relative` without it.
+        #[clap(long, arg_enum)]
+        time: Option<TimeFormat>,
+        /// `json` prints an array with an object per stash, including its metadata.
+        /// `list.format` in the config if not given, and `text` without it.
+        #[clap(long, arg_enum, conflicts_with_all = &["tree", "null"])]
+        format: Option<ListFormat>,
+    },
+    /// Print the name of every named stack, in alphabetical order
+    Names {
+        /// Terminate each name by a NUL byte instead of a newline, for `xargs -0`
+        #[clap(short = '0', long)]
+        null: bool,
+    },
+    /// Store stdin as the newest stash of the given name
+    #[clap(alias = "store")]
+    Push {
+        #[clap(default_value_t)]
+        name: String,
+        /// Append to the newest stash of that name instead of creating a new one
+        #[clap(short, long)]
+        append: bool,
+        /// Fail without stashing anything instead of evicting old stashes if the
+        /// push would exceed a quota
+        #[clap(long, conflicts_with = "append")]
+        strict: bool,
+        /// Keep only the newest N stashes of the name, evicting older ones.
+        /// Overrides `max_count` of the name's quota.
+        #[clap(long, value_name = "N")]
+        max_history: Option<usize>,
+        /// Stash this file instead of stdin, can be given multiple times.
+        /// Directories are stashed as a tar archive.
+        #[clap(long = "file", value_name = "PATH", conflicts_with = "append")]
+        files: Vec<PathBuf>,
+        /// A short description of the stash, shown by `list`
+        #[clap(short, long, conflicts_with = "append")]
+        message: Option<String>,
+        /// Can be given multiple times
+        #[clap(long = "tag", value_name = "TAG", conflicts_with = "append")]
+        tags: Vec<String>,
+        /// Remove the files once they're stashed, to put them back later with `pop --restore`
+        #[clap(long = "move", requires = "files")]
+        move_files: bool,
+        /// Language of the contents for syntax highlighting, e.g. `json`.
+        /// Guessed from the file name or first line otherwise.
+        #[clap(long, conflicts_with = "append")]
+        lang: Option<String>,
+        /// Store the stash zstd-compressed, it's decompressed again whenever it's read.
+        /// The default if `compress` is set in the config. Contents that are
+        /// compressed already, like gzip, zstd, png or jpeg, are stored as they are.
+        #[clap(long)]
+        compress: bool,
+        /// Store the stash as it is, even if `compress` is set in the config
+        #[clap(long = "store", conflicts_with = "compress")]
+        uncompressed: bool,
+        /// Store the stash encrypted with a passphrase, taken from $STASH_PASSPHRASE
+        /// or asked for. Reading it asks for the passphrase again.
+        #[clap(long)]
+        encrypt: bool,
+        /// Make the stash only readable by you and refuse to print it anywhere but
+        /// a terminal without --force
+        #[clap(long)]
+        secret: bool,
+        /// Delete the stash once this long has passed, e.g. `1h` or `7d`
+        #[clap(long, value_name = "DURATION", parse(try_from_str = parse_duration))]
+        expire: Option<std::time::Duration>,
+        /// Stash the text on the clipboard instead of stdin
+        #[clap(long, conflicts_with_all = &["append", "files"])]
+        clipboard: bool,
+        /// Stash a tmux paste buffer instead of stdin, the most recent one if no name is given
+        #[clap(long, value_name = "BUFFER", conflicts_with_all = &["append", "files", "clipboard"])]
+        tmux: Option<Option<String>>,
+    },
+    /// Append stdin to the newest stash of the given name, creating it if there is none.
+    /// Same as `push --append`.
+    Append {
+        #[clap(default_value_t)]
+        name: String,
+        /// A line written between the existing contents and the appended ones
+        #[clap(short, long, allow_hyphen_values = true)]
+        separator: Option<String>,
+    },
+    Show {
+        #[clap(default_value_t)]
+        id: StashId,
+        /// Pick the stash from a fuzzy finder instead
+        #[clap(short, long, conflicts_with = "id")]
+        interactive: bool,
+        #[clap(short, long)]
+        delete: bool,
+        #[clap(flatten)]
+        options: ShowOptions,
+        #[clap(flatten)]
+        window: Window,
+        /// Print where the stash was pushed from instead of its contents
+        #[clap(long, conflicts_with_all = &["delete", "decompress", "unique-lines", "sort-lines"])]
+        meta: bool,
+        /// Print the notes added with `annotate` instead of its contents
+        #[clap(long, conflicts_with_all = &["delete", "decompress", "unique-lines", "sort-lines", "meta"])]
+        notes: bool,
+    },
+    /// Show a stash and delete it afterwards
+    Pop {
+        #[clap(default_value_t)]
+        id: StashId,
+        /// Pick the stash from a fuzzy finder instead
+        #[clap(short, long, conflicts_with_all = &["id", "all"])]
+        interactive: bool,
+        /// Pop every stash of the given name
+        #[clap(long)]
+        all: bool,
+        /// With --all, start with the oldest stash instead of the newest
+        #[clap(long, requires = "all")]
+        oldest_first: bool,
+        /// With --all, printed between consecutive stashes
+        #[clap(
+            short,
+            long,
+            requires = "all",
+            conflicts_with = "to-dir",
+            allow_hyphen_values = true
+        )]
+        separator: Option<String>,
+        /// With --all, write each stash to its own file in this directory instead of stdout
+        #[clap(long, requires = "all", conflicts_with = "output")]
+        to_dir: Option<PathBuf>,
+        /// Write the stash back to the path it was pushed from with --file
+        /// instead of printing it, unpacking directories
+        #[clap(long, conflicts_with = "all")]
+        restore: bool,
+        #[clap(flatten)]
+        options: ShowOptions,
+    },
+    /// Print several stashes back-to-back
+    Cat {
+        #[clap(required = true)]
+        ids: Vec<StashId>,
+        /// Printed between consecutive stashes
+        #[clap(short, long, allow_hyphen_values = true)]
+        separator: Option<String>,
+    },
+    /// Delete a stash without printing it: the newest one for a bare name,
+    /// or a specific one for `name:index`
+    #[clap(alias = "delete")]
+    Drop {
+        #[clap(required_unless_present_any = &["all-names", "stdin"])]
+        id: Option<StashId>,
+        /// Delete the stashes whose ids are read from stdin, one per line
+        #[clap(long, conflicts_with_all = &["id", "keep-last"])]
+        stdin: bool,
+        /// With --stdin, the ids are separated by NUL bytes instead of newlines
+        #[clap(short = '0', long, requires = "stdin")]
+        null: bool,
+        /// Delete all but the newest N stashes of the given name
+        #[clap(long, value_name = "N")]
+        keep_last: Option<usize>,
+        /// With --keep-last, trim every name instead of just one
+        #[clap(long, requires = "keep-last", conflicts_with = "id")]
+        all_names: bool,
+        /// With --keep-last, only print what would be deleted
+        #[clap(long, requires = "keep-last")]
+        dry_run: bool,
+    },
+    /// Bring back a popped or dropped stash, the most recently removed one by default.
+    /// `name:index` counts the removed stashes of `name`, most recently removed first.
+    Restore {
+        id: Option<StashId>,
+        /// List the stashes in the trash instead, only those of `id`'s name if given
+        #[clap(short, long)]
+        list: bool,
+    },
+    /// Replace text in a stash with a sed-style `s/pattern/replacement/flags` command.
+    /// The pattern uses Rust regex syntax, `&` and `\1` in the replacement work as in sed.
+    Sed {
+        id: StashId,
+        command: String,
+        /// Show a diff of the changes instead of applying them
+        #[clap(long)]
+        dry_run: bool,
+    },
+    /// Search the contents of all stashes, or of those under one name, for a regex.
+    /// Exits with 1 if nothing matched, like grep.
+    Grep {
+        pattern: String,
+        name: Option<String>,
+        /// Ignore case distinctions in the pattern
+        #[clap(short, long)]
+        ignore_case: bool,
+        /// Print lines of trailing context after each match
+        #[clap(short = 'A', long, value_name = "NUM")]
+        after_context: Option<usize>,
+        /// Print lines of leading context before each match
+        #[clap(short = 'B', long, value_name = "NUM")]
+        before_context: Option<usize>,
+        /// Print lines of context before and after each match
+        #[clap(short = 'C', long, value_name = "NUM")]
+        context: Option<usize>,
+        /// Only print the ids of stashes with a match
+        #[clap(short = 'l', long)]
+        ids_only: bool,
+        /// Search secret stashes too
+        #[clap(long)]
+        force: bool,
+    },
+    /// Open a stash in $VISUAL or $EDITOR and save the changes back into it
+    Edit {
+        #[clap(default_value_t)]
+        id: StashId,
+    },
+    /// Add a timestamped note to a stash, for example while investigating a captured log
+    Annotate {
+        id: StashId,
+        /// Read from stdin if not given
+        note: Vec<String>,
+    },
+    /// Show the differences between two stashes as a unified diff
+    Diff {
+        old: StashId,
+        new: StashId,
+        /// Open both stashes in this program instead, e.g. `meld` or `vimdiff`.
+        /// Defaults to `diff.tool` from the config.
+        #[clap(long, value_name = "CMD")]
+        tool: Option<String>,
+    },
+    /// Add tags to a stash with `+tag` or just `tag` and remove them with `-tag`
+    Tag {
+        id: StashId,
+        #[clap(required = true, allow_hyphen_values = true)]
+        changes: Vec<String>,
+    },
+    /// Count lines, words and bytes of a stash
+    Wc {
+        id: StashId,
+        /// Count every stash of the given name and print a total
+        #[clap(long)]
+        all: bool,
+    },
+    /// Exit successfully if the stash exists, with status 1 otherwise
+    Exists {
+        id: StashId,
+        /// Print the canonical id of the stash if it exists
+        #[clap(long)]
+        print_id: bool,
+    },
+    /// Move a stash to the top of its stack, making it `name:0`
+    Touch {
+        id: StashId,
+    },
+    /// Move a stash to another name, e.g. to name one pushed without a name.
+    /// It keeps its contents and timestamps.
+    Rename {
+        id: StashId,
+        new_name: String,
+    },
+    /// Push a copy of a stash, with its metadata, on top of the stack `new_name`
+    Copy {
+        id: StashId,
+        /// Defaults to the stash's own name
+        new_name: Option<String>,
+    },
+    /// Protect a stash from being changed or deleted
+    Lock {
+        id: StashId,
+    },
+    Unlock {
+        id: StashId,
+    },
+    /// Print the size of a stash in bytes
+    Size {
+        id: StashId,
+        /// Use KiB, MiB, ... instead of bytes
+        #[clap(short = 'H', long)]
+        human: bool,
+    },
+    /// Stash every file that appears in a directory
+    WatchDir {
+        dir: PathBuf,
+        /// Name to push the files under
+        #[clap(long, default_value_t)]
+        name: String,
+        /// Delete each file once it has been stashed
+        #[clap(long)]
+        delete: bool,
+        /// Move each file into this directory once it has been stashed
+        #[clap(long, conflicts_with = "delete")]
+        move_to: Option<PathBuf>,
+    },
+    /// Print the shell integration, to add `eval "$(stash shell-init bash)"` to
+    /// the shell's rc file: completions, and with --capture the recording of each
+    /// command's output for `last-output`
+    ShellInit {
+        #[clap(arg_enum)]
+        shell: shell::Shell,
+        /// Also record the output of every command. It goes through a pipe, so
+        /// programs see no terminal; those in $STASH_CAPTURE_IGNORE are left out.
+        #[clap(long)]
+        capture: bool,
+    },
+    /// Push the output of the previous command, as recorded by `shell-init --capture`
+    LastOutput {
+        #[clap(default_value = "")]
+        name: String,
+        /// Where the shell's recorded output is, set by `shell-init --capture`
+        #[clap(long, env = "STASH_CAPTURE_DIR", hide_env_values = true)]
+        capture_dir: PathBuf,
+    },
+    /// Run by `shell-init --capture` for every command: copy stdin to stdout and
+    /// keep its end for `last-output`
+    #[clap(hide = true)]
+    Capture {
+        /// The command line the output is of
+        command: String,
+        #[clap(long, env = "STASH_CAPTURE_DIR", hide_env_values = true)]
+        capture_dir: PathBuf,
+    },
+    /// Print the number of stashes, quickly enough to run on every shell prompt
+    Status {
+        /// Only count stashes of this name
+        #[clap(long)]
+        name: Option<String>,
+        /// Output format, `{count}` is replaced with the number of stashes
+        #[clap(long, default_value = "{count}")]
+        format: String,
+        /// Print nothing if there are no stashes
+        #[clap(long)]
+        hide_empty: bool,
+    },
+    /// List stashes for fzf, as `id<TAB>age<TAB>first line`. For example:
+    ///
+    /// stash fzf-source | fzf -d '\t' --preview "$(stash fzf-source --preview-cmd)" | cut -f1
+    FzfSource {
+        /// Print the command fzf should run for its preview window instead
+        #[clap(long)]
+        preview_cmd: bool,
+    },
+    /// Write stashes with their metadata to a zstd-compressed tar archive, for
+    /// moving them to another store
+    Export {
+        /// Only export stashes of these names
+        names: Vec<String>,
+        /// One json object per stash and line, with base64 encoded contents
+        #[clap(long)]
+        ndjson: bool,
+        /// Write to this file instead of stdout
+        #[clap(short, long, value_name = "PATH")]
+        output: Option<PathBuf>,
+        /// Export secret stashes too
+        #[clap(long)]
+        force: bool,
+        /// Leave out the stashes whose name and checksum are in the JSON list of
+        /// `[name, sha256]` pairs on stdin, for `sync`
+        #[clap(long, hide = true, conflicts_with = "ndjson")]
+        skip_known: bool,
+    },
+    /// Take a snapshot of the profile's whole store. In a backup dir, files that haven't
+    /// changed since the last snapshot are hard links to it, so this can run often.
+    Backup {
+        /// Backup dir to add the snapshot to, or a `.tar.zst` file to write it to
+        #[clap(long, value_name = "PATH")]
+        to: PathBuf,
+    },
+    /// Roll the profile's whole store back to a snapshot taken by `backup`.
+    /// The store as it was is kept in its `before-restore` dir.
+    RestoreBackup {
+        /// A snapshot, a backup dir to take the newest snapshot of, or a `.tar.zst` file
+        from: PathBuf,
+    },
+    /// Copy stashes between this store and the one on another machine, over ssh.
+    /// Each side gets the stashes it doesn't have yet.
+    Sync {
+        /// Where to ssh to, e.g. `user@host`
+        host: String,
+        /// Only sync stashes of these names
+        names: Vec<String>,
+        /// Only copy stashes from the other machine to this one
+        #[clap(long, conflicts_with = "push")]
+        pull: bool,
+        /// Only copy stashes from this machine to the other one
+        #[clap(long)]
+        push: bool,
+        /// How to run stash on the other machine
+        #[clap(long, value_name = "COMMAND", default_value = "stash")]
+        remote_command: String,
+        /// Copy secret stashes too
+        #[clap(long)]
+        force: bool,
+        /// Afterwards check by their checksums that both sides have the same
+        /// stashes, without transferring them again
+        #[clap(long)]
+        verify: bool,
+    },
+    /// Send a stash to another machine, which gets it with the `receive` command
+    /// this prints. It's end-to-end encrypted and only one try at the code is allowed.
+    Send {
+        #[clap(default_value_t)]
+        id: StashId,
+        /// Port to listen on, any free one by default
+        #[clap(long, default_value_t = 0)]
+        port: u16,
+        /// Send it even if it's secret
+        #[clap(long)]
+        force: bool,
+        /// Don't advertise it on the local network for `receive --discover`,
+        /// which only happens in a terminal, where sending can be confirmed
+        #[clap(long)]
+        no_advertise: bool,
+    },
+    /// Push a stash sent with `send`
+    Receive {
+        /// The code printed by `send`
+        #[clap(required_unless_present = "discover")]
+        code: Option<String>,
+        /// Look for `send`s on the local network instead, and pick one to receive.
+        /// Outside of a terminal, they're only listed with their codes.
+        #[clap(long, conflicts_with = "code")]
+        discover: bool,
+        /// Push it to this stack instead of the one of the same name
+        #[clap(long)]
+        name: Option<String>,
+    },
+    /// Push the stashes written by `export` on top of the existing ones
+    Import {
+        /// Read from this file instead of stdin
+        file: Option<PathBuf>,
+        #[clap(long)]
+        ndjson: bool,
+    },
+    /// Push every file matching a glob pattern, one stash per file
+    ImportFiles {
+        /// For example `'logs/*.txt'`, quoted so the shell doesn't expand it
+        pattern: String,
+        /// Name for the stashes, `{filename}` and `{stem}` are replaced with the
+        /// file's name with and without extension. The unnamed stack if not given.
+        #[clap(long, value_name = "TEMPLATE")]
+        name_from: Option<String>,
+    },
+    /// Run a stash as a script, exiting with its exit code. Without `#!` line or
+    /// an option for the interpreter, it is run with `sh`.
+    Exec {
+        #[clap(default_value_t)]
+        id: StashId,
+        /// Run it with $SHELL
+        #[clap(long, conflicts_with = "interpreter")]
+        shell: bool,
+        /// Run it with this program, e.g. `python3`
+        #[clap(long, value_name = "PROGRAM")]
+        interpreter: Option<String>,
+        /// Passed on to the script
+        #[clap(last = true)]
+        args: Vec<String>,
+    },
+    /// Pipe a stash through a command and print the result, for example:
+    ///
+    /// stash filter logs -- jq '.items[]'
+    Filter {
+        id: StashId,
+        /// Replace the stash with the output instead, if the command succeeds
+        #[clap(short, long)]
+        in_place: bool,
+        #[clap(last = true, required = true)]
+        command: Vec<String>,
+    },
+    /// Run a command in a pipeline, stashing its input as `name/input` and its
+    /// output as `name/output`. For example:
+    ///
+    /// producer | stash wrap filter-step -- sort -u | consumer
+    Wrap {
+        name: String,
+        #[clap(last = true, required = true)]
+        command: Vec<String>,
+    },
+    /// Send a stash by email, as an attachment if it isn't text
+    Mail {
+        #[clap(default_value_t)]
+        id: StashId,
+        #[clap(long)]
+        to: String,
+        /// Defaults to `mail.from` from the config
+        #[clap(long)]
+        from: Option<String>,
+        #[clap(long)]
+        subject: Option<String>,
+        /// Send it even if it's secret
+        #[clap(long)]
+        force: bool,
+    },
+    /// Upload a stash to a paste service and print its URL
+    Publish {
+        #[clap(default_value_t)]
+        id: StashId,
+        /// `0x0.st`, `paste.rs` or `gist`. Defaults to `publish.service` from the
+        /// config, and to paste.rs without it.
+        #[clap(long)]
+        to: Option<publish::Service>,
+        /// Publish it even if it's secret
+        #[clap(long)]
+        force: bool,
+    },
+    /// Download a URL into a new stash
+    Fetch {
+        url: String,
+        /// Name to push the download under
+        #[clap(long, default_value_t)]
+        name: String,
+    },
+    /// Stash the uncommitted changes of a git repo as a patch and apply it back,
+    /// in the same or another repo
+    Git {
+        #[clap(subcommand)]
+        command: GitCommand,
+    },
+    /// List the profiles or move stashes between them, see `--profile`
+    Profile {
+        #[clap(subcommand)]
+        command: ProfileCommand,
+    },
+    /// Print or change a setting in the config file, e.g. `stash config set list.sort size`
+    Config {
+        #[clap(subcommand)]
+        command: ConfigCommand,
+    },
+    /// Pick a recent stash from a menu and show, pop, copy or drop it
+    Menu {
+        /// Copy secret stashes to the clipboard too
+        #[clap(long)]
+        force: bool,
+    },
+    /// Browse stashes in a full-screen terminal UI
+    Tui,
+    /// Serves the clipboard's contents in the background for `--clipboard` on Linux
+    #[clap(hide = true)]
+    ServeClipboard,
+    /// Print the log of changes to the store as json lines
+    Events {
+        /// Keep running and print new events as they happen
+        #[clap(short, long)]
+        follow: bool,
+    },
+    /// Speak JSON-RPC on stdin/stdout, for editor plugins
+    Rpc {
+        /// Let `get` return secret stashes too
+        #[clap(long)]
+        force: bool,
+    },
+    /// Serve a REST API over HTTP to list, fetch, push and drop stashes, e.g.
+    /// `curl -H "Authorization: Bearer $TOKEN" http://127.0.0.1:7877/stashes/0`,
+    /// a page for doing that in the browser and a read-only WebDAV view at `/dav/`
+    Serve {
+        /// Address and port to listen on
+        #[clap(long, value_name = "ADDR", default_value = "127.0.0.1:7877")]
+        listen: String,
+        /// Bearer token requests have to carry, `serve.token` in the config if not given
+        #[clap(long)]
+        token: Option<String>,
+    },
+    /// Delete all stashes except locked ones
+    Clear,
+    /// Delete expired stashes now, rather than when they're next come across,
+    /// and those matching all of the given criteria. Locked stashes are kept.
+    Prune {
+        /// Only prune stashes of this name
+        name: Option<String>,
+        /// Stashes created longer ago than this, e.g. `30d`
+        #[clap(long, value_name = "DURATION", parse(try_from_str = parse_duration))]
+        older_than: Option<std::time::Duration>,
+        /// All but the newest N stashes of each name
+        #[clap(long, value_name = "N")]
+        keep_last: Option<usize>,
+        /// Stashes larger than this, e.g. `10M`
+        #[clap(long, value_name = "SIZE", parse(try_from_str = parse_size))]
+        larger_than: Option<u64>,
+        /// Only print what would be deleted
+        #[clap(long)]
+        dry_run: bool,
+    },
+    /// Show how many stashes there are and how much space they take, by name
+    #[clap(alias = "du")]
+    Stats {
+        /// How many of the largest stashes to show
+        #[clap(long, value_name = "N", default_value_t = 5)]
+        top: usize,
+    },
+    /// Measure push, list, show and pop latency against the current store
+    Bench {
+        /// How often to run each operation
+        #[clap(long, default_value_t = 100)]
+        iterations: usize,
+        /// Size of the benchmark stashes, in bytes
+        #[clap(long, default_value_t = 1024)]
+        size: usize,
+    },
+    /// Rebuild the index of stashes from the files
+    Reindex,
+    /// Check the data dir for files that don't belong there, metadata without a
+    /// stash and the like
+    Fsck {
+        /// Fix the problems found, files that can't be made sense of are moved to
+        /// the `quarantine` subdirectory
+        #[clap(long)]
+        repair: bool,
+        /// Also check every stash's contents against its checksum
+        #[clap(long)]
+        checksums: bool,
+    },
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum GitCommand {
+    /// Stash what `git diff HEAD` shows for the repo of the current directory,
+    /// along with its path, branch and commit
+    Push {
+        #[clap(default_value_t)]
+        name: String,
+        /// Also stash untracked files, except ignored ones
+        #[clap(short, long)]
+        untracked: bool,
+        /// A short description of the stash, shown by `list`
+        #[clap(short, long)]
+        message: Option<String>,
+    },
+    /// Apply a stashed patch to the repo of the current directory with `git apply`
+    Apply {
+        #[clap(default_value_t)]
+        id: StashId,
+        /// Merge the parts that don't apply cleanly, leaving conflicts to resolve
+        #[clap(long = "3way")]
+        three_way: bool,
+    },
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum ProfileCommand {
+    /// Print the profile of every store, marking the current one with `*`
+    List,
+    /// Move a stash to the top of its stack in another profile
+    Move {
+        id: StashId,
+        /// The profile to move it to
+        to: String,
+    },
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum ConfigCommand {
+    /// Print a setting, or exit with 1 if it isn't set. Nested keys are separated
+    /// by dots, e.g. `s3.bucket`.
+    Get { key: String },
+    /// Change a setting. The value is TOML, like `true`, `30` or `["a", "b"]`,
+    /// and taken as a string if it isn't valid TOML. The file is only written if
+    /// it's a valid config afterwards.
+    Set { key: String, value: String },
+}
+
+#[derive(clap::ArgEnum, Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum ListFormat {
+    Text,
+    Json,
+}
+
+/// A stash as printed by `list --format json`.
+#[derive(serde::Serialize)]
+struct ListEntry {
+    id: String,
+    name: String,
+    index: usize,
+    /// Stays the same when newer stashes are pushed, unlike the index
+    internal_id: String,
+    size: u64,
+    created: String,
+    locked: bool,
+    meta: Meta,
+}
+
+#[derive(clap::ArgEnum, Debug, Clone, Copy, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum TimeFormat {
+    Relative,
+    Local,
+    Rfc3339,
+}
+
+#[derive(clap::ArgEnum, Debug, Clone, Copy, Default, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum SortKey {
+    Name,
+    #[default]
+    Time,
+    Size,
+}
+
+/// The order `list` prints stashes in.
+#[derive(clap::Args, Debug, Default)]
+struct ListOrder {
+    /// Ascending, so sorted by `time` the newest stash is printed last.
+    /// `list.sort` in the config if not given, and `time` without it.
+    #[clap(long, arg_enum, conflicts_with = "tree")]
+    sort: Option<SortKey>,
+    #[clap(long, conflicts_with = "tree")]
+    reverse: bool,
+    /// Only print the last N stashes, e.g. the newest or with --sort size the largest
+    #[clap(long, value_name = "N", conflicts_with = "tree")]
+    limit: Option<usize>,
+}
+
+/// What a bare `stash` without a subcommand does.
+fn default_command(default_action: config::DefaultAction) -> Subcommand {
+    let push = match default_action {
+        config::DefaultAction::Auto => !std::io::stdin().is_terminal(),
+        config::DefaultAction::Push => true,
+        config::DefaultAction::List => false,
+    };
+    match push {
+        true => Subcommand::Push {
+            name: String::new(),
+            append: false,
+            strict: false,
+            max_history: None,
+            files: vec![],
+            move_files: false,
+            message: None,
+            tags: vec![],
+            lang: None,
+            compress: false,
+            uncompressed: false,
+            encrypt: false,
+            secret: false,
+            expire: None,
+            clipboard: false,
+            tmux: None,
+        },
+        false => Subcommand::List {
+            tree: false,
+            filter: ListFilter::default(),
+            order: ListOrder::default(),
+            null: false,
+            time: None,
+            format: None,
+        },
+    }
+}
+
+/// The columns `list` starts each line with: id, time and size.
+fn list_rows(stashes: &[Data], time: TimeFormat) -> Result<Vec<String>> {
+    let mut rows = vec![];
+    for stash in stashes {
+        let created = match time {
+            TimeFormat::Relative => format_age(stash.created),
+            TimeFormat::Local => format_local_time(stash.created)?,
+            TimeFormat::Rfc3339 => format_time(stash.created)?,
+        };
+        rows.push((stash.id().to_string(), created, human_size(stash.size)));
+    }
+    let id_width = rows.iter().map(|row| row.0.len()).max().unwrap_or(0);
+    let time_width = rows.iter().map(|row| row.1.len()).max().unwrap_or(0);
+    Ok(rows
+        .into_iter()
+        .map(|(id, created, size)| format!("{id:id_width$}  {created:time_width$}  {size:>10}"))
+        .collect())
+}
+
+impl ListOrder {
+    /// Expects the stashes in `Data::detect` order.
+    fn apply(&self, mut stashes: Vec<Data>) -> Vec<Data> {
+        // oldest first, which the stable sorts keep among equal keys
+        stashes.reverse();
+        match self.sort.unwrap_or_default() {
+            SortKey::Name => stashes.sort_by(|a, b| a.name.cmp(&b.name)),
+            SortKey::Time => {}
+            SortKey::Size => stashes.sort_by_key(|stash| stash.size),
+        }
+        if self.reverse {
+            stashes.reverse();
+        }
+        if let Some(limit) = self.limit {
+            stashes.drain(..stashes.len().saturating_sub(limit));
+        }
+        stashes
+    }
+}
+
+/// Which stashes `list` shows. Every given condition must hold.
+#[derive(clap::Args, Debug, Default)]
+struct ListFilter {
+    /// Only list stashes of this name, or with names matching this glob pattern
+    #[clap(value_name = "NAME_OR_GLOB")]
+    pattern: Option<glob::Pattern>,
+    /// Only list stashes whose names match this regex
+    #[clap(long)]
+    regex: Option<regex::Regex>,
+    /// Only list stashes created after this time, given as RFC3339,
+    /// a date like 2024-05-01 or an age like 30m, 12h, 7d
+    #[clap(long, value_name = "TIME", parse(try_from_str = parse_time))]
+    since: Option<std::time::SystemTime>,
+    /// Only list stashes created before this time, in the same formats as --since
+    #[clap(long, value_name = "TIME", parse(try_from_str = parse_time))]
+    until: Option<std::time::SystemTime>,
+    /// Only list stashes of at least this size, in bytes or with a K, M or G suffix
+    #[clap(long, value_name = "SIZE", parse(try_from_str = parse_size))]
+    min_size: Option<u64>,
+    /// Only list stashes of at most this size, in the same format as --min-size
+    #[clap(long, value_name = "SIZE", parse(try_from_str = parse_size))]
+    max_size: Option<u64>,
+    /// Only list stashes pushed on this host
+    #[clap(long)]
+    host: Option<String>,
+    /// Only list stashes pushed from this directory or below it
+    #[clap(long)]
+    cwd: Option<std::path::PathBuf>,
+    /// Only list stashes with this tag, can be given multiple times
+    #[clap(long = "tag", value_name = "TAG")]
+    tags: Vec<String>,
+}
+
+impl ListFilter {
+    fn apply(&self, store: &dyn StorageBackend, mut stashes: Vec<Data>) -> Result<Vec<Data>> {
+        stashes.retain(|stash| {
+            self.pattern
+                .as_ref()
+                .is_none_or(|pattern| pattern.matches(&stash.name))
+                && self
+                    .regex
+                    .as_ref()
+                    .is_none_or(|regex| regex.is_match(&stash.name))
+                && self.since.is_none_or(|since| stash.created >= since)
+                && self.until.is_none_or(|until| stash.created <= until)
+                && self.min_size.is_none_or(|min| stash.size >= min)
+                && self.max_size.is_none_or(|max| stash.size <= max)
+        });
+
+        // the remaining conditions need the metadata, which is only read if necessary
+        if self.host.is_none() && self.cwd.is_none() && self.tags.is_empty() {
+            return Ok(stashes);
+        }
+        if self.host.is_none() && self.cwd.is_none() {
+            let mut filtered = vec![];
+            for stash in stashes {
+                let tags = store.tags(&stash)?;
+                if self.tags.iter().all(|tag| tags.contains(tag)) {
+                    filtered.push(stash);
+                }
+            }
+            return Ok(filtered);
+        }
+        let cwd = self.cwd.as_ref().map(std::path::absolute).transpose()?;
+        let mut filtered = vec![];
+        for stash in stashes {
+            let meta = store.meta(&stash)?;
+            let provenance_matches = (self.host.is_none() && cwd.is_none())
+                || meta
+                    .provenance
+                    .is_some_and(|p| p.matches(self.host.as_deref(), cwd.as_deref()));
+            if provenance_matches && self.tags.iter().all(|tag| meta.tags.contains(tag)) {
+                filtered.push(stash);
+            }
+        }
+        Ok(filtered)
+    }
+}
+
+/// Transformations applied to a stash's contents on the way to stdout,
+/// and where they go instead of stdout.
+#[derive(clap::Args, Debug, Default)]
+struct ShowOptions {
+    /// Decompress gzip or zstd compressed stashes on output
+    #[clap(short = 'z', long)]
+    decompress: bool,
+    /// Print each distinct line only once, at its first occurrence
+    #[clap(long)]
+    unique_lines: bool,
+    /// Sort lines bytewise
+    #[clap(long)]
+    sort_lines: bool,
+    /// Fill in `${VAR}` placeholders from --var or the environment
+    #[clap(long)]
+    render: bool,
+    /// A value for --render, given as key=value
+    #[clap(long = "var", value_name = "KEY=VALUE", requires = "render", parse(try_from_str = render::parse_var))]
+    vars: Vec<(String, String)>,
+    /// Write to this file instead of stdout
+    #[clap(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+    /// Overwrite the --output file if it already exists, and print secret stashes
+    /// somewhere other than a terminal
+    #[clap(long)]
+    force: bool,
+    /// Put the contents on the clipboard instead of stdout
+    #[clap(long, conflicts_with = "output")]
+    clipboard: bool,
+    /// Put the contents into a tmux paste buffer instead of stdout, a new one if no name is given
+    #[clap(long, value_name = "BUFFER", conflicts_with_all = &["output", "clipboard"])]
+    tmux: Option<Option<String>>,
+    /// Don't page the output with $PAGER when printing to a terminal
+    #[clap(long)]
+    no_pager: bool,
+    /// Don't highlight syntax when printing to a terminal, always the case with
+    /// `color = "never"` in the config
+    #[clap(long)]
+    plain: bool,
+    /// Print binary contents as a hex dump
+    #[clap(long, conflicts_with = "raw")]
+    hex: bool,
+    /// Print the contents as they are stored: still compressed or encrypted if
+    /// pushed that way, and even binary ones to a terminal
+    #[clap(long)]
+    raw: bool,
+    /// Check the contents against the checksum recorded when they were pushed,
+    /// and fail instead of printing them if they don't match
+    #[clap(long)]
+    verify: bool,
+    /// Only set by `show`, a popped stash is gone afterwards
+    #[clap(skip)]
+    window: Window,
+}
+
+/// The part of a stash's output to print.
+#[derive(clap::Args, Debug, Default)]
+struct Window {
+    /// Only print the first NUM lines
+    #[clap(long, value_name = "NUM", conflicts_with_all = &["tail", "bytes"])]
+    head: Option<usize>,
+    /// Only print the last NUM lines
+    #[clap(long, value_name = "NUM", conflicts_with = "bytes")]
+    tail: Option<usize>,
+    /// Only print this range of bytes, end exclusive, e.g. `4K..8K`, `..100` or `1M..`
+    #[clap(long, value_name = "RANGE", parse(try_from_str = parse_byte_range))]
+    bytes: Option<(u64, Option<u64>)>,
+}
+
+impl Window {
+    /// Cuts the window out of `reader`. Only as much is read as needed, except
+    /// for `tail`, which has to go through everything.
+    fn apply<'a>(&self, mut reader: Box<dyn Read + 'a>) -> Result<Box<dyn Read + 'a>> {
+        if let Some(lines) = self.head {
+            return Ok(Box::new(HeadLines {
+                reader: std::io::BufReader::new(reader),
+                remaining: lines,
+            }));
+        }
+        if let Some(lines) = self.tail {
+            let mut tail = std::collections::VecDeque::with_capacity(lines);
+            for line in std::io::BufReader::new(reader).split(b'\n') {
+                if tail.len() == lines {
+                    tail.pop_front();
+                }
+                if lines > 0 {
+                    tail.push_back(line?);
+                }
+            }
+            let mut contents = vec![];
+            for line in tail {
+                contents.extend(line);
+                contents.push(b'\n');
+            }
+            return Ok(Box::new(std::io::Cursor::new(contents)));
+        }
+        if let Some((start, end)) = self.bytes {
+            std::io::copy(&mut (&mut reader).take(start), &mut std::io::sink())?;
+            if let Some(end) = end {
+                return Ok(Box::new(reader.take(end.saturating_sub(start))));
+            }
+        }
+        Ok(reader)
+    }
+}
+
+/// Passes on lines until `remaining` of them have been read.
+struct HeadLines<R> {
+    reader: std::io::BufReader<R>,
+    remaining: usize,
+}
+
+impl<R: Read> Read for HeadLines<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.remaining == 0 {
+            return Ok(0);
+        }
+        let available = self.reader.fill_buf()?;
+        let mut n = available.len().min(buf.len());
+        if let Some(newline) = available[..n].iter().position(|&b| b == b'\n') {
+            n = newline + 1;
+            self.remaining -= 1;
+        }
+        buf[..n].copy_from_slice(&available[..n]);
+        self.reader.consume(n);
+        Ok(n)
+    }
+}
+
+impl ShowOptions {
+    fn to_terminal(&self) -> bool {
+        self.output.is_none() && !self.to_buffer() && std::io::stdout().is_terminal()
+    }
+
+    /// Whether the output goes to the clipboard or a tmux buffer, which take it all at once.
+    fn to_buffer(&self) -> bool {
+        self.clipboard || self.tmux.is_some()
+    }
+
+    fn write_buffer(&self, contents: Vec<u8>) -> Result<()> {
+        match &self.tmux {
+            Some(buffer) => tmux::load_buffer(buffer.as_deref(), &contents),
+            None => clipboard::write(contents),
+        }
+    }
+
+    fn open_output(&self) -> Result<Box<dyn Write>> {
+        let Some(path) = &self.output else {
+            if !self.no_pager && std::io::stdout().is_terminal() {
+                if let Some(pager) = pager::Pager::spawn()? {
+                    return Ok(Box::new(std::io::BufWriter::new(pager)));
+                }
+            }
+            return Ok(Box::new(std::io::stdout().lock()));
+        };
+        let mut options = fs_err::OpenOptions::new();
+        match self.force {
+            true => options.write(true).create(true).truncate(true),
+            false => options.write(true).create_new(true),
+        };
+        let file = options.open(path).map_err(|e| match e.kind() {
+            std::io::ErrorKind::AlreadyExists => {
+                eyre!("{path} already exists, pass --force to overwrite it")
+            }
+            _ => e.into(),
+        })?;
+        Ok(Box::new(std::io::BufWriter::new(file)))
+    }
+}
+
+impl Data {
+    /// All stashes, newest first.
+    fn detect(store: &dyn StorageBackend) -> Result<Vec<Data>> {
+        let stashes = Data::remove_expired(store, store.entries()?)?;
+        Ok(Data::numbered(stashes))
+    }
+
+    /// The stashes in the stack `name`, newest first.
+    /// Unlike `detect`, this doesn't look at the other stacks.
+    fn stack(store: &dyn StorageBackend, name: &str) -> Result<Vec<Data>> {
+        let stashes = Data::remove_expired(store, store.stack(name)?)?;
+        Ok(Data::numbered(stashes))
+    }
+
+    /// Expired stashes are only deleted once they're come across,
+    /// until then they're skipped.
+    fn remove_expired(store: &dyn StorageBackend, stashes: Vec<Data>) -> Result<Vec<Data>> {
+        let (expired, stashes): (Vec<_>, _) = stashes.into_iter().partition(Data::expired);
+        for stash in &expired {
+            store.remove(stash)?;
+            events::record(store, events::Kind::Expire, Some(stash))?;
+        }
+        Ok(stashes)
+    }
+
+    /// Sorts the stashes newest first and fills in their indices.
+    fn numbered(mut stashes: Vec<Data>) -> Vec<Data> {
+        stashes.sort_by_key(|data| std::cmp::Reverse(data.seq));
+
+        let mut next_index = std::collections::HashMap::new();
+        for stash in &mut stashes {
+            let index = next_index.entry(stash.name.clone()).or_insert(0);
+            stash.index = *index;
+            *index += 1;
+        }
+        stashes
+    }
+
+    fn get(store: &dyn StorageBackend, id: &StashId) -> Result<Option<Data>> {
+        if id.index == 0 {
+            match store.newest(&id.name)? {
+                // removed by `stack`, the next one takes its place
+                Some(stash) if stash.expired() => {}
+                newest => return Ok(newest),
+            }
+        }
+        Ok(Data::stack(store, &id.name)?
+            .into_iter()
+            .find(|data| data.index == id.index))
+    }
+
+    fn get_newest(store: &dyn StorageBackend, name: &str) -> Result<Option<Data>> {
+        Data::get(store, &StashId::newest(name))
+    }
+}
+
+/// Runs the command line interface with the process's arguments.
+pub fn run() -> Result<()> {
+    let args = Args::parse();
+    // neither needs the store, and Ctrl-C is for the command `capture` records
+    match &args.command {
+        Some(Subcommand::ShellInit { shell, capture }) => {
+            return shell::init(*shell, *capture, &mut Args::command())
+        }
+        Some(Subcommand::Capture {
+            command,
+            capture_dir,
+        }) => return shell::capture(capture_dir, command),
+        _ => {}
+    }
+    interrupt::install_handler()?;
+
+    let proj_dirs = directories::ProjectDirs::from("", "", "stash")
+        .ok_or_else(|| eyre!("couldn't get project dirs"))?;
+    let config_path = PathBuf::from_path_buf(proj_dirs.config_dir().join("config.toml"))
+        .map_err(|_| eyre!("non-utf8 config dir path"))?;
+    // before loading the config, so a broken one can be fixed with `config set`
+    if let Some(Subcommand::Config { command }) = &args.command {
+        return match command {
+            ConfigCommand::Get { key } => match config::get(&config_path, key)? {
+                Some(value) => {
+                    println!("{value}");
+                    Ok(())
+                }
+                None => std::process::exit(1),
+            },
+            ConfigCommand::Set { key, value } => config::set(&config_path, key, value),
+        };
+    }
+    let mut config = config::Config::load(&config_path)?;
+    if let Some(remote) = &args.remote {
+        let (url, fragment) = match remote.split_once('#') {
+            Some((url, token)) => (url, Some(token)),
+            None => (remote.as_str(), None),
+        };
+        let token = args
+            .remote_token
+            .as_deref()
+            .or(fragment)
+            .or(config.serve.token.as_deref())
+            .ok_or_else(|| eyre!("--remote needs a token, pass it with --remote-token"))?;
+        let command = args
+            .command
+            .unwrap_or_else(|| default_command(config.default_action));
+        return remote::run(&stash_client::Client::new(url, token), &config, command);
+    }
+    // `STASH_DIR` may be set for the whole session, `--ephemeral` is given for the command
+    let data_dir = match (args.ephemeral, &args.data_dir, config.data_dir.take()) {
+        (true, ..) => proj_dirs
+            .runtime_dir()
+            .ok_or_else(|| eyre!("no runtime dir available for ephemeral stashes"))?
+            .to_owned(),
+        // absolute, so it's the same dir for commands run elsewhere, like fzf's preview
+        (false, Some(data_dir), _) => std::path::absolute(data_dir)?,
+        (false, None, Some(data_dir)) => data_dir.into(),
+        (false, None, None) => proj_dirs.data_dir().to_owned(),
+    };
+    let data_dir = PathBuf::from_path_buf(data_dir).map_err(|_| eyre!("non-utf8 data dir path"))?;
+    highlight::set_theme(config.theme.take())?;
+    events::set_webhooks(std::mem::take(&mut config.webhook));
+    let backend = args.backend.unwrap_or(config.backend);
+    if backend == config::Backend::S3 && args.ephemeral {
+        return Err(eyre!("--ephemeral only works with the local backend"));
+    }
+    // stashes from before sequence numbers can only be in the default data dir
+    if backend == config::Backend::Local && data_dir == proj_dirs.data_dir() {
+        storage::LocalFs::upgrade(data_dir.clone())?;
+    }
+    let stores = profile::Stores {
+        backend,
+        data_dir: &data_dir,
+        s3: &config.s3,
+    };
+    let store = stores.open(&args.profile)?;
+    let store: &dyn StorageBackend = &*store;
+    let profile_dir = stores.data_dir(&args.profile)?;
+    let trash_days = config.trash_days.unwrap_or(7);
+    store.expire_trash(std::time::Duration::from_secs(trash_days * 24 * 60 * 60))?;
+
+    let mut command = args
+        .command
+        .unwrap_or_else(|| default_command(config.default_action));
+
+    if let Subcommand::Show {
+        id,
+        interactive: true,
+        ..
+    }
+    | Subcommand::Pop {
+        id,
+        interactive: true,
+        ..
+    } = &mut command
+    {
+        match menu::pick(store)? {
+            Some(stash) => *id = stash.id(),
+            None => return Ok(()),
+        }
+    }
+    if let Subcommand::Show { options, .. } | Subcommand::Pop { options, .. } = &mut command {
+        options.plain |= config.color == config::Color::Never;
+    }
+
+    match command {
+        Subcommand::List {
+            tree,
+            filter,
+            mut order,
+            null,
+            time,
+            format,
+        } => {
+            order.sort = order.sort.or(config.list.sort);
+            let time = time.or(config.list.time).unwrap_or(TimeFormat::Relative);
+            // the configured format is only a default for the formats that use it
+            let configured_format = config.list.format.filter(|_| !tree && !null);
+            let format = format.or(configured_format).unwrap_or(ListFormat::Text);
+            let stashes = filter.apply(store, Data::detect(store)?)?;
+            // `detect` order for the tree, the order of the output lines for the rest
+            let stashes = match tree {
+                true => stashes,
+                false => order.apply(stashes),
+            };
+
+            match (format, tree, null) {
+                (ListFormat::Json, ..) => {
+                    let mut entries = vec![];
+                    for stash in stashes {
+                        entries.push(ListEntry {
+                            id: stash.id().to_string(),
+                            internal_id: stash.filename(),
+                            created: format_time(stash.created)?,
+                            meta: store.meta(&stash)?,
+                            name: stash.name,
+                            index: stash.index,
+                            size: stash.size,
+                            locked: stash.locked,
+                        });
+                    }
+                    println!("{}", serde_json::to_string_pretty(&entries)?);
+                }
+                (_, true, _) => tree::print(&stashes),
+                (_, false, true) => {
+                    let mut stdout = std::io::stdout().lock();
+                    for stash in stashes {
+                        write!(stdout, "{}\0", stash.id())?;
+                    }
+                }
+                (_, false, false) => {
+                    for (stash, mut line) in stashes.iter().zip(list_rows(&stashes, time)?) {
+                        let meta = store.meta(stash)?;
+                        if meta.encrypted {
+                            line += "  (encrypted)";
+                        }
+                        if meta.secret {
+                            line += "  (secret)";
+                        }
+                        if !meta.tags.is_empty() {
+                            line += &format!("  [{}]", meta.tags.join(", "));
+                        }
+                        if let Some(message) = meta.message {
+                            line += &format!("  {message}");
+                        }
+                        println!("{line}");
+                    }
+                }
+            }
+        }
+        Subcommand::Push {
+            name,
+            strict,
+            max_history,
+            files,
+            move_files,
+            message,
+            tags,
+            lang,
+            compress,
+            uncompressed,
+            encrypt,
+            secret,
+            expire,
+            ..
+        } if !files.is_empty() => {
+            let encoding =
+                (compress || (config.compress && !uncompressed)).then_some(Encoding::Zstd);
+            let expires = expiry(expire, config.expire.as_deref())?;
+            for path in files {
+                let origin = PathBuf::from_path_buf(std::path::absolute(&path)?)
+                    .map_err(|_| eyre!("non-utf8 path {path}"))?;
+                let meta = Meta {
+                    filename: path.file_name().map(str::to_owned),
+                    origin: Some(origin.to_string()),
+                    message: message.clone(),
+                    tags: meta::normalized_tags(tags.clone()),
+                    lang: lang.clone(),
+                    encoding,
+                    encrypted: encrypt || config.encrypt,
+                    secret,
+                    expires: expires.clone(),
+                    ..Meta::captured()
+                };
+                if strict {
+                    let size = match path.is_dir() {
+                        true => archive::archive_size(&path)?,
+                        false => fs_err::metadata(&path)?.len(),
+                    };
+                    quota::check(store, &config, &name, max_history, size)?;
+                }
+                let pushed = match path.is_dir() {
+                    true => archive::push_dir(store, &name, meta, &path)?,
+                    false => push(store, &name, &meta, &mut fs_err::File::open(&path)?)?,
+                };
+                if move_files {
+                    match path.is_dir() {
+                        true => fs_err::remove_dir_all(&path)?,
+                        false => fs_err::remove_file(&path)?,
+                    }
+                }
+                quota::enforce(store, &config, &pushed, max_history)?;
+            }
+        }
+        Subcommand::Push {
+            name,
+            strict,
+            max_history,
+            message,
+            tags,
+            lang,
+            compress,
+            uncompressed,
+            encrypt,
+            secret,
+            expire,
+            clipboard,
+            tmux,
+            ..
+        } if clipboard || tmux.is_some() => {
+            let contents = match tmux {
+                Some(buffer) => tmux::save_buffer(buffer.as_deref())?,
+                None => clipboard::read()?.into_bytes(),
+            };
+            if strict {
+                quota::check(store, &config, &name, max_history, contents.len() as u64)?;
+            }
+            let meta = Meta {
+                message,
+                tags: meta::normalized_tags(tags),
+                lang,
+                encoding: (compress || (config.compress && !uncompressed))
+                    .then_some(Encoding::Zstd),
+                encrypted: encrypt || config.encrypt,
+                secret,
+                expires: expiry(expire, config.expire.as_deref())?,
+                ..Meta::captured()
+            };
+            let pushed = push(store, &name, &meta, &mut contents.as_slice())?;
+            quota::enforce(store, &config, &pushed, max_history)?;
+        }
+        Subcommand::Push {
+            name,
+            append,
+            strict,
+            max_history,
+            message,
+            tags,
+            lang,
+            compress,
+            uncompressed,
+            encrypt,
+            secret,
+            expire,
+            ..
+        } => {
+            let stdin = &mut std::io::stdin().lock();
+            let newest = match append {
+                true => Data::get_newest(store, &name)?,
+                false => None,
+            };
+            let pushed = match newest {
+                Some(stash) => {
+                    append_to(store, &stash, stdin)?;
+                    stash
+                }
+                None => {
+                    let meta = Meta {
+                        message,
+                        tags: meta::normalized_tags(tags),
+                        lang,
+                        encoding: (compress || (config.compress && !uncompressed))
+                            .then_some(Encoding::Zstd),
+                        encrypted: encrypt || config.encrypt,
+                        secret,
+                        expires: expiry(expire, config.expire.as_deref())?,
+                        ..Meta::captured()
+                    };
+                    match strict {
+                        // the size has to be known before anything is pushed
+                        true => {
+                            let mut contents = vec![];
+                            stdin.read_to_end(&mut contents)?;
+                            quota::check(
+                                store,
+                                &config,
+                                &name,
+                                max_history,
+                                contents.len() as u64,
+                            )?;
+                            push(store, &name, &meta, &mut contents.as_slice())?
+                        }
+                        false => push(store, &name, &meta, stdin)?,
+                    }
+                }
+            };
+            quota::enforce(store, &config, &pushed, max_history)?;
+        }
+        Subcommand::Append { name, separator } => {
+            let stdin = &mut std::io::stdin().lock();
+            let pushed = match Data::get_newest(store, &name)? {
+                Some(stash) => {
+                    let separator = match (separator, stash.size) {
+                        (Some(separator), 1..) => format!("{separator}\n"),
+                        _ => String::new(),
+                    };
+                    append_to(store, &stash, &mut separator.as_bytes().chain(stdin))?;
+                    stash
+                }
+                None => push(store, &name, &Meta::captured(), stdin)?,
+            };
+            quota::enforce(store, &config, &pushed, None)?;
+        }
+        Subcommand::Show { id, meta: true, .. } => {
+            let stash = Data::get(store, &id)?.ok_or_else(|| eyre!("Stash {id} does not exist"))?;
+            let meta = store.meta(&stash)?;
+            if let Some(message) = meta.message {
+                println!("message: {message}");
+            }
+            if !meta.tags.is_empty() {
+                println!("tags: {}", meta.tags.join(", "));
+            }
+            if let Some(filename) = meta.filename {
+                println!("file: {filename}");
+            }
+            if let Some(lang) = meta.lang {
+                println!("lang: {lang}");
+            }
+            if meta.archive == Some(archive::Archive::Tar) {
+                println!("archive: tar");
+            }
+            if meta.encoding == Some(Encoding::Zstd) {
+                println!("encoding: zstd");
+            }
+            if let Some(format) = meta.format {
+                println!("format: {}", format.name());
+            }
+            if meta.encrypted {
+                println!("encrypted: yes");
+            }
+            if meta.secret {
+                println!("secret: yes");
+            }
+            if let Some(expires) = &meta.expires {
+                println!("expires: {expires}");
+            }
+            if let Some(sha256) = &meta.sha256 {
+                println!("sha256: {sha256}");
+            }
+            for url in meta.published {
+                println!("published: {url}");
+            }
+            if let Some(download) = meta.download {
+                println!("url: {}", download.url);
+                if let Some(etag) = download.etag {
+                    println!("etag: {etag}");
+                }
+                if let Some(content_type) = download.content_type {
+                    println!("content type: {content_type}");
+                }
+            }
+            if let Some(git) = meta.git {
+                println!("repo: {}", git.repo);
+                if let Some(branch) = git.branch {
+                    println!("branch: {branch}");
+                }
+                println!("commit: {}", git.commit);
+            }
+            if let Some(provenance) = meta.provenance {
+                println!("host: {}", provenance.host);
+                if let Some(user) = provenance.user {
+                    println!("user: {user}");
+                }
+                if let Some(cwd) = provenance.cwd {
+                    println!("cwd: {cwd}");
+                }
+                let stdin = if provenance.stdin_tty {
+                    "terminal"
+                } else {
+                    "pipe"
+                };
+                println!("stdin: {stdin}");
+            }
+        }
+        Subcommand::Show {
+            id, notes: true, ..
+        } => {
+            let stash = Data::get(store, &id)?.ok_or_else(|| eyre!("Stash {id} does not exist"))?;
+            for note in store.meta(&stash)?.notes {
+                println!("{}  {}", note.time, note.text);
+            }
+        }
+        Subcommand::Show {
+            id,
+            delete,
+            mut options,
+            window,
+            ..
+        } => {
+            options.window = window;
+            let stash = Data::get(store, &id)?.ok_or_else(|| eyre!("Stash {id} does not exist"))?;
+            if delete {
+                ensure_unlocked(&stash)?;
+            }
+            print_stash(store, &stash, &options)?;
+            if delete {
+                delete_stash(store, Some(stash))?
+            }
+        }
+        Subcommand::Pop {
+            id,
+            all: true,
+            oldest_first,
+            separator,
+            to_dir,
+            options,
+            ..
+        } => {
+            if id.index != 0 {
+                return Err(eyre!("pop --all takes a name, not {id}"));
+            }
+            let mut stashes = Data::stack(store, &id.name)?;
+            if stashes.is_empty() {
+                return Err(eyre!("Stash {} does not exist", id.name));
+            }
+            if oldest_first {
+                stashes.reverse();
+            }
+            for stash in &stashes {
+                ensure_unlocked(stash)?;
+            }
+
+            match to_dir {
+                Some(dir) => {
+                    fs_err::create_dir_all(&dir)?;
+                    for stash in &stashes {
+                        let path = dir.join(stash.id().to_string().replace(':', "."));
+                        let mut file = fs_err::OpenOptions::new()
+                            .write(true)
+                            .create_new(true)
+                            .open(path)?;
+                        write_stash(store, stash, &options, &mut file)?;
+                    }
+                }
+                None => print_stashes(store, &stashes, separator.as_deref(), &options)?,
+            }
+            // only delete once everything has been written out
+            for stash in stashes {
+                store.remove(&stash)?;
+                events::record(store, events::Kind::Pop, Some(&stash))?;
+            }
+        }
+        Subcommand::Pop {
+            id, restore: true, ..
+        } => {
+            let stash = Data::get(store, &id)?.ok_or_else(|| eyre!("Stash {id} does not exist"))?;
+            ensure_unlocked(&stash)?;
+            let meta = store.meta(&stash)?;
+            let origin = meta.origin.ok_or_else(|| {
+                eyre!("Stash {id} wasn't pushed from a file, there's nowhere to restore it to")
+            })?;
+            archive::restore(store, &stash, meta.archive, origin.as_ref())?;
+            store.remove(&stash)?;
+            events::record(store, events::Kind::Pop, Some(&stash))?;
+            eprintln!("Restored {origin}");
+        }
+        Subcommand::Pop { id, options, .. } => {
+            let stash = Data::get(store, &id)?.ok_or_else(|| eyre!("Stash {id} does not exist"))?;
+            ensure_unlocked(&stash)?;
+            print_stash(store, &stash, &options)?;
+            store.remove(&stash)?;
+            events::record(store, events::Kind::Pop, Some(&stash))?;
+        }
+        Subcommand::Cat { ids, separator } => {
+            let stashes = ids
+                .iter()
+                .map(|id| Data::get(store, id)?.ok_or_else(|| eyre!("Stash {id} does not exist")))
+                .collect::<Result<Vec<_>>>()?;
+            print_stashes(
+                store,
+                &stashes,
+                separator.as_deref(),
+                &ShowOptions::default(),
+            )?;
+        }
+        Subcommand::Drop {
+            id,
+            keep_last: Some(keep_last),
+            all_names,
+            dry_run,
+            ..
+        } => {
+            if let Some(id) = id.as_ref().filter(|id| id.index != 0) {
+                return Err(eyre!("--keep-last takes a name, not {id}"));
+            }
+            let trimmed = match (all_names, &id) {
+                (true, _) => Data::detect(store)?,
+                (false, Some(id)) => Data::stack(store, &id.name)?,
+                (false, None) => vec![],
+            };
+            for stash in trimmed.into_iter().filter(|stash| stash.index >= keep_last) {
+                match (dry_run, stash.locked) {
+                    (true, true) => println!("Would keep locked stash {}", stash.id()),
+                    (true, false) => println!("Would delete {}", stash.id()),
+                    (false, true) => eprintln!("Keeping locked stash {}", stash.id()),
+                    (false, false) => {
+                        let id = stash.id();
+                        delete_stash(store, Some(stash))?;
+                        println!("Deleted {id}");
+                    }
+                }
+            }
+        }
+        Subcommand::Drop {
+            stdin: true, null, ..
+        } => {
+            let mut input = String::new();
+            std::io::stdin().read_to_string(&mut input)?;
+            let separator = if null { '\0' } else { '\n' };
+            let ids = input
+                .split(separator)
+                .filter(|id| !id.is_empty())
+                .map(|id| id.parse::<StashId>().unwrap());
+            // all ids refer to the stashes as they were before the first one is deleted
+            let stashes = Data::detect(store)?;
+            let mut doomed = vec![];
+            for id in ids {
+                let stash = stashes
+                    .iter()
+                    .find(|stash| stash.id() == id)
+                    .ok_or_else(|| eyre!("Stash {id} does not exist"))?;
+                ensure_unlocked(stash)?;
+                doomed.push(stash.clone());
+            }
+            for stash in doomed {
+                delete_stash(store, Some(stash))?;
+            }
+        }
+        Subcommand::Drop { id, .. } => {
+            let id = id.unwrap();
+            let stash = Data::get(store, &id)?.ok_or_else(|| eyre!("Stash {id} does not exist"))?;
+            delete_stash(store, Some(stash))?;
+        }
+        Subcommand::Names { null } => {
+            let mut names = store.names()?;
+            names.sort();
+            names.dedup();
+            let terminator = if null { '\0' } else { '\n' };
+            let mut stdout = std::io::stdout().lock();
+            for name in names.iter().filter(|name| !name.is_empty()) {
+                write!(stdout, "{name}{terminator}")?;
+            }
+        }
+        Subcommand::Restore { id, list: true } => {
+            for (stash, removed) in trashed(store, id.as_ref())? {
+                println!(
+                    "{}  removed {}  {:>10}",
+                    stash.id(),
+                    format_age(removed),
+                    human_size(stash.size)
+                );
+            }
+        }
+        Subcommand::Restore { id, list: false } => {
+            let stash = trashed(store, id.as_ref())?
+                .into_iter()
+                .map(|(stash, _)| stash)
+                .find(|stash| id.as_ref().is_none_or(|id| stash.index == id.index))
+                .ok_or_else(|| match &id {
+                    Some(id) => eyre!("No removed stash {id}"),
+                    None => eyre!("The trash is empty"),
+                })?;
+            let seq = store.restore(&stash)?.seq;
+            let restored = Data::stack(store, &stash.name)?
+                .into_iter()
+                .find(|stash| stash.seq == seq);
+            if let Some(restored) = &restored {
+                eprintln!("Restored {}", restored.id());
+            }
+            events::record(store, events::Kind::Restore, restored.as_ref())?;
+        }
+        Subcommand::Sed {
+            id,
+            command,
+            dry_run,
+        } => {
+            let substitution = sed::Substitution::parse(&command)?;
+            let stash = Data::get(store, &id)?.ok_or_else(|| eyre!("Stash {id} does not exist"))?;
+            if !dry_run {
+                ensure_unlocked(&stash)?;
+            }
+            let mut contents = vec![];
+            store.reader(&stash)?.read_to_end(&mut contents)?;
+            let replaced = substitution.apply(&contents);
+
+            match dry_run {
+                true => {
+                    let (old, new) = (
+                        String::from_utf8_lossy(&contents),
+                        String::from_utf8_lossy(&replaced),
+                    );
+                    let diff = similar::TextDiff::from_lines(&old, &new);
+                    let id = stash.id().to_string();
+                    print!("{}", diff.unified_diff().header(&id, &id));
+                }
+                false => {
+                    store.replace(&stash, &mut replaced.as_slice())?;
+                    events::record(store, events::Kind::Modify, Some(&stash))?;
+                }
+            }
+        }
+        Subcommand::Edit { id } => {
+            let stash = Data::get(store, &id)?.ok_or_else(|| eyre!("Stash {id} does not exist"))?;
+            ensure_unlocked(&stash)?;
+            edit::edit(store, &stash)?;
+        }
+        Subcommand::Diff { old, new, tool } => {
+            let old = Data::get(store, &old)?.ok_or_else(|| eyre!("Stash {old} does not exist"))?;
+            let new = Data::get(store, &new)?.ok_or_else(|| eyre!("Stash {new} does not exist"))?;
+            match tool.or(config.diff.tool) {
+                Some(tool) => diff::run_tool(store, &tool, &old, &new)?,
+                None => diff::print(store, &old, &new)?,
+            }
+        }
+        Subcommand::Wc { id, all } => {
+            let stashes = match all {
+                true => Data::stack(store, &id.name)?,
+                false => {
+                    vec![Data::get(store, &id)?.ok_or_else(|| eyre!("Stash {id} does not exist"))?]
+                }
+            };
+            let mut total = Counts::default();
+            for stash in &stashes {
+                let counts = Counts::of(store.reader(stash)?)?;
+                println!("{} {}", counts, stash.id());
+                total.lines += counts.lines;
+                total.words += counts.words;
+                total.bytes += counts.bytes;
+            }
+            if all {
+                println!("{total} total");
+            }
+        }
+        Subcommand::Annotate { id, note } => {
+            let stash = Data::get(store, &id)?.ok_or_else(|| eyre!("Stash {id} does not exist"))?;
+            let text = match note.is_empty() {
+                true => std::io::read_to_string(std::io::stdin())?,
+                false => note.join(" "),
+            };
+            let mut meta = store.meta(&stash)?;
+            meta.notes.push(meta::Note {
+                time: format_time(std::time::SystemTime::now())?,
+                text: text.trim_end().to_owned(),
+            });
+            store.set_meta(&stash, &meta)?;
+        }
+        Subcommand::Grep {
+            pattern,
+            name,
+            ignore_case,
+            after_context,
+            before_context,
+            context,
+            ids_only,
+            force,
+        } => {
+            let regex = regex::bytes::RegexBuilder::new(&pattern)
+                .case_insensitive(ignore_case)
+                .build()?;
+            let stashes = match &name {
+                Some(name) => Data::stack(store, name)?,
+                None => Data::detect(store)?,
+            };
+            let stashes = without_withheld(store, stashes, force)?;
+            let context = grep::Context {
+                before: before_context.or(context).unwrap_or(0),
+                after: after_context.or(context).unwrap_or(0),
+            };
+            if !grep::grep(store, &stashes, &regex, context, ids_only)? {
+                std::process::exit(1);
+            }
+        }
+        Subcommand::Tag { id, changes } => {
+            let stash = Data::get(store, &id)?.ok_or_else(|| eyre!("Stash {id} does not exist"))?;
+            let mut meta = store.meta(&stash)?;
+            meta.change_tags(&changes)?;
+            store.set_meta(&stash, &meta)?;
+        }
+        Subcommand::Exists { id, print_id } => match Data::get(store, &id)? {
+            Some(stash) if print_id => println!("{}", stash.id()),
+            Some(_) => {}
+            None => std::process::exit(1),
+        },
+        Subcommand::Touch { id } => {
+            let stash = Data::get(store, &id)?.ok_or_else(|| eyre!("Stash {id} does not exist"))?;
+            store.touch(&stash)?;
+            events::record(store, events::Kind::Touch, Some(&stash))?;
+        }
+        Subcommand::Rename { id, new_name } => {
+            let stash = Data::get(store, &id)?.ok_or_else(|| eyre!("Stash {id} does not exist"))?;
+            rename_stash(store, &stash, &new_name)?;
+        }
+        Subcommand::Copy { id, new_name } => {
+            let stash = Data::get(store, &id)?.ok_or_else(|| eyre!("Stash {id} does not exist"))?;
+            let name = new_name.unwrap_or_else(|| stash.name.clone());
+            let copy = push(
+                store,
+                &name,
+                &store.meta(&stash)?,
+                &mut store.reader(&stash)?,
+            )?;
+            eprintln!("Copied to {}", copy.id());
+        }
+        Subcommand::Lock { id } => {
+            let stash = Data::get(store, &id)?.ok_or_else(|| eyre!("Stash {id} does not exist"))?;
+            store.set_locked(&stash, true)?;
+        }
+        Subcommand::Unlock { id } => {
+            let stash = Data::get(store, &id)?.ok_or_else(|| eyre!("Stash {id} does not exist"))?;
+            store.set_locked(&stash, false)?;
+        }
+        Subcommand::Size { id, human } => {
+            let stash = Data::get(store, &id)?.ok_or_else(|| eyre!("Stash {id} does not exist"))?;
+            match human {
+                true => println!("{}", human_size(stash.size)),
+                false => println!("{}", stash.size),
+            }
+        }
+        Subcommand::WatchDir {
+            dir,
+            name,
+            delete,
+            move_to,
+        } => {
+            let after_push = match (delete, move_to) {
+                (true, _) => watch::AfterPush::Delete,
+                (false, Some(dir)) => watch::AfterPush::MoveTo(dir),
+                (false, None) => watch::AfterPush::Keep,
+            };
+            watch::watch_dir(store, &dir, &name, &after_push)?;
+        }
+        Subcommand::LastOutput { name, capture_dir } => {
+            let stash = shell::last_output(store, &capture_dir, &name)?;
+            eprintln!("Stashed as {}", stash.id());
+        }
+        Subcommand::Status {
+            name,
+            format,
+            hide_empty,
+        } => {
+            // what `list` would show, without removing the expired ones here
+            let count = store
+                .entries()?
+                .into_iter()
+                .filter(|stash| !stash.expired())
+                .filter(|stash| name.as_ref().is_none_or(|name| *name == stash.name))
+                .count();
+            if count != 0 || !hide_empty {
+                println!("{}", format.replace("{count}", &count.to_string()));
+            }
+        }
+        Subcommand::FzfSource { preview_cmd: true } => {
+            let exe = std::env::current_exe()?;
+            let mut flags = String::new();
+            if args.ephemeral {
+                flags += " --ephemeral";
+            } else if args.data_dir.is_some() {
+                flags += &format!(" --data-dir '{data_dir}'");
+            }
+            if args.profile != profile::DEFAULT {
+                flags += &format!(" --profile {}", args.profile);
+            }
+            println!("'{}'{flags} show {{1}}", exe.display());
+        }
+        Subcommand::FzfSource { preview_cmd: false } => {
+            for stash in Data::detect(store)? {
+                let snippet = first_line(store, &stash)?;
+                println!("{}\t{}\t{snippet}", stash.id(), format_age(stash.created));
+            }
+        }
+        Subcommand::Export {
+            names,
+            ndjson,
+            output,
+            force,
+            skip_known,
+        } => {
+            let known = match skip_known {
+                true => serde_json::from_reader(std::io::stdin().lock())
+                    .map_err(|e| eyre!("expected the known stashes on stdin: {e}"))?,
+                false => Default::default(),
+            };
+            let mut out: Box<dyn Write> = match &output {
+                Some(path) => Box::new(std::io::BufWriter::new(fs_err::File::create(path)?)),
+                None if !ndjson && std::io::stdout().is_terminal() => {
+                    return Err(eyre!(
+                        "Not writing an archive to the terminal, pass -o to write it to a file"
+                    ))
+                }
+                None => Box::new(std::io::stdout().lock()),
+            };
+            match ndjson {
+                true => ndjson::export(store, &names, force, &mut out)?,
+                false => export::export(store, &names, force, &known, &mut out)?,
+            }
+            out.flush()?;
+        }
+        Subcommand::Backup { .. } | Subcommand::RestoreBackup { .. }
+            if backend != config::Backend::Local =>
+        {
+            return Err(eyre!(
+                "backups are of the data dir, other backends can use `export` instead"
+            ))
+        }
+        Subcommand::Backup { to } => {
+            let snapshot = backup::backup(&profile_dir, &to)?;
+            eprintln!("Backed up to {snapshot}");
+        }
+        Subcommand::RestoreBackup { from } => {
+            let old = backup::restore(&profile_dir, &from)?;
+            eprintln!("Restored {from}, the store as it was before is in {old}");
+        }
+        Subcommand::Sync {
+            host,
+            names,
+            pull,
+            push,
+            remote_command,
+            force,
+            verify,
+        } => {
+            sync::sync(store, &host, &remote_command, &names, (pull, push), force)?;
+            if verify {
+                sync::verify(store, &host, &remote_command, &names, force)?;
+            }
+        }
+        Subcommand::Send {
+            id,
+            port,
+            force,
+            no_advertise,
+        } => {
+            let stash = Data::get(store, &id)?.ok_or_else(|| eyre!("Stash {id} does not exist"))?;
+            ensure_shareable(store, &stash, force)?;
+            let advertise = !no_advertise && std::io::stdin().is_terminal();
+            wormhole::send(store, &stash, port, advertise)?
+        }
+        Subcommand::Receive {
+            code,
+            // without a code, clap made sure it's set
+            discover: _,
+            name,
+        } => {
+            let code = match code {
+                Some(code) => code,
+                None => match pick_sender()? {
+                    Some(code) => code,
+                    None => return Ok(()),
+                },
+            };
+            let stash = wormhole::receive(store, &code, name.as_deref())?;
+            eprintln!("Received {}", stash.id());
+        }
+        Subcommand::Import { file, ndjson } => {
+            let input: Box<dyn BufRead> = match &file {
+                Some(path) => Box::new(std::io::BufReader::new(fs_err::File::open(path)?)),
+                None => Box::new(std::io::stdin().lock()),
+            };
+            match ndjson {
+                true => ndjson::import(store, input)?,
+                false => export::import(store, input)?,
+            }
+        }
+        Subcommand::ImportFiles { pattern, name_from } => {
+            import_files::import_files(store, &pattern, name_from.as_deref())?
+        }
+        Subcommand::Exec {
+            id,
+            shell,
+            interpreter,
+            args,
+        } => {
+            let stash = Data::get(store, &id)?.ok_or_else(|| eyre!("Stash {id} does not exist"))?;
+            let interpreter = match (shell, interpreter) {
+                (true, _) => exec::Interpreter::Shell,
+                (false, Some(program)) => exec::Interpreter::Program(program),
+                (false, None) => exec::Interpreter::Script,
+            };
+            let status = exec::exec(store, &stash, interpreter, &args)?;
+            if !status.success() {
+                std::process::exit(status.code().unwrap_or(1));
+            }
+        }
+        Subcommand::Filter {
+            id,
+            in_place,
+            command,
+        } => {
+            let stash = Data::get(store, &id)?.ok_or_else(|| eyre!("Stash {id} does not exist"))?;
+            if in_place {
+                ensure_unlocked(&stash)?;
+            }
+            let status = filter::filter(store, &stash, &command, in_place)?;
+            if !status.success() {
+                std::process::exit(status.code().unwrap_or(1));
+            }
+        }
+        Subcommand::Wrap { name, command } => {
+            let status = wrap::wrap(store, &name, &command)?;
+            if !status.success() {
+                std::process::exit(status.code().unwrap_or(1));
+            }
+        }
+        Subcommand::Mail {
+            id,
+            to,
+            from,
+            subject,
+            force,
+        } => {
+            let stash = Data::get(store, &id)?.ok_or_else(|| eyre!("Stash {id} does not exist"))?;
+            ensure_shareable(store, &stash, force)?;
+            let from = from.or_else(|| config.mail.from.clone()).ok_or_else(|| {
+                eyre!("no sender address, pass --from or set mail.from in the config")
+            })?;
+            mail::send(store, &config.mail, &stash, &from, &to, subject)?;
+        }
+        Subcommand::Publish { id, to, force } => {
+            let stash = Data::get(store, &id)?.ok_or_else(|| eyre!("Stash {id} does not exist"))?;
+            ensure_shareable(store, &stash, force)?;
+            let service = to
+                .or(config.publish.service)
+                .unwrap_or(publish::Service::PasteRs);
+            let url = publish::publish(
+                store,
+                &stash,
+                service,
+                config.publish.github_token.as_deref(),
+            )?;
+            let mut meta = store.meta(&stash)?;
+            meta.published.push(url.clone());
+            store.set_meta(&stash, &meta)?;
+            println!("{url}");
+        }
+        Subcommand::Fetch { url, name } => {
+            let response = ureq::get(&url).call()?;
+            let header = |name| {
+                response
+                    .headers()
+                    .get(name)
+                    .and_then(|value| value.to_str().ok())
+                    .map(str::to_owned)
+            };
+            let meta = Meta {
+                download: Some(meta::Download {
+                    url: url.clone(),
+                    etag: header("etag"),
+                    content_type: header("content-type"),
+                }),
+                ..Meta::captured()
+            };
+            let stash = push(store, &name, &meta, &mut response.into_body().into_reader())?;
+            eprintln!("Stashed {url} as {}", stash.id());
+            quota::enforce(store, &config, &stash, None)?;
+        }
+        Subcommand::Git {
+            command:
+                GitCommand::Push {
+                    name,
+                    untracked,
+                    message,
+                },
+        } => {
+            let stash = git::push(store, &name, message, untracked)?;
+            eprintln!("Stashed the changes as {}", stash.id());
+            quota::enforce(store, &config, &stash, None)?;
+        }
+        Subcommand::Git {
+            command: GitCommand::Apply { id, three_way },
+        } => {
+            let stash = Data::get(store, &id)?.ok_or_else(|| eyre!("Stash {id} does not exist"))?;
+            git::apply(store, &stash, three_way)?;
+        }
+        Subcommand::Profile {
+            command: ProfileCommand::List,
+        } => {
+            let mut profiles = stores.list()?;
+            // the s3 backend only knows of profiles that have been pushed to
+            if !profiles.contains(&args.profile) {
+                profiles.push(args.profile.clone());
+                profiles.sort();
+            }
+            for profile in profiles {
+                let current = if profile == args.profile { '*' } else { ' ' };
+                println!("{current} {profile}");
+            }
+        }
+        Subcommand::Profile {
+            command: ProfileCommand::Move { id, to },
+        } => {
+            if to == args.profile {
+                return Err(eyre!("Stash {id} is in profile {to} already"));
+            }
+            let stash = Data::get(store, &id)?.ok_or_else(|| eyre!("Stash {id} does not exist"))?;
+            let target = stores.open(&to)?;
+            let moved = profile::move_stash(store, stash, &*target)?;
+            eprintln!("Moved to {} in profile {to}", moved.id());
+            quota::enforce(&*target, &config, &moved, None)?;
+        }
+        Subcommand::Config { .. } => unreachable!("handled before loading the config"),
+        Subcommand::ShellInit { .. } | Subcommand::Capture { .. } => {
+            unreachable!("handled before opening the store")
+        }
+        Subcommand::Menu { force } => menu::run(store, force)?,
+        Subcommand::Tui => tui::run(store)?,
+        Subcommand::ServeClipboard => clipboard::serve()?,
+        Subcommand::Events { follow } => events::print(store, follow)?,
+        Subcommand::Stats { top } => stats::print(store, top)?,
+        Subcommand::Bench { iterations, size } => bench::run(store, iterations, size)?,
+        Subcommand::Reindex => store.reindex()?,
+        Subcommand::Fsck { repair, checksums } => {
+            let problems = store.fsck(repair)?;
+            for problem in &problems {
+                println!("{problem}");
+            }
+            // there's nothing to repair corrupted stashes from
+            let mut corrupted = false;
+            if checksums {
+                for stash in Data::detect(store)? {
+                    // decrypting would ask for the passphrase, and age detects
+                    // corruption by itself
+                    if store.meta(&stash)?.encrypted {
+                        continue;
+                    }
+                    if let Err(e) = checksum::verify(store, &stash) {
+                        println!("{e}");
+                        corrupted = true;
+                    }
+                }
+            }
+            if corrupted || !repair && !problems.is_empty() {
+                std::process::exit(1);
+            }
+        }
+        Subcommand::Rpc { force } => rpc::serve(store, force)?,
+        Subcommand::Serve { listen, token } => {
+            let (token, generated) = match token.or(config.serve.token.take()) {
+                Some(token) => (token, false),
+                None => {
+                    let token = serve::generate_token()?;
+                    eprintln!("Token: {token}");
+                    (token, true)
+                }
+            };
+            serve::serve(store, &config, &listen, &token, generated)?
+        }
+        Subcommand::Clear => {
+            let (locked, unlocked) = Data::detect(store)?
+                .into_iter()
+                .partition::<Vec<_>, _>(|stash| stash.locked);
+            for stash in unlocked {
+                store.remove(&stash)?;
+            }
+            events::record(store, events::Kind::Clear, None)?;
+            if !locked.is_empty() {
+                eprintln!("Kept {} locked stash(es)", locked.len());
+            }
+        }
+        Subcommand::Prune {
+            name,
+            older_than,
+            keep_last,
+            larger_than,
+            dry_run,
+        } => {
+            let stashes = match &name {
+                Some(name) => store.stack(name)?,
+                None => store.entries()?,
+            };
+            let (expired, live) = stashes.into_iter().partition::<Vec<_>, _>(Data::expired);
+            for stash in Data::numbered(expired) {
+                match dry_run {
+                    true => println!("Would delete expired stash {}", stash.id()),
+                    false => {
+                        store.remove(&stash)?;
+                        events::record(store, events::Kind::Expire, Some(&stash))?;
+                        println!("Deleted expired stash {}", stash.id());
+                    }
+                }
+            }
+
+            if older_than.is_none() && keep_last.is_none() && larger_than.is_none() {
+                return Ok(());
+            }
+            let pruned = Data::numbered(live).into_iter().filter(|stash| {
+                older_than.is_none_or(|age| stash.created.elapsed().is_ok_and(|e| e > age))
+                    && keep_last.is_none_or(|keep_last| stash.index >= keep_last)
+                    && larger_than.is_none_or(|size| stash.size > size)
+            });
+            for stash in pruned {
+                match (dry_run, stash.locked) {
+                    (true, true) => println!("Would keep locked stash {}", stash.id()),
+                    (true, false) => println!("Would delete {}", stash.id()),
+                    (false, true) => eprintln!("Keeping locked stash {}", stash.id()),
+                    (false, false) => {
+                        let id = stash.id();
+                        delete_stash(store, Some(stash))?;
+                        println!("Deleted {id}");
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Default)]
+struct Counts {
+    lines: u64,
+    words: u64,
+    bytes: u64,
+}
+
+impl Counts {
+    fn of(reader: impl Read) -> Result<Counts> {
+        let mut reader = std::io::BufReader::new(reader);
+        let mut counts = Counts::default();
+        let mut in_word = false;
+        loop {
+            let buf = reader.fill_buf()?;
+            if buf.is_empty() {
+                return Ok(counts);
+            }
+            for &byte in buf {
+                counts.lines += u64::from(byte == b'\n');
+                let is_space = byte.is_ascii_whitespace();
+                counts.words += u64::from(!in_word && !is_space);
+                in_word = !is_space;
+            }
+            counts.bytes += buf.len() as u64;
+            let len = buf.len();
+            reader.consume(len);
+        }
+    }
+}
+
+impl std::fmt::Display for Counts {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:>7} {:>7} {:>7}", self.lines, self.words, self.bytes)
+    }
+}
+
+fn format_time(timestamp: std::time::SystemTime) -> Result<String> {
+    let unix_epoch = time::OffsetDateTime::UNIX_EPOCH;
+    let since_epoch = timestamp.duration_since(std::time::UNIX_EPOCH).unwrap();
+    let timestamp = time::Duration::try_from(since_epoch)
+        .ok()
+        .and_then(|since_epoch| unix_epoch.checked_add(since_epoch))
+        .ok_or_else(|| eyre!("{since_epoch:?} after 1970 is too far in the future"))?;
+    Ok(timestamp.format(&time::format_description::well_known::Rfc3339)?)
+}
+
+/// `timestamp` in the local time zone, or in UTC if that can't be determined.
+fn format_local_time(timestamp: std::time::SystemTime) -> Result<String> {
+    let timestamp = time::OffsetDateTime::from(timestamp);
+    let offset = time::UtcOffset::local_offset_at(timestamp).unwrap_or(time::UtcOffset::UTC);
+    let format = time::macros::format_description!("[year]-[month]-[day] [hour]:[minute]:[second]");
+    Ok(timestamp.to_offset(offset).format(&format)?)
+}
+
+/// How long ago `timestamp` was, in the largest fitting unit.
+fn format_age(timestamp: std::time::SystemTime) -> String {
+    let secs = timestamp.elapsed().map_or(0, |age| age.as_secs());
+    let (amount, unit) = match secs {
+        0..=59 => return "just now".to_owned(),
+        60..=3599 => (secs / 60, "minute"),
+        3600..=86399 => (secs / 3600, "hour"),
+        86400..=2591999 => (secs / 86400, "day"),
+        2592000..=31535999 => (secs / 2592000, "month"),
+        _ => (secs / 31536000, "year"),
+    };
+    let plural = if amount == 1 { "" } else { "s" };
+    format!("{amount} {unit}{plural} ago")
+}
+
+/// Parses an RFC3339 timestamp, a date (midnight UTC) or an age like `12h`.
+fn parse_time(input: &str) -> Result<std::time::SystemTime, String> {
+    use time::format_description::well_known::Rfc3339;
+
+    if let Ok(timestamp) = time::OffsetDateTime::parse(input, &Rfc3339) {
+        return Ok(timestamp.into());
+    }
+    let date_format = time::macros::format_description!("[year]-[month]-[day]");
+    if let Ok(date) = time::Date::parse(input, &date_format) {
+        return Ok(date.midnight().assume_utc().into());
+    }
+    let age = parse_duration(input)
+        .map_err(|_| format!("expected a timestamp, date or age, got {input:?}"))?;
+    std::time::SystemTime::now()
+        .checked_sub(age)
+        .ok_or_else(|| format!("age {input:?} is too long"))
+}
+
+/// Parses a duration like `90s`, `12h` or `7d`.
+fn parse_duration(input: &str) -> Result<std::time::Duration, String> {
+    let split = input
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(input.len());
+    let (amount, unit) = input.split_at(split);
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        "w" => 604800,
+        _ => {
+            return Err(format!(
+                "expected a duration like 30m, 12h or 7d, got {input:?}"
+            ))
+        }
+    };
+    let amount: u64 = amount
+        .parse()
+        .map_err(|_| format!("expected a duration like 30m, 12h or 7d, got {input:?}"))?;
+    let seconds = amount
+        .checked_mul(seconds)
+        .ok_or_else(|| format!("duration {input:?} is too long"))?;
+    Ok(std::time::Duration::from_secs(seconds))
+}
+
+/// When a stash pushed now expires, after `expire` or the configured default.
+fn expiry(expire: Option<std::time::Duration>, default: Option<&str>) -> Result<Option<String>> {
+    let expire = match expire {
+        Some(expire) => Some(expire),
+        None => default
+            .map(parse_duration)
+            .transpose()
+            .map_err(|e| eyre!("expire in the config: {e}"))?,
+    };
+    expire
+        .map(|expire| {
+            let too_far = || eyre!("expiry in {expire:?} is too far in the future");
+            let expires = std::time::SystemTime::now()
+                .checked_add(expire)
+                .ok_or_else(too_far)?;
+            format_time(expires).map_err(|_| too_far())
+        })
+        .transpose()
+}
+
+/// Parses a `START..END` range of sizes in bytes, where either side may be left out.
+fn parse_byte_range(input: &str) -> Result<(u64, Option<u64>), String> {
+    let (start, end) = input
+        .split_once("..")
+        .ok_or_else(|| format!("expected a range like 4K..8K, got {input:?}"))?;
+    let start = match start {
+        "" => 0,
+        start => parse_size(start)?,
+    };
+    let end = match end {
+        "" => None,
+        end => Some(parse_size(end)?),
+    };
+    if end.is_some_and(|end| end < start) {
+        return Err(format!("range {input:?} ends before it starts"));
+    }
+    Ok((start, end))
+}
+
+/// Parses a size in bytes, optionally with a binary K, M or G suffix.
+fn parse_size(input: &str) -> Result<u64, String> {
+    let split = input
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(input.len());
+    let (amount, unit) = input.split_at(split);
+    let unit = match unit.trim_end_matches("iB").trim_end_matches('B') {
+        "" => 1,
+        "K" | "k" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        _ => {
+            return Err(format!(
+                "expected a size like 512, 10K or 2M, got {input:?}"
+            ))
+        }
+    };
+    let amount: u64 = amount
+        .parse()
+        .map_err(|_| format!("expected a size like 512, 10K or 2M, got {input:?}"))?;
+    amount
+        .checked_mul(unit)
+        .ok_or_else(|| format!("size {input:?} is too large"))
+}
+
+/// Random bytes from the operating system, for tokens and codes.
+fn random_bytes<const N: usize>() -> Result<[u8; N]> {
+    let mut bytes = [0; N];
+    fs_err::File::open("/dev/urandom")?.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["KiB", "MiB", "GiB", "TiB", "PiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
+
+/// The beginning of a stash's first line, made safe to print on a single line.
+fn first_line(store: &dyn StorageBackend, stash: &Data) -> Result<String> {
+    if withheld(store, stash, false)? {
+        return Ok("(secret)".to_owned());
+    }
+    // not worth asking for the passphrase
+    if store.meta(stash)?.encrypted {
+        return Ok("(encrypted)".to_owned());
+    }
+    let mut head = vec![];
+    store.reader(stash)?.take(4096).read_to_end(&mut head)?;
+    let head = String::from_utf8_lossy(&head);
+    let first_line = head.lines().next().unwrap_or_default();
+    Ok(first_line
+        .chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .take(80)
+        .collect())
+}
+
+/// Creates a new stash on top of the stack `name`.
+fn push(
+    store: &dyn StorageBackend,
+    name: &str,
+    meta: &Meta,
+    contents: &mut dyn Read,
+) -> Result<Data> {
+    let mut contents = std::io::BufReader::new(contents);
+    let format = compression::Format::detect(contents.fill_buf()?);
+    let meta = Meta {
+        format,
+        // they wouldn't get any smaller
+        encoding: meta.encoding.filter(|_| format.is_none()),
+        ..meta.clone()
+    };
+    let stash = store.create(name, &meta, &mut contents)?;
+    events::record(store, events::Kind::Push, Some(&stash))?;
+    Ok(stash)
+}
+
+/// Appends to an existing stash.
+fn append_to(store: &dyn StorageBackend, stash: &Data, contents: &mut dyn Read) -> Result<()> {
+    ensure_unlocked(stash)?;
+    let mut file = store.append(stash)?;
+    let appended = Data {
+        size: stash.size + std::io::copy(contents, &mut file)?,
+        ..stash.clone()
+    };
+    // backends that can't append in place upload it here
+    file.flush()?;
+    // and the checksum is only updated once it's closed
+    drop(file);
+    events::record(store, events::Kind::Append, Some(&appended))
+}
+
+/// Writes the stash's contents to `out`, transformed according to `options`.
+fn write_stash(
+    store: &dyn StorageBackend,
+    stash: &Data,
+    options: &ShowOptions,
+    out: &mut dyn Write,
+) -> Result<()> {
+    if options.verify {
+        checksum::verify(store, stash)?;
+    }
+    if !options.to_terminal() {
+        ensure_shareable(store, stash, options.force)?;
+    }
+    let mut contents = std::io::BufReader::with_capacity(
+        binary::SNIFF_LEN,
+        shown_contents(store, stash, options)?,
+    );
+    if options.hex {
+        binary::hex_dump(&mut contents, out)?;
+        return Ok(());
+    }
+    if !options.to_terminal() || options.raw {
+        std::io::copy(&mut contents, out)?;
+        return Ok(());
+    }
+
+    // binary data would only garble the terminal. Failing rather than printing
+    // a summary keeps `pop` from deleting what was never shown.
+    if binary::is_binary(contents.fill_buf()?) {
+        return Err(eyre!(
+            "Stash {} is {} of binary data, pass --hex to dump it or --raw to print it anyway",
+            stash.id(),
+            human_size(stash.size)
+        ));
+    }
+    match options.plain {
+        true => {
+            std::io::copy(&mut contents, out)?;
+        }
+        false => {
+            let meta = store.meta(stash)?;
+            highlight::highlight(
+                meta.lang.as_deref(),
+                meta.filename.as_deref(),
+                &mut contents,
+                out,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// The contents of the stash with the transformations and window of `options` applied.
+fn shown_contents(
+    store: &dyn StorageBackend,
+    stash: &Data,
+    options: &ShowOptions,
+) -> Result<Box<dyn Read>> {
+    let transformed =
+        options.decompress || options.unique_lines || options.sort_lines || options.render;
+    if let (Some((start, end)), false) = (options.window.bytes, transformed || options.raw) {
+        // no need to read what comes before the range
+        let file = store.reader_at(stash, start)?;
+        return Ok(match end {
+            Some(end) => Box::new(file.take(end.saturating_sub(start))),
+            None => file,
+        });
+    }
+
+    let mut file = match options.raw {
+        true => store.raw_reader(stash)?,
+        false => store.reader(stash)?,
+    };
+    if options.decompress {
+        file = compression::decompressed(file, store.meta(stash)?.format)?;
+    }
+    if !(options.unique_lines || options.sort_lines || options.render) {
+        return options.window.apply(file);
+    }
+
+    let mut contents = vec![];
+    file.read_to_end(&mut contents)?;
+    if options.render {
+        contents = render::render(&contents, &options.vars)?;
+    }
+    if options.unique_lines || options.sort_lines {
+        let mut lines = contents
+            .split_inclusive(|&b| b == b'\n')
+            .collect::<Vec<_>>();
+        // so the last line compares equal to others even without its newline
+        let trim = |line: &[u8]| line.strip_suffix(b"\n").unwrap_or(line).to_vec();
+        if options.sort_lines {
+            lines.sort_by_key(|line| trim(line));
+        }
+        if options.unique_lines {
+            let mut seen = std::collections::HashSet::new();
+            lines.retain(|line| seen.insert(trim(line)));
+        }
+        let mut transformed = vec![];
+        for line in lines {
+            transformed.extend(trim(line));
+            transformed.push(b'\n');
+        }
+        contents = transformed;
+    }
+    options
+        .window
+        .apply(Box::new(std::io::Cursor::new(contents)))
+}
+
+fn print_stash(store: &dyn StorageBackend, stash: &Data, options: &ShowOptions) -> Result<()> {
+    if options.to_buffer() {
+        let mut contents = vec![];
+        write_stash(store, stash, options, &mut contents)?;
+        options.write_buffer(contents)?;
+    } else {
+        let mut out = options.open_output()?;
+        write_stash(store, stash, options, &mut out)?;
+        out.flush()?;
+    }
+    Ok(())
+}
+
+/// Prints the stashes back-to-back, with an optional separator line in between.
+fn print_stashes(
+    store: &dyn StorageBackend,
+    stashes: &[Data],
+    separator: Option<&str>,
+    options: &ShowOptions,
+) -> Result<()> {
+    let mut contents = vec![];
+    let mut out: Box<dyn Write> = match options.to_buffer() {
+        true => Box::new(&mut contents),
+        false => options.open_output()?,
+    };
+    for (i, stash) in stashes.iter().enumerate() {
+        if let (Some(separator), true) = (separator, i != 0) {
+            writeln!(out, "{separator}")?;
+        }
+        write_stash(store, stash, options, &mut out)?;
+    }
+    out.flush()?;
+    drop(out);
+    if options.to_buffer() {
+        options.write_buffer(contents)?;
+    }
+    Ok(())
+}
+
+fn ensure_unlocked(stash: &Data) -> Result<()> {
+    match stash.locked {
+        true => Err(eyre!("Stash {} is locked, unlock it first", stash.id())),
+        false => Ok(()),
+    }
+}
+
+/// Whether the stash's contents are kept from everything but the user's terminal,
+/// because it's secret and `force` isn't given. Whatever hands out contents asks
+/// this first, and skips the stash or fails with `ensure_shareable`.
+fn withheld(store: &dyn StorageBackend, stash: &Data, force: bool) -> Result<bool> {
+    Ok(!force && store.meta(stash)?.secret)
+}
+
+fn ensure_shareable(store: &dyn StorageBackend, stash: &Data, force: bool) -> Result<()> {
+    match withheld(store, stash, force)? {
+        true => Err(eyre!(
+            "Stash {} is secret, pass --force to let it out of the terminal",
+            stash.id()
+        )),
+        false => Ok(()),
+    }
+}
+
+/// Drops the withheld stashes, with a warning if there are any.
+fn without_withheld(
+    store: &dyn StorageBackend,
+    stashes: Vec<Data>,
+    force: bool,
+) -> Result<Vec<Data>> {
+    let mut shareable = Vec::with_capacity(stashes.len());
+    let mut skipped = 0;
+    for stash in stashes {
+        match withheld(store, &stash, force)? {
+            true => skipped += 1,
+            false => shareable.push(stash),
+        }
+    }
+    if skipped > 0 {
+        eprintln!("Skipped {skipped} secret stashes, pass --force to include them");
+    }
+    Ok(shareable)
+}
+
+/// Writes `contents` to a file in a new temporary dir, for programs that need a
+/// path. Both are only accessible to the user from the start, the file with
+/// `mode`. The dir is removed when the returned `TempDir` is dropped.
+fn private_copy(
+    prefix: &str,
+    filename: &str,
+    contents: &[u8],
+    mode: u32,
+) -> Result<(tempdir::TempDir, std::path::PathBuf)> {
+    let dir = tempdir::TempDir::new(prefix)?;
+    let path = dir.path().join(filename);
+    let mut options = fs_err::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use fs_err::os::unix::fs::OpenOptionsExt;
+        use std::os::unix::fs::PermissionsExt;
+        fs_err::set_permissions(dir.path(), std::fs::Permissions::from_mode(0o700))?;
+        options.mode(mode);
+    }
+    #[cfg(not(unix))]
+    let _ = mode;
+    options.open(&path)?.write_all(contents)?;
+    Ok((dir, path))
+}
+
+/// The stashes in the trash, most recently removed first, optionally only those
+/// of `id`'s name. Their indices count the removed stashes of each name.
+fn trashed(
+    store: &dyn StorageBackend,
+    id: Option<&StashId>,
+) -> Result<Vec<(Data, std::time::SystemTime)>> {
+    let mut trashed = store.trashed()?;
+    if let Some(id) = id {
+        trashed.retain(|(stash, _)| stash.name == id.name);
+    }
+    trashed.sort_by_key(|(_, removed)| std::cmp::Reverse(*removed));
+    let mut next_index = std::collections::HashMap::new();
+    for (stash, _) in &mut trashed {
+        let index = next_index.entry(stash.name.clone()).or_insert(0);
+        stash.index = *index;
+        *index += 1;
+    }
+    Ok(trashed)
+}
+
+fn rename_stash(store: &dyn StorageBackend, stash: &Data, new_name: &str) -> Result<()> {
+    ensure_unlocked(stash)?;
+    let seq = store.rename(stash, new_name)?.seq;
+    // the stash's index under its new name
+    let renamed = Data::stack(store, new_name)?
+        .into_iter()
+        .find(|stash| stash.seq == seq);
+    events::record(store, events::Kind::Rename, renamed.as_ref())
+}
+
+/// The code of a sender found by `receive --discover`, chosen in a terminal, or
+/// `None` after listing them elsewhere.
+fn pick_sender() -> Result<Option<String>> {
+    let mut senders = wormhole::discover()?;
+    if senders.is_empty() {
+        return Err(eyre!("Found no `stash send` on the local network"));
+    }
+    let descriptions: Vec<_> = senders
+        .iter()
+        .map(|sender| {
+            format!(
+                "{} ({}) from {}",
+                sender.id,
+                human_size(sender.size),
+                sender.host
+            )
+        })
+        .collect();
+    if !std::io::stdin().is_terminal() {
+        for (sender, description) in senders.iter().zip(descriptions) {
+            println!("{}\t{description}", sender.code);
+        }
+        return Ok(None);
+    }
+    let selection = dialoguer::Select::new()
+        .with_prompt("Receive")
+        .items(&descriptions)
+        .default(0)
+        .interact_opt()?;
+    Ok(selection.map(|selection| senders.swap_remove(selection).code))
+}
+
+fn delete_stash(store: &dyn StorageBackend, stash: Option<Data>) -> Result<()> {
+    if let Some(stash) = stash {
+        ensure_unlocked(&stash)?;
+        store.remove(&stash)?;
+        events::record(store, events::Kind::Drop, Some(&stash))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("0s"), Ok(Duration::ZERO));
+        assert_eq!(parse_duration("90s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("30m"), Ok(Duration::from_secs(1800)));
+        assert_eq!(parse_duration("12h"), Ok(Duration::from_secs(43200)));
+        assert_eq!(parse_duration("7d"), Ok(Duration::from_secs(604800)));
+        assert_eq!(parse_duration("2w"), Ok(Duration::from_secs(1209600)));
+    }
+
+    #[test]
+    fn invalid_durations() {
+        for input in ["", "7", "d", "7 d", "-7d", "1.5h", "7y", "7dd", "7D"] {
+            assert!(parse_duration(input).is_err(), "{input:?}");
+        }
+        assert!(parse_duration(&format!("{}s", u64::MAX)).is_ok());
+        assert!(parse_duration(&format!("{}m", u64::MAX)).is_err());
+        assert!(parse_duration("99999999999999999999s").is_err());
+    }
+
+    #[test]
+    fn ages_before_the_epoch() {
+        assert!(parse_time("1h").is_ok());
+        assert!(parse_time(&format!("{}s", u64::MAX)).is_err());
+    }
+
+    #[test]
+    fn expiries_too_far_in_the_future() {
+        assert!(expiry(Some(Duration::from_secs(60)), None).is_ok());
+        assert!(expiry(Some(Duration::MAX), None).is_err());
+        assert!(expiry(None, Some("1000000w")).is_err());
+    }
+
+    #[test]
+    fn sizes() {
+        assert_eq!(parse_size("0"), Ok(0));
+        assert_eq!(parse_size("512"), Ok(512));
+        assert_eq!(parse_size("512B"), Ok(512));
+        assert_eq!(parse_size("10K"), Ok(10240));
+        assert_eq!(parse_size("10k"), Ok(10240));
+        assert_eq!(parse_size("10KB"), Ok(10240));
+        assert_eq!(parse_size("10KiB"), Ok(10240));
+        assert_eq!(parse_size("2M"), Ok(2 << 20));
+        assert_eq!(parse_size("1G"), Ok(1 << 30));
+    }
+
+    #[test]
+    fn invalid_sizes() {
+        for input in ["", "K", "10 K", "-1", "1.5M", "10T", "10m", "10KK"] {
+            assert!(parse_size(input).is_err(), "{input:?}");
+        }
+        assert_eq!(parse_size(&u64::MAX.to_string()), Ok(u64::MAX));
+        assert!(parse_size(&format!("{}K", u64::MAX)).is_err());
+    }
+
+    #[test]
+    fn byte_ranges() {
+        assert_eq!(parse_byte_range("4K..8K"), Ok((4096, Some(8192))));
+        assert_eq!(parse_byte_range("..100"), Ok((0, Some(100))));
+        assert_eq!(parse_byte_range("1M.."), Ok((1 << 20, None)));
+        assert_eq!(parse_byte_range(".."), Ok((0, None)));
+        assert_eq!(parse_byte_range("5..5"), Ok((5, Some(5))));
+    }
+
+    #[test]
+    fn invalid_byte_ranges() {
+        for input in ["", "100", "4K-8K", "8K..4K", "1..x", "x..1", "1...2"] {
+            assert!(parse_byte_range(input).is_err(), "{input:?}");
+        }
+    }
+}
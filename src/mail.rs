@@ -0,0 +1,71 @@
+use std::io::Read;
+
+use eyre::{eyre, Result};
+use lettre::message::header::{ContentTransferEncoding, ContentType};
+use lettre::message::{Attachment, Body, Mailbox, MultiPart, SinglePart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{Message, SendmailTransport, SmtpTransport, Transport};
+
+use crate::config;
+use crate::storage::{Data, StorageBackend};
+
+/// Mails the stash to `to`, inline if it's text and as an attachment otherwise.
+pub fn send(
+    store: &dyn StorageBackend,
+    config: &config::Mail,
+    stash: &Data,
+    from: &str,
+    to: &str,
+    subject: Option<String>,
+) -> Result<()> {
+    let mut contents = vec![];
+    store.reader(stash)?.read_to_end(&mut contents)?;
+
+    let builder = Message::builder()
+        .from(parse_address(from)?)
+        .to(parse_address(to)?)
+        .subject(subject.unwrap_or_else(|| format!("stash {}", stash.id())));
+    let message = match String::from_utf8(contents) {
+        Ok(text) if !text.contains('\0') => builder.singlepart(SinglePart::plain(text))?,
+        contents => {
+            let contents = contents.map_or_else(|e| e.into_bytes(), String::into_bytes);
+            let filename = store
+                .meta(stash)?
+                .filename
+                .unwrap_or_else(|| format!("{}.bin", stash.id()).replace('/', "_"));
+            // the automatically chosen encoding doesn't always survive binary data
+            let body = Body::new_with_encoding(contents, ContentTransferEncoding::Base64)
+                .map_err(|_| eyre!("couldn't encode attachment"))?;
+            let attachment = Attachment::new(filename)
+                .body(body, ContentType::parse("application/octet-stream")?);
+            builder.multipart(
+                MultiPart::mixed()
+                    .singlepart(SinglePart::plain(format!(
+                        "stash {} is attached.",
+                        stash.id()
+                    )))
+                    .singlepart(attachment),
+            )?
+        }
+    };
+
+    match &config.smtp_host {
+        Some(host) => {
+            let mut transport = SmtpTransport::relay(host)?;
+            if let (Some(user), Some(password)) = (&config.smtp_user, &config.smtp_password) {
+                transport = transport.credentials(Credentials::new(user.clone(), password.clone()));
+            }
+            transport.build().send(&message)?;
+        }
+        None => {
+            SendmailTransport::new().send(&message)?;
+        }
+    }
+    Ok(())
+}
+
+fn parse_address(address: &str) -> Result<Mailbox> {
+    address
+        .parse()
+        .map_err(|e| eyre!("invalid email address {address}: {e}"))
+}
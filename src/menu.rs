@@ -0,0 +1,102 @@
+use std::io::Write;
+
+use base64::Engine;
+use dialoguer::console::{Key, Term};
+use dialoguer::{FuzzySelect, Select};
+use eyre::Result;
+
+use crate::storage::{Data, StorageBackend};
+use crate::ShowOptions;
+
+/// How many of the most recent stashes the menu offers.
+const RECENT: usize = 20;
+
+/// Lets the user pick one of the recent stashes and act on it with a single keypress.
+/// Secret ones are only copied to the clipboard with `force`.
+pub fn run(store: &dyn StorageBackend, force: bool) -> Result<()> {
+    let stashes = Data::detect(store)?
+        .into_iter()
+        .take(RECENT)
+        .collect::<Vec<_>>();
+    if stashes.is_empty() {
+        eprintln!("No stashes");
+        return Ok(());
+    }
+    let Some(selection) = Select::new()
+        .with_prompt("Stash")
+        .items(&describe(store, &stashes)?)
+        .default(0)
+        .interact_opt()?
+    else {
+        return Ok(());
+    };
+    let stash = &stashes[selection];
+
+    let term = Term::stderr();
+    term.write_line("[s]how  [p]op  [c]opy to clipboard  [d]rop  [q]uit")?;
+    loop {
+        match term.read_key()? {
+            Key::Char('s') => {
+                crate::print_stash(store, stash, &ShowOptions::default())?;
+            }
+            Key::Char('p') => {
+                crate::ensure_unlocked(stash)?;
+                crate::print_stash(store, stash, &ShowOptions::default())?;
+                store.remove(stash)?;
+                crate::events::record(store, crate::events::Kind::Pop, Some(stash))?;
+            }
+            Key::Char('c') => {
+                crate::ensure_shareable(store, stash, force)?;
+                copy_to_clipboard(store, stash)?
+            }
+            Key::Char('d') => crate::delete_stash(store, Some(stash.clone()))?,
+            Key::Char('q') | Key::Escape => {}
+            _ => continue,
+        }
+        return Ok(());
+    }
+}
+
+/// Lets the user pick any stash by typing part of its id, age or first line.
+pub fn pick(store: &dyn StorageBackend) -> Result<Option<Data>> {
+    let mut stashes = Data::detect(store)?;
+    if stashes.is_empty() {
+        return Ok(None);
+    }
+    let selection = FuzzySelect::new()
+        .with_prompt("Stash")
+        .items(&describe(store, &stashes)?)
+        .default(0)
+        .interact_opt()?;
+    Ok(selection.map(|selection| stashes.swap_remove(selection)))
+}
+
+/// A line per stash with its id, age and the beginning of its contents.
+fn describe(store: &dyn StorageBackend, stashes: &[Data]) -> Result<Vec<String>> {
+    stashes
+        .iter()
+        .map(|stash| {
+            Ok(format!(
+                "{}  {}  {}",
+                stash.id(),
+                crate::format_age(stash.created),
+                crate::first_line(store, stash)?
+            ))
+        })
+        .collect()
+}
+
+/// Copies to the system clipboard, or through the terminal with an OSC 52 escape
+/// sequence where there is none, e.g. over ssh, if the terminal supports it.
+fn copy_to_clipboard(store: &dyn StorageBackend, stash: &Data) -> Result<()> {
+    let mut contents = vec![];
+    std::io::copy(&mut store.reader(stash)?, &mut contents)?;
+    if crate::clipboard::write(contents.clone()).is_ok() {
+        return Ok(());
+    }
+    let encoded = base64::engine::general_purpose::STANDARD.encode(contents);
+    let mut term = Term::stderr();
+    write!(term, "\x1b]52;c;{encoded}\x07")?;
+    term.flush()?;
+    Ok(())
+}
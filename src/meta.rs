@@ -0,0 +1,179 @@
+use std::path::Path;
+use std::time::SystemTime;
+
+use eyre::{eyre, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::archive::Archive;
+use crate::compression::{Encoding, Format};
+
+/// Everything known about a stash besides its contents.
+/// Kept next to the stash by the storage backend.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Meta {
+    /// A short description given with `push -m`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    /// Sorted, without duplicates
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<Provenance>,
+    /// Name of the file the contents were read from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filename: Option<String>,
+    /// Language of the contents for syntax highlighting, given with `push --lang`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lang: Option<String>,
+    /// Absolute path of the file the contents were read from, `pop --restore` puts them back there
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub origin: Option<String>,
+    /// Added with `annotate`, oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub notes: Vec<Note>,
+    /// Where the stash was uploaded to with `publish`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub published: Vec<String>,
+    /// Where the contents were downloaded from with `fetch`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub download: Option<Download>,
+    /// Set if the contents are an archive of a directory rather than a file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub archive: Option<Archive>,
+    /// Set if the contents are stored compressed, given with `push --compress`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<Encoding>,
+    /// Set if the contents were compressed already when pushed, detected by `push`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format: Option<Format>,
+    /// Set if the contents are stored encrypted with a passphrase, given with `push --encrypt`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub encrypted: bool,
+    /// Set if only the user may read the stash and it's only shown on a terminal,
+    /// given with `push --secret`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub secret: bool,
+    /// When the stash is deleted, given with `push --expire`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires: Option<String>,
+    /// Of the contents, hex encoded, to tell if they've been corrupted or truncated
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// Length of the contents, set by the storage backend if they are stored
+    /// encoded, which takes a different number of bytes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// Set if the contents are a patch of a git repo's changes, pushed with `git push`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git: Option<Git>,
+}
+
+impl Meta {
+    /// Whether the contents are stored as something other than themselves.
+    pub fn encoded(&self) -> bool {
+        self.encoding.is_some() || self.encrypted
+    }
+
+    pub fn expiry(&self) -> Option<SystemTime> {
+        self.expires
+            .as_deref()
+            .and_then(|expires| crate::parse_time(expires).ok())
+    }
+
+    /// Adds tags given as `+tag` or `tag` and removes those given as `-tag`.
+    pub fn change_tags(&mut self, changes: &[impl AsRef<str>]) -> Result<()> {
+        for change in changes {
+            let change = change.as_ref();
+            match change.strip_prefix('-') {
+                Some(tag) => self.tags.retain(|existing| existing != tag),
+                None => {
+                    let tag = change.strip_prefix('+').unwrap_or(change);
+                    if tag.is_empty() {
+                        return Err(eyre!("empty tag in {change:?}"));
+                    }
+                    self.tags.push(tag.to_owned());
+                }
+            }
+        }
+        self.tags = normalized_tags(std::mem::take(&mut self.tags));
+        Ok(())
+    }
+
+    /// Metadata for a stash pushed right now by this process.
+    pub fn captured() -> Meta {
+        Meta {
+            provenance: Some(Provenance::current()),
+            ..Meta::default()
+        }
+    }
+}
+
+pub fn normalized_tags(mut tags: Vec<String>) -> Vec<String> {
+    tags.sort();
+    tags.dedup();
+    tags
+}
+
+/// A timestamped comment on a stash.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Note {
+    pub time: String,
+    pub text: String,
+}
+
+/// The response a fetched stash came from.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Download {
+    pub url: String,
+    pub etag: Option<String>,
+    pub content_type: Option<String>,
+}
+
+/// The repo a patch was taken from.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Git {
+    /// Path of the repo's root
+    pub repo: String,
+    /// `None` on a detached HEAD
+    pub branch: Option<String>,
+    /// The commit the patch is against
+    pub commit: String,
+}
+
+/// Where a stash was captured.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Provenance {
+    pub host: String,
+    pub user: Option<String>,
+    pub cwd: Option<String>,
+    /// Whether stdin was a terminal rather than a pipe or file
+    pub stdin_tty: bool,
+}
+
+impl Provenance {
+    pub fn current() -> Provenance {
+        use std::io::IsTerminal;
+
+        Provenance {
+            host: gethostname::gethostname().to_string_lossy().into_owned(),
+            user: std::env::var("USER")
+                .or_else(|_| std::env::var("USERNAME"))
+                .ok(),
+            cwd: std::env::current_dir()
+                .ok()
+                .map(|dir| dir.to_string_lossy().into_owned()),
+            stdin_tty: std::io::stdin().is_terminal(),
+        }
+    }
+
+    /// Whether the stash was pushed on `host` and from within `cwd`, if given.
+    pub fn matches(&self, host: Option<&str>, cwd: Option<&Path>) -> bool {
+        let on_host = host.is_none_or(|host| host == self.host);
+        let in_dir = cwd.is_none_or(|cwd| {
+            self.cwd
+                .as_ref()
+                .is_some_and(|dir| Path::new(dir).starts_with(cwd))
+        });
+        on_host && in_dir
+    }
+}
@@ -0,0 +1,82 @@
+//! Export and import of stashes as newline-delimited json, one stash per line.
+
+use std::io::{BufRead, Write};
+
+use base64::Engine;
+use eyre::{eyre, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::meta::Meta;
+use crate::storage::{Data, StorageBackend};
+
+#[derive(Serialize, Deserialize)]
+struct Record {
+    name: String,
+    /// Informational only, the index is determined by the order of the lines on import
+    #[serde(default)]
+    index: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    created: Option<String>,
+    #[serde(default)]
+    size: u64,
+    #[serde(default)]
+    locked: bool,
+    #[serde(default)]
+    meta: Meta,
+    /// base64 encoded
+    content: String,
+}
+
+/// Writes the stashes of the given names (or all of them) oldest first,
+/// so an import rebuilds every stack in the same order. Secret ones are left out
+/// without `force`.
+pub fn export(
+    store: &dyn StorageBackend,
+    names: &[String],
+    force: bool,
+    out: &mut dyn Write,
+) -> Result<()> {
+    let selected = Data::detect(store)?
+        .into_iter()
+        .rev()
+        .filter(|stash| names.is_empty() || names.contains(&stash.name))
+        .collect();
+    let selected = crate::without_withheld(store, selected, force)?;
+    for stash in &selected {
+        let mut content = vec![];
+        std::io::copy(&mut store.reader(stash)?, &mut content)?;
+        let record = Record {
+            name: stash.name.clone(),
+            index: stash.index,
+            created: Some(crate::format_time(stash.created)?),
+            size: stash.size,
+            locked: stash.locked,
+            meta: store.meta(stash)?,
+            content: base64::engine::general_purpose::STANDARD.encode(content),
+        };
+        serde_json::to_writer(&mut *out, &record)?;
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+/// Pushes one stash per line of `input`, in order.
+/// Lines are imported as they are read, so a failure leaves the earlier ones in place.
+pub fn import(store: &dyn StorageBackend, input: impl BufRead) -> Result<()> {
+    for (number, line) in input.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record: Record =
+            serde_json::from_str(&line).map_err(|e| eyre!("line {}: {e}", number + 1))?;
+        let content = base64::engine::general_purpose::STANDARD
+            .decode(&record.content)
+            .map_err(|e| eyre!("line {}: {e}", number + 1))?;
+        let stash = crate::push(store, &record.name, &record.meta, &mut content.as_slice())?;
+        if record.locked {
+            store.set_locked(&stash, true)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,68 @@
+use std::io::Write;
+use std::process::{Child, ChildStdin, Command, Stdio};
+
+use eyre::Result;
+
+use crate::interrupt::PagerRunning;
+
+/// Output going through the user's pager, like git does it.
+/// Dropping it waits for the pager to be closed.
+pub struct Pager {
+    child: Child,
+    stdin: Option<ChildStdin>,
+    // dropped after waiting for the pager
+    _running: PagerRunning,
+}
+
+impl Pager {
+    /// Starts `$PAGER`, or `less -FRX` if it isn't set, so short output is printed
+    /// as is. Returns `None` if `$PAGER` is empty or `cat`, or if the pager
+    /// can't be started.
+    pub fn spawn() -> Result<Option<Pager>> {
+        let pager = std::env::var("PAGER").unwrap_or_else(|_| "less -FRX".to_owned());
+        if pager.trim().is_empty() || pager.trim() == "cat" {
+            return Ok(None);
+        }
+        // through the shell, so that $PAGER can contain arguments
+        let child = Command::new("sh")
+            .args(["-c", &pager])
+            .stdin(Stdio::piped())
+            .spawn();
+        let Ok(mut child) = child else {
+            return Ok(None);
+        };
+        let stdin = child.stdin.take();
+        Ok(Some(Pager {
+            child,
+            stdin,
+            _running: PagerRunning::new(),
+        }))
+    }
+}
+
+impl Write for Pager {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let stdin = self.stdin.as_mut().unwrap();
+        match stdin.write(buf) {
+            // the pager was quit before reading everything
+            Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => Ok(buf.len()),
+            result => result,
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        let stdin = self.stdin.as_mut().unwrap();
+        match stdin.flush() {
+            Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => Ok(()),
+            result => result,
+        }
+    }
+}
+
+impl Drop for Pager {
+    fn drop(&mut self) {
+        // closing its stdin lets the pager know that everything has been written
+        self.stdin = None;
+        let _ = self.child.wait();
+    }
+}
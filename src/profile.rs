@@ -0,0 +1,89 @@
+//! Profiles keep stashes apart in stores of their own, e.g. for work and personal
+//! ones. The default profile is the store as it is without profiles, the others
+//! are in `profiles/<name>` of the data dir or below `profiles/<name>/` of the s3
+//! prefix.
+
+use camino::{Utf8Path as Path, Utf8PathBuf as PathBuf};
+use eyre::{eyre, Result};
+use lazy_regex::regex;
+
+use crate::config::{self, Backend};
+use crate::storage::{Data, LocalFs, StorageBackend};
+
+pub const DEFAULT: &str = "default";
+
+/// Where the stores of all profiles are.
+pub struct Stores<'a> {
+    pub backend: Backend,
+    /// Of the default profile
+    pub data_dir: &'a Path,
+    pub s3: &'a config::S3,
+}
+
+impl Stores<'_> {
+    /// The data dir of the profile, where the local backend keeps its stashes.
+    pub fn data_dir(&self, profile: &str) -> Result<PathBuf> {
+        check_name(profile)?;
+        Ok(match profile {
+            DEFAULT => self.data_dir.to_owned(),
+            _ => self.data_dir.join("profiles").join(profile),
+        })
+    }
+
+    pub fn open(&self, profile: &str) -> Result<Box<dyn StorageBackend>> {
+        check_name(profile)?;
+        Ok(match self.backend {
+            Backend::Local => Box::new(LocalFs::new(self.data_dir(profile)?)?),
+            Backend::S3 => {
+                let bucket = crate::s3::S3Bucket::new(self.s3)?;
+                match profile {
+                    DEFAULT => Box::new(bucket),
+                    _ => Box::new(bucket.for_profile(profile)),
+                }
+            }
+        })
+    }
+
+    /// The default profile and those that have been pushed to, sorted.
+    pub fn list(&self) -> Result<Vec<String>> {
+        let mut profiles = match self.backend {
+            Backend::Local => {
+                let dir = self.data_dir.join("profiles");
+                match fs_err::read_dir(&dir) {
+                    Ok(entries) => entries
+                        .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+                        .collect::<Result<Vec<_>>>()?,
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => vec![],
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            Backend::S3 => crate::s3::S3Bucket::new(self.s3)?.profiles()?,
+        };
+        profiles.push(DEFAULT.to_owned());
+        profiles.sort();
+        Ok(profiles)
+    }
+}
+
+/// Moves a stash to the top of its stack in another profile's store. Like `drop`,
+/// this leaves it in the trash of `from`.
+pub fn move_stash(from: &dyn StorageBackend, stash: Data, to: &dyn StorageBackend) -> Result<Data> {
+    crate::ensure_unlocked(&stash)?;
+    let moved = crate::push(
+        to,
+        &stash.name,
+        &from.meta(&stash)?,
+        &mut from.reader(&stash)?,
+    )?;
+    crate::delete_stash(from, Some(stash))?;
+    Ok(moved)
+}
+
+fn check_name(profile: &str) -> Result<()> {
+    match regex!("^[A-Za-z0-9_-]+$").is_match(profile) {
+        true => Ok(()),
+        false => Err(eyre!(
+            "invalid profile name {profile:?}, it may only contain letters, digits, `-` and `_`"
+        )),
+    }
+}
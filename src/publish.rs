@@ -0,0 +1,96 @@
+use std::io::Read;
+
+use eyre::{eyre, Result};
+use serde::Deserialize;
+
+use crate::storage::{encode_name, Data, StorageBackend};
+
+/// Paste services `publish` can upload to.
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub enum Service {
+    #[serde(rename = "0x0.st")]
+    NullPointer,
+    #[serde(rename = "paste.rs")]
+    PasteRs,
+    /// A secret GitHub gist, needs `publish.github_token`
+    #[serde(rename = "gist")]
+    Gist,
+}
+
+impl std::str::FromStr for Service {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "0x0.st" => Ok(Service::NullPointer),
+            "paste.rs" => Ok(Service::PasteRs),
+            "gist" => Ok(Service::Gist),
+            _ => Err(format!(
+                "unknown paste service {s}, expected 0x0.st, paste.rs or gist"
+            )),
+        }
+    }
+}
+
+/// Uploads the stash and returns the URL it can be found at.
+pub fn publish(
+    store: &dyn StorageBackend,
+    stash: &Data,
+    service: Service,
+    github_token: Option<&str>,
+) -> Result<String> {
+    let mut contents = vec![];
+    store.reader(stash)?.read_to_end(&mut contents)?;
+    let filename = store.meta(stash)?.filename.unwrap_or_else(|| {
+        format!("{}.txt", encode_name(&stash.id().to_string())).replace(':', "_")
+    });
+
+    let url = match service {
+        Service::NullPointer => {
+            let boundary = "stash-publish-boundary";
+            // a quote would end the filename early
+            let filename = filename.replace('"', "%22");
+            let mut body = format!(
+                "--{boundary}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{filename}\"\r\n\
+                 Content-Type: application/octet-stream\r\n\r\n"
+            )
+            .into_bytes();
+            body.extend_from_slice(&contents);
+            body.extend_from_slice(format!("\r\n--{boundary}--\r\n").as_bytes());
+            ureq::post("https://0x0.st")
+                .header(
+                    "Content-Type",
+                    format!("multipart/form-data; boundary={boundary}"),
+                )
+                .send(&body)?
+                .body_mut()
+                .read_to_string()?
+        }
+        Service::PasteRs => ureq::post("https://paste.rs")
+            .send(&contents)?
+            .body_mut()
+            .read_to_string()?,
+        Service::Gist => {
+            let token = github_token.ok_or_else(|| {
+                eyre!("publishing to gist needs publish.github_token in the config")
+            })?;
+            let text = String::from_utf8(contents)
+                .map_err(|_| eyre!("only text stashes can be published as a gist"))?;
+            let gist = serde_json::json!({
+                "files": { filename: { "content": text } },
+                "public": false,
+            });
+            let response: serde_json::Value = ureq::post("https://api.github.com/gists")
+                .header("Authorization", format!("Bearer {token}"))
+                .header("Accept", "application/vnd.github+json")
+                .send_json(gist)?
+                .body_mut()
+                .read_json()?;
+            response["html_url"]
+                .as_str()
+                .ok_or_else(|| eyre!("unexpected response from GitHub: {response}"))?
+                .to_owned()
+        }
+    };
+    Ok(url.trim().to_owned())
+}
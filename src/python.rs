@@ -0,0 +1,105 @@
+//! The `stash_py` Python module of the `python` feature, for scripts that would
+//! rather not run `stash` for every call. `pip install .` builds it with maturin,
+//! see `pyproject.toml`.
+//!
+//! ```python
+//! import stash_py
+//!
+//! store = stash_py.Store()  # or Store("/some/data/dir")
+//! store.push("logs", b"...")  # -> "logs:0"
+//! store.list()  # -> [{"id": "logs:0", "created": datetime(...), ...}]
+//! store.search("error")  # the stashes a line of matches
+//! store.pop("logs")  # -> b"...", None if there's no such stash
+//! ```
+
+use std::time::SystemTime;
+
+use pyo3::prelude::*;
+
+use crate::embed::Embedded;
+use crate::storage::Data;
+
+pyo3::create_exception!(stash_py, StashError, pyo3::exceptions::PyException);
+
+fn error(report: eyre::Report) -> PyErr {
+    StashError::new_err(report.to_string())
+}
+
+/// A stash as `list` and `search` return it, a dict in Python.
+#[derive(IntoPyObject)]
+struct Stash {
+    id: String,
+    name: String,
+    index: usize,
+    size: u64,
+    created: SystemTime,
+    locked: bool,
+}
+
+impl Stash {
+    fn new(stash: Data) -> Stash {
+        Stash {
+            id: stash.id().to_string(),
+            name: stash.name,
+            index: stash.index,
+            size: stash.size,
+            created: stash.created,
+            locked: stash.locked,
+        }
+    }
+}
+
+#[derive(FromPyObject)]
+enum Contents {
+    Bytes(Vec<u8>),
+    Text(String),
+}
+
+/// The local store in `data_dir` if given, else the one `stash` uses by default.
+#[pyclass(module = "stash_py")]
+struct Store(Embedded);
+
+#[pymethods]
+impl Store {
+    #[new]
+    #[pyo3(signature = (data_dir=None))]
+    fn new(data_dir: Option<&str>) -> PyResult<Store> {
+        Ok(Store(Embedded::open(data_dir).map_err(error)?))
+    }
+
+    /// Pushes `contents`, bytes or str, as the newest stash of `name` and
+    /// returns its id. `""` is the unnamed stack.
+    fn push(&self, py: Python<'_>, name: &str, contents: Contents) -> PyResult<String> {
+        let contents = match contents {
+            Contents::Bytes(bytes) => bytes,
+            Contents::Text(text) => text.into_bytes(),
+        };
+        py.detach(|| self.0.push(name, &contents)).map_err(error)
+    }
+
+    /// Removes the stash with the id, like `logs:1` or `logs`, and returns its
+    /// contents, `None` if it doesn't exist.
+    fn pop(&self, py: Python<'_>, id: &str) -> PyResult<Option<Vec<u8>>> {
+        py.detach(|| self.0.pop(id)).map_err(error)
+    }
+
+    /// All stashes, newest first.
+    fn list(&self, py: Python<'_>) -> PyResult<Vec<Stash>> {
+        let stashes = py.detach(|| self.0.list()).map_err(error)?;
+        Ok(stashes.into_iter().map(Stash::new).collect())
+    }
+
+    /// The stashes with a line matching the regex, newest first. Secret and
+    /// encrypted stashes never match.
+    fn search(&self, py: Python<'_>, regex: &str) -> PyResult<Vec<Stash>> {
+        let stashes = py.detach(|| self.0.search(regex)).map_err(error)?;
+        Ok(stashes.into_iter().map(Stash::new).collect())
+    }
+}
+
+#[pymodule]
+fn stash_py(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<Store>()?;
+    module.add("StashError", module.py().get_type::<StashError>())?;
+    Ok(())
+}
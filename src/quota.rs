@@ -0,0 +1,101 @@
+use eyre::{eyre, Result};
+
+use crate::config::{Config, Quota};
+use crate::storage::{Data, StorageBackend};
+
+/// Fails if pushing `size` bytes to the stack `name` would exceed its quota, with
+/// `max_history` in place of its `max_count`, or the total quota. For
+/// `push --strict`, before anything is written.
+pub fn check(
+    store: &dyn StorageBackend,
+    config: &Config,
+    name: &str,
+    max_history: Option<usize>,
+    size: u64,
+) -> Result<()> {
+    let quota = quota_of(config, name, max_history);
+    let pushing = |stashes: Vec<Data>| {
+        (
+            stashes.len() + 1,
+            stashes.iter().map(|stash| stash.size).sum::<u64>() + size,
+        )
+    };
+    if !quota.is_empty() && exceeded(&quota, pushing(Data::stack(store, name)?)) {
+        return Err(eyre!("pushing would exceed the quota of {name:?}"));
+    }
+    if !config.total_quota.is_empty()
+        && exceeded(&config.total_quota, pushing(Data::detect(store)?))
+    {
+        return Err(eyre!("pushing would exceed the total quota"));
+    }
+    Ok(())
+}
+
+/// Brings the stack `pushed` was just pushed to back within its quota, with
+/// `max_history` in place of its `max_count`, and then the whole store back within
+/// the total quota. The oldest unlocked stashes are deleted for good to do so,
+/// without going through the trash.
+pub fn enforce(
+    store: &dyn StorageBackend,
+    config: &Config,
+    pushed: &Data,
+    max_history: Option<usize>,
+) -> Result<()> {
+    let quota = quota_of(config, &pushed.name, max_history);
+    if !quota.is_empty() {
+        let scope = format!("the quota of {:?}", pushed.name);
+        let stack = Data::stack(store, &pushed.name)?;
+        evict(store, &quota, stack, pushed, &scope)?;
+    }
+    if !config.total_quota.is_empty() {
+        let stashes = Data::detect(store)?;
+        evict(
+            store,
+            &config.total_quota,
+            stashes,
+            pushed,
+            "the total quota",
+        )?;
+    }
+    Ok(())
+}
+
+fn quota_of(config: &Config, name: &str, max_history: Option<usize>) -> Quota {
+    let mut quota = config.quota.get(name).cloned().unwrap_or_default();
+    if max_history.is_some() {
+        quota.max_count = max_history;
+    }
+    quota
+}
+
+/// Whether a number of stashes with a total size exceed `quota`.
+fn exceeded(quota: &Quota, (count, size): (usize, u64)) -> bool {
+    quota.max_count.is_some_and(|max| count > max) || quota.max_size.is_some_and(|max| size > max)
+}
+
+/// Evicts from `stashes`, in `Data::detect` order, until they're within `quota`.
+fn evict(
+    store: &dyn StorageBackend,
+    quota: &Quota,
+    mut stashes: Vec<Data>,
+    pushed: &Data,
+    scope: &str,
+) -> Result<()> {
+    let usage = |stashes: &[Data]| (stashes.len(), stashes.iter().map(|stash| stash.size).sum());
+    // newest first, so the oldest are at the end
+    while exceeded(quota, usage(&stashes)) {
+        let Some(oldest) = stashes
+            .iter()
+            .rposition(|stash| !stash.locked && stash.seq != pushed.seq)
+        else {
+            eprintln!("Only locked stashes left to evict, staying over {scope}");
+            break;
+        };
+        let evicted = stashes.remove(oldest);
+        store.remove(&evicted)?;
+        store.purge(&evicted)?;
+        crate::events::record(store, crate::events::Kind::Evict, Some(&evicted))?;
+        eprintln!("Evicted {} to stay within {scope}", evicted.id());
+    }
+    Ok(())
+}
@@ -0,0 +1,163 @@
+//! `--remote`: running the basic subcommands against `stash serve` on another
+//! machine instead of the local store, through its REST API.
+
+use std::io::Write;
+
+use eyre::{eyre, Result};
+
+use crate::config::Config;
+use crate::stash_client::{Client, Query, Summary};
+use crate::storage::Data;
+use crate::{ListFilter, ListFormat, ShowOptions, Subcommand, TimeFormat};
+
+pub fn run(client: &Client, config: &Config, command: Subcommand) -> Result<()> {
+    match command {
+        Subcommand::List {
+            tree,
+            filter,
+            mut order,
+            null,
+            time,
+            format,
+        } => {
+            order.sort = order.sort.or(config.list.sort);
+            let time = time.or(config.list.time).unwrap_or(TimeFormat::Relative);
+            let configured_format = config.list.format.filter(|_| !tree && !null);
+            let format = format.or(configured_format).unwrap_or(ListFormat::Text);
+            let summaries = client.list(&query(&filter)?)?;
+            if let ListFormat::Json = format {
+                println!("{}", serde_json::to_string_pretty(&summaries)?);
+                return Ok(());
+            }
+            let stashes = summaries.iter().map(data).collect::<Result<Vec<_>>>()?;
+            match (tree, null) {
+                (true, _) => crate::tree::print(&stashes),
+                (false, true) => {
+                    let mut stdout = std::io::stdout().lock();
+                    for stash in order.apply(stashes) {
+                        write!(stdout, "{}\0", stash.id())?;
+                    }
+                }
+                (false, false) => {
+                    let stashes = order.apply(stashes);
+                    for line in crate::list_rows(&stashes, time)? {
+                        println!("{line}");
+                    }
+                }
+            }
+        }
+        Subcommand::Show {
+            id,
+            interactive: false,
+            delete,
+            mut options,
+            window,
+            meta: false,
+            notes: false,
+        } if as_stored(&options) => {
+            options.window = window;
+            let version = show(client, &id.to_string(), &options)?;
+            if delete {
+                client.delete(&id.to_string(), version.as_deref())?;
+            }
+        }
+        Subcommand::Pop {
+            id,
+            interactive: false,
+            all: false,
+            restore: false,
+            options,
+            ..
+        } if as_stored(&options) => {
+            let version = show(client, &id.to_string(), &options)?;
+            client.delete(&id.to_string(), version.as_deref())?;
+        }
+        Subcommand::Drop {
+            id: Some(id),
+            stdin: false,
+            keep_last: None,
+            ..
+        } => client.delete(&id.to_string(), None)?,
+        Subcommand::Push {
+            name,
+            append: false,
+            strict: false,
+            max_history: None,
+            files,
+            message: None,
+            tags,
+            move_files: false,
+            lang: None,
+            compress: false,
+            uncompressed: false,
+            encrypt: false,
+            secret: false,
+            expire: None,
+            clipboard: false,
+            tmux: None,
+        } if files.is_empty() && tags.is_empty() => {
+            let id = client.push(&name, &mut std::io::stdin().lock())?;
+            eprintln!("Stashed as {id}");
+        }
+        _ => {
+            return Err(eyre!(
+                "--remote only lists, shows, pops and drops stashes and pushes stdin, \
+                 without any options that need the store itself"
+            ))
+        }
+    }
+    Ok(())
+}
+
+/// Returns the version of the stash that was shown.
+fn show(client: &Client, id: &str, options: &ShowOptions) -> Result<Option<String>> {
+    let mut out = options.open_output()?;
+    let contents = client.get(id)?;
+    let version = contents.version.clone();
+    std::io::copy(&mut options.window.apply(Box::new(contents))?, &mut out)?;
+    out.flush()?;
+    Ok(version)
+}
+
+/// Whether the options leave the contents as they are, which is all the server
+/// hands out.
+fn as_stored(options: &ShowOptions) -> bool {
+    !(options.decompress
+        || options.unique_lines
+        || options.sort_lines
+        || options.render
+        || options.clipboard
+        || options.tmux.is_some()
+        || options.hex
+        || options.raw
+        || options.verify)
+}
+
+fn query(filter: &ListFilter) -> Result<Query> {
+    if filter.host.is_some() || filter.cwd.is_some() {
+        return Err(eyre!("--host and --cwd don't work with --remote"));
+    }
+    Ok(Query {
+        name: filter.pattern.as_ref().map(|pattern| pattern.to_string()),
+        regex: filter.regex.as_ref().map(|regex| regex.to_string()),
+        since: filter.since.map(crate::format_time).transpose()?,
+        until: filter.until.map(crate::format_time).transpose()?,
+        min_size: filter.min_size.map(|size| size.to_string()),
+        max_size: filter.max_size.map(|size| size.to_string()),
+        tag: filter.tags.clone(),
+        q: None,
+    })
+}
+
+/// As far as the server tells.
+fn data(summary: &Summary) -> Result<Data> {
+    Ok(Data {
+        name: summary.name.clone(),
+        index: summary.index,
+        seq: 0,
+        created: crate::parse_time(&summary.created).map_err(|e| eyre!(e))?,
+        size: summary.size,
+        locked: summary.locked,
+        expires: None,
+    })
+}
@@ -0,0 +1,42 @@
+use std::collections::HashMap;
+
+use eyre::{eyre, Result};
+use lazy_regex::regex;
+
+/// Replaces `${VAR}` placeholders with values from `vars`, falling back to the environment.
+/// Placeholders that neither defines are an error rather than silently left empty.
+pub fn render(contents: &[u8], vars: &[(String, String)]) -> Result<Vec<u8>> {
+    let vars = vars.iter().cloned().collect::<HashMap<_, _>>();
+    let placeholder = regex!(r"\$\{([A-Za-z_][A-Za-z0-9_]*)\}"B);
+
+    let mut missing = vec![];
+    let rendered = placeholder.replace_all(contents, |captures: &regex::bytes::Captures| {
+        let name = String::from_utf8_lossy(&captures[1]).into_owned();
+        match vars
+            .get(&name)
+            .cloned()
+            .or_else(|| std::env::var(&name).ok())
+        {
+            Some(value) => value.into_bytes(),
+            None => {
+                missing.push(name);
+                vec![]
+            }
+        }
+    });
+
+    match missing.is_empty() {
+        true => Ok(rendered.into_owned()),
+        false => Err(eyre!(
+            "undefined template variables: {}",
+            missing.join(", ")
+        )),
+    }
+}
+
+/// Parses a `key=value` template variable.
+pub fn parse_var(var: &str) -> Result<(String, String), String> {
+    var.split_once('=')
+        .map(|(key, value)| (key.to_owned(), value.to_owned()))
+        .ok_or_else(|| format!("expected key=value, got {var:?}"))
+}
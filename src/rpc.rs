@@ -0,0 +1,222 @@
+//! JSON-RPC 2.0 over stdin/stdout, one message per line.
+//!
+//! Methods:
+//! - `list` → `[{id, name, index, size, created, locked}]`, newest first
+//! - `get {id}` → `{id, content}`, content must be valid UTF-8, and the stash
+//!   mustn't be secret unless `stash rpc --force` is running
+//! - `put {name?, content, append?}` → `{id}`
+//! - `drop {id}` → `null`
+//! - `subscribe` → `null`, afterwards a `changed {stashes: [id]}` notification
+//!   is sent whenever the set of stashes changes
+
+use std::io::{BufRead, Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use eyre::Result;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::meta::Meta;
+use crate::stash_id::StashId;
+use crate::storage::{Data, StorageBackend};
+
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const STASH_ERROR: i64 = -32000;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Deserialize)]
+struct Request {
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Deserialize)]
+struct IdParams {
+    id: String,
+}
+
+#[derive(Deserialize)]
+struct PutParams {
+    #[serde(default)]
+    name: String,
+    content: String,
+    #[serde(default)]
+    append: bool,
+}
+
+struct Error {
+    code: i64,
+    message: String,
+}
+
+impl From<eyre::Report> for Error {
+    fn from(report: eyre::Report) -> Self {
+        Error {
+            code: STASH_ERROR,
+            message: report.to_string(),
+        }
+    }
+}
+
+fn params<'a, T: Deserialize<'a>>(params: &'a Value) -> Result<T, Error> {
+    T::deserialize(params).map_err(|e| Error {
+        code: INVALID_PARAMS,
+        message: e.to_string(),
+    })
+}
+
+fn get(store: &dyn StorageBackend, id: &str) -> Result<Data, Error> {
+    let id: StashId = id.parse().unwrap();
+    Data::get(store, &id)?.ok_or_else(|| Error {
+        code: STASH_ERROR,
+        message: format!("Stash {id} does not exist"),
+    })
+}
+
+/// What `list` returns per stash.
+pub fn summary(stash: &Data) -> Result<Value> {
+    Ok(serde_json::to_value(crate::stash_client::Summary::new(
+        stash,
+    )?)?)
+}
+
+fn ids(store: &dyn StorageBackend) -> Result<Vec<String>> {
+    Ok(Data::detect(store)?
+        .iter()
+        .map(|stash| stash.id().to_string())
+        .collect())
+}
+
+pub fn serve(store: &dyn StorageBackend, force: bool) -> Result<()> {
+    let stdout = Mutex::new(std::io::stdout());
+    let send = |message: Value| -> Result<()> {
+        let mut stdout = stdout.lock().unwrap();
+        writeln!(stdout, "{message}")?;
+        stdout.flush()?;
+        Ok(())
+    };
+    let subscribed = AtomicBool::new(false);
+    let done = AtomicBool::new(false);
+
+    std::thread::scope(|scope| -> Result<()> {
+        let poller = scope.spawn(|| -> Result<()> {
+            let mut last = ids(store)?;
+            while !done.load(Ordering::Relaxed) {
+                std::thread::sleep(POLL_INTERVAL);
+                if !subscribed.load(Ordering::Relaxed) {
+                    continue;
+                }
+                let current = ids(store)?;
+                if current != last {
+                    send(json!({
+                        "jsonrpc": "2.0",
+                        "method": "changed",
+                        "params": { "stashes": current },
+                    }))?;
+                    last = current;
+                }
+            }
+            Ok(())
+        });
+
+        let result = (|| -> Result<()> {
+            for line in std::io::stdin().lock().lines() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let (id, result) = match serde_json::from_str::<Request>(&line) {
+                    Ok(request) => {
+                        let result = handle(store, &request, &subscribed, force);
+                        (request.id, result)
+                    }
+                    Err(e) => (
+                        Some(Value::Null),
+                        Err(Error {
+                            code: PARSE_ERROR,
+                            message: e.to_string(),
+                        }),
+                    ),
+                };
+                // requests without an id are notifications and don't get a response
+                let Some(id) = id else { continue };
+                send(match result {
+                    Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                    Err(Error { code, message }) => json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "error": { "code": code, "message": message },
+                    }),
+                })?;
+            }
+            Ok(())
+        })();
+
+        done.store(true, Ordering::Relaxed);
+        poller.join().unwrap()?;
+        result
+    })
+}
+
+fn handle(
+    store: &dyn StorageBackend,
+    request: &Request,
+    subscribed: &AtomicBool,
+    force: bool,
+) -> Result<Value, Error> {
+    match request.method.as_str() {
+        "list" => {
+            let stashes = Data::detect(store)?
+                .iter()
+                .map(summary)
+                .collect::<Result<Vec<_>>>()?;
+            Ok(Value::Array(stashes))
+        }
+        "get" => {
+            let IdParams { id } = params(&request.params)?;
+            let stash = get(store, &id)?;
+            crate::ensure_shareable(store, &stash, force)?;
+            let mut content = String::new();
+            store
+                .reader(&stash)?
+                .read_to_string(&mut content)
+                .map_err(eyre::Report::from)?;
+            Ok(json!({ "id": stash.id().to_string(), "content": content }))
+        }
+        "put" => {
+            let PutParams {
+                name,
+                content,
+                append,
+            } = params(&request.params)?;
+            let contents = &mut content.as_bytes();
+            match Data::get_newest(store, &name)? {
+                Some(stash) if append => crate::append_to(store, &stash, contents)?,
+                _ => {
+                    crate::push(store, &name, &Meta::default(), contents)?;
+                }
+            }
+            Ok(json!({ "id": StashId::newest(&name).to_string() }))
+        }
+        "drop" => {
+            let IdParams { id } = params(&request.params)?;
+            crate::delete_stash(store, Some(get(store, &id)?))?;
+            Ok(Value::Null)
+        }
+        "subscribe" => {
+            subscribed.store(true, Ordering::Relaxed);
+            Ok(Value::Null)
+        }
+        method => Err(Error {
+            code: METHOD_NOT_FOUND,
+            message: format!("unknown method {method}"),
+        }),
+    }
+}
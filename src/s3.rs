@@ -0,0 +1,809 @@
+//! A storage backend for a bucket of an S3-compatible object store, so several
+//! machines and people can share their stashes.
+
+use std::io::{Read, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::SystemTime;
+
+use eyre::{eyre, Result};
+use hmac::{Hmac, Mac};
+use lazy_regex::regex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use ureq::http::{Request, Response};
+use ureq::Body;
+
+use crate::checksum::HashingReader;
+use crate::meta::Meta;
+use crate::storage::{self, Data, StorageBackend};
+
+/// How many objects are fetched at once when listing stashes.
+const PARALLEL_REQUESTS: usize = 16;
+
+/// Stashes as objects in a bucket, all below a common prefix.
+/// The contents are in `stashes/`, under the same names as the files of `LocalFs`,
+/// everything else about a stash in a json file of that name in `meta/`.
+/// Objects can't be renamed, so that includes the creation time.
+///
+/// A stash only shows up once its metadata exists, which is written after the
+/// contents. The sequence number of a new stash is claimed by creating its
+/// contents only if there is no object of that name yet, so concurrent pushes
+/// from several machines don't take the same one.
+///
+/// Removed stashes are moved to `trash/` with their metadata and the time of
+/// removal next to them, and every event is an object of its own in `log/`.
+#[derive(Clone)]
+pub struct S3Bucket {
+    agent: ureq::Agent,
+    endpoint: String,
+    host: String,
+    bucket: String,
+    region: String,
+    prefix: String,
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: Option<String>,
+}
+
+/// What's kept about a stash besides its contents.
+#[derive(Serialize, Deserialize)]
+struct Stored {
+    created: SystemTime,
+    size: u64,
+    locked: bool,
+    meta: Meta,
+}
+
+impl Stored {
+    fn data(&self, seq: u64, name: String) -> Data {
+        Data {
+            name,
+            index: 0,
+            seq,
+            created: self.created,
+            size: self.size,
+            locked: self.locked,
+            expires: self.meta.expiry(),
+        }
+    }
+}
+
+/// What's kept of a stash while it's in the trash.
+#[derive(Serialize, Deserialize)]
+struct Trashed {
+    removed: SystemTime,
+    stored: Stored,
+}
+
+/// Collects what's appended to a stash and uploads it together with the old
+/// contents on `flush`, objects can't be appended to.
+struct Appender {
+    store: S3Bucket,
+    stash: Data,
+    contents: Vec<u8>,
+    changed: bool,
+}
+
+impl Write for Appender {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.contents.extend_from_slice(buf);
+        self.changed = true;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        if self.changed {
+            self.store
+                .replace(&self.stash, &mut self.contents.as_slice())
+                .map_err(std::io::Error::other)?;
+            self.changed = false;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Appender {
+    fn drop(&mut self) {
+        if let Err(e) = self.flush() {
+            eprintln!("Error: couldn't upload the appended contents: {e}");
+        }
+    }
+}
+
+impl S3Bucket {
+    pub fn new(config: &crate::config::S3) -> Result<Self> {
+        let bucket = config
+            .bucket
+            .clone()
+            .ok_or_else(|| eyre!("the s3 backend needs s3.bucket in the config"))?;
+        let region = config.region.as_deref().unwrap_or("us-east-1").to_owned();
+        let endpoint = match &config.endpoint {
+            Some(endpoint) => endpoint.trim_end_matches('/').to_owned(),
+            None => format!("https://s3.{region}.amazonaws.com"),
+        };
+        let host = match endpoint.split_once("://") {
+            Some((_, host)) if !host.contains('/') => host.to_owned(),
+            _ => {
+                return Err(eyre!(
+                    "s3.endpoint should look like https://host[:port], got {endpoint}"
+                ))
+            }
+        };
+        let credential = |configured: &Option<String>, var: &str| {
+            configured
+                .clone()
+                .or_else(|| std::env::var(var).ok())
+                .ok_or_else(|| eyre!("the s3 backend needs credentials, from the config or {var}"))
+        };
+        Ok(S3Bucket {
+            agent: ureq::Agent::config_builder()
+                .http_status_as_error(false)
+                .build()
+                .into(),
+            endpoint,
+            host,
+            bucket,
+            region,
+            prefix: config.prefix.clone(),
+            access_key_id: credential(&config.access_key_id, "AWS_ACCESS_KEY_ID")?,
+            secret_access_key: credential(&config.secret_access_key, "AWS_SECRET_ACCESS_KEY")?,
+            session_token: std::env::var("AWS_SESSION_TOKEN").ok(),
+        })
+    }
+
+    /// The same bucket, for the stashes of another profile than the default one.
+    pub fn for_profile(&self, profile: &str) -> S3Bucket {
+        S3Bucket {
+            prefix: format!("{}profiles/{profile}/", self.prefix),
+            ..self.clone()
+        }
+    }
+
+    /// The profiles other than the default one that have stashes in the bucket.
+    pub fn profiles(&self) -> Result<Vec<String>> {
+        let prefix = format!("{}profiles/", self.prefix);
+        let mut profiles = vec![];
+        let mut token: Option<String> = None;
+        loop {
+            let mut query = vec![
+                ("list-type", "2"),
+                ("prefix", prefix.as_str()),
+                // only the profiles' prefixes rather than every object in them
+                ("delimiter", "/"),
+                ("encoding-type", "url"),
+            ];
+            if let Some(token) = &token {
+                query.push(("continuation-token", token));
+            }
+            let response = self.send("GET", None, &query, &[], &[])?;
+            let listing = check(response, &format!("listing {prefix}"))?
+                .into_body()
+                .read_to_string()?;
+            for common in
+                regex!("(?s)<CommonPrefixes>(.*?)</CommonPrefixes>").captures_iter(&listing)
+            {
+                let Some(profile) = regex!("<Prefix>(.*?)</Prefix>").captures(&common[1]) else {
+                    continue;
+                };
+                let profile = url_decode(&xml_unescape(&profile[1]));
+                if let Some(name) = profile.strip_prefix(&prefix) {
+                    profiles.push(name.trim_end_matches('/').to_owned());
+                }
+            }
+            token = regex!("<NextContinuationToken>(.*?)</NextContinuationToken>")
+                .captures(&listing)
+                .map(|token| xml_unescape(&token[1]));
+            if token.is_none() {
+                break;
+            }
+        }
+        profiles.sort();
+        Ok(profiles)
+    }
+
+    /// Sends a request for the object `key` below the prefix, or for the bucket
+    /// itself, signed with AWS signature version 4.
+    fn send(
+        &self,
+        method: &str,
+        key: Option<&str>,
+        query: &[(&str, &str)],
+        headers: &[(&str, &str)],
+        body: &[u8],
+    ) -> Result<Response<Body>> {
+        let format =
+            time::macros::format_description!("[year][month][day]T[hour][minute][second]Z");
+        let timestamp = time::OffsetDateTime::now_utc().format(&format)?;
+        let date = &timestamp[..8];
+
+        let mut path = format!("/{}", uri_encode(&self.bucket, false));
+        if let Some(key) = key {
+            path = format!(
+                "{path}/{}",
+                uri_encode(&format!("{}{key}", self.prefix), true)
+            );
+        }
+        let mut query: Vec<_> = query
+            .iter()
+            .map(|(name, value)| {
+                format!("{}={}", uri_encode(name, false), uri_encode(value, false))
+            })
+            .collect();
+        query.sort();
+        let query = query.join("&");
+        let payload_hash = format!("{:x}", Sha256::digest(body));
+        let mut signed: Vec<(String, String)> = vec![
+            ("host".to_owned(), self.host.clone()),
+            ("x-amz-content-sha256".to_owned(), payload_hash.clone()),
+            ("x-amz-date".to_owned(), timestamp.clone()),
+        ];
+        if let Some(token) = &self.session_token {
+            signed.push(("x-amz-security-token".to_owned(), token.clone()));
+        }
+        for (name, value) in headers {
+            signed.push((name.to_lowercase(), value.trim().to_owned()));
+        }
+        signed.sort();
+        let signed_names = signed
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>()
+            .join(";");
+        let canonical_headers: String = signed
+            .iter()
+            .map(|(name, value)| format!("{name}:{value}\n"))
+            .collect();
+        let canonical_request = format!(
+            "{method}\n{path}\n{query}\n{canonical_headers}\n{signed_names}\n{payload_hash}"
+        );
+        let scope = format!("{date}/{}/s3/aws4_request", self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{timestamp}\n{scope}\n{:x}",
+            Sha256::digest(canonical_request)
+        );
+        let mut signing_key = hmac(
+            format!("AWS4{}", self.secret_access_key).as_bytes(),
+            date.as_bytes(),
+        );
+        for part in [self.region.as_str(), "s3", "aws4_request"] {
+            signing_key = hmac(&signing_key, part.as_bytes());
+        }
+        let signature: String = hmac(&signing_key, string_to_sign.as_bytes())
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+
+        let url = match query.is_empty() {
+            true => format!("{}{path}", self.endpoint),
+            false => format!("{}{path}?{query}", self.endpoint),
+        };
+        let mut request = Request::builder().method(method).uri(url);
+        for (name, value) in &signed {
+            request = request.header(name, value);
+        }
+        let request = request.header(
+            "authorization",
+            format!(
+                "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_names}, Signature={signature}",
+                self.access_key_id
+            ),
+        );
+        // only uploads have a body, an empty one for an empty stash
+        let response = match method {
+            "PUT" => self.agent.run(request.body(body)?)?,
+            _ => self.agent.run(request.body(())?)?,
+        };
+        Ok(response)
+    }
+
+    /// The objects below `dir/` by name relative to it, with their sizes, sorted by name.
+    fn list(&self, dir: &str) -> Result<Vec<(String, u64)>> {
+        let prefix = format!("{}{dir}/", self.prefix);
+        let mut objects = vec![];
+        let mut token: Option<String> = None;
+        loop {
+            let mut query = vec![
+                ("list-type", "2"),
+                ("prefix", prefix.as_str()),
+                // keys may contain characters xml can't
+                ("encoding-type", "url"),
+            ];
+            if let Some(token) = &token {
+                query.push(("continuation-token", token));
+            }
+            let response = self.send("GET", None, &query, &[], &[])?;
+            let listing = check(response, &format!("listing {prefix}"))?
+                .into_body()
+                .read_to_string()?;
+            for object in regex!("(?s)<Contents>(.*?)</Contents>").captures_iter(&listing) {
+                let key = regex!("<Key>(.*?)</Key>").captures(&object[1]);
+                let size = regex!(r"<Size>(\d+)</Size>").captures(&object[1]);
+                let (Some(key), Some(size)) = (key, size) else {
+                    continue;
+                };
+                let key = url_decode(&xml_unescape(&key[1]));
+                if let Some(name) = key.strip_prefix(&prefix) {
+                    objects.push((name.to_owned(), size[1].parse()?));
+                }
+            }
+            token = regex!("<NextContinuationToken>(.*?)</NextContinuationToken>")
+                .captures(&listing)
+                .map(|token| xml_unescape(&token[1]));
+            if token.is_none() {
+                break;
+            }
+        }
+        objects.sort();
+        Ok(objects)
+    }
+
+    /// The object's contents, `None` if there is no such object.
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let response = self.send("GET", Some(key), &[], &[], &[])?;
+        if response.status() == 404 {
+            return Ok(None);
+        }
+        let mut contents = vec![];
+        check(response, &format!("downloading {key}"))?
+            .into_body()
+            .into_reader()
+            .read_to_end(&mut contents)?;
+        Ok(Some(contents))
+    }
+
+    /// Like `get` for many objects, fetched in parallel.
+    fn get_all(&self, keys: &[String]) -> Result<Vec<Option<Vec<u8>>>> {
+        let chunk_size = keys.len().div_ceil(PARALLEL_REQUESTS).max(1);
+        std::thread::scope(|scope| {
+            let threads: Vec<_> = keys
+                .chunks(chunk_size)
+                .map(|chunk| {
+                    scope.spawn(move || {
+                        chunk
+                            .iter()
+                            .map(|key| self.get(key))
+                            .collect::<Result<Vec<_>>>()
+                    })
+                })
+                .collect();
+            let mut objects = vec![];
+            for thread in threads {
+                objects.extend(thread.join().map_err(|_| eyre!("a download panicked"))??);
+            }
+            Ok(objects)
+        })
+    }
+
+    fn put(&self, key: &str, contents: &[u8]) -> Result<()> {
+        let response = self.send("PUT", Some(key), &[], &[], contents)?;
+        check(response, &format!("uploading {key}"))?;
+        Ok(())
+    }
+
+    /// Like `put`, but only if there is no object `key` yet. Returns whether there wasn't.
+    fn put_new(&self, key: &str, contents: &[u8]) -> Result<bool> {
+        let response = self.send("PUT", Some(key), &[], &[("if-none-match", "*")], contents)?;
+        // 409 is a concurrent upload of the same key
+        if [409, 412].contains(&response.status().as_u16()) {
+            return Ok(false);
+        }
+        check(response, &format!("uploading {key}"))?;
+        Ok(true)
+    }
+
+    fn copy(&self, from: &str, to: &str) -> Result<()> {
+        let source = format!(
+            "/{}/{}",
+            uri_encode(&self.bucket, false),
+            uri_encode(&format!("{}{from}", self.prefix), true)
+        );
+        let response = self.send("PUT", Some(to), &[], &[("x-amz-copy-source", &source)], &[])?;
+        check(response, &format!("copying {from} to {to}"))?;
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> Result<()> {
+        let response = self.send("DELETE", Some(key), &[], &[], &[])?;
+        check(response, &format!("deleting {key}"))?;
+        Ok(())
+    }
+
+    fn contents_key(stash: &Data) -> String {
+        format!("stashes/{}", stash.filename())
+    }
+
+    fn stored_key(stash: &Data) -> String {
+        format!("meta/{}.json", stash.filename())
+    }
+
+    fn trash_key(stash: &Data) -> String {
+        format!("trash/{}", stash.filename())
+    }
+
+    fn trashed_key(stash: &Data) -> String {
+        format!("trash/{}.json", stash.filename())
+    }
+
+    fn stored(&self, stash: &Data) -> Result<Stored> {
+        let json = self
+            .get(&S3Bucket::stored_key(stash))?
+            .ok_or_else(|| eyre!("Stash {} was removed in the meantime", stash.id()))?;
+        Ok(serde_json::from_slice(&json)?)
+    }
+
+    fn put_stored(&self, stash: &Data, stored: &Stored) -> Result<()> {
+        self.put(&S3Bucket::stored_key(stash), &serde_json::to_vec(stored)?)
+    }
+
+    /// Sequence number and name of every stash, from the listing alone.
+    fn scan(&self) -> Result<Vec<(u64, String)>> {
+        Ok(self
+            .list("meta")?
+            .into_iter()
+            .filter_map(|(key, _)| storage::parse_filename(key.strip_suffix(".json")?))
+            .collect())
+    }
+
+    /// Looks up the stashes with the given sequence numbers and names.
+    /// Those that were removed since they were listed are left out.
+    fn stashes(&self, ids: Vec<(u64, String)>) -> Result<Vec<Data>> {
+        let keys: Vec<_> = ids
+            .iter()
+            .map(|(seq, name)| format!("meta/{}.json", storage::filename(*seq, name)))
+            .collect();
+        let mut stashes = vec![];
+        for ((seq, name), json) in ids.into_iter().zip(self.get_all(&keys)?) {
+            if let Some(json) = json {
+                let stored: Stored = serde_json::from_slice(&json)?;
+                stashes.push(stored.data(seq, name));
+            }
+        }
+        Ok(stashes)
+    }
+
+    /// One past the highest sequence number in use, also counting the trash
+    /// so restored stashes don't collide.
+    fn next_seq(&self) -> Result<u64> {
+        let mut keys = self.list("stashes")?;
+        keys.extend(self.list("trash")?);
+        let highest = keys
+            .iter()
+            .filter_map(|(key, _)| storage::parse_filename(key.trim_end_matches(".json")))
+            .map(|(seq, _)| seq)
+            .max();
+        Ok(highest.map_or(0, |seq| seq + 1))
+    }
+
+    /// Uploads `contents` as a new stash in the stack `name`, at the first free
+    /// sequence number from `seq` on.
+    fn claim(&self, mut seq: u64, name: &str, contents: &[u8]) -> Result<u64> {
+        while !self.put_new(
+            &format!("stashes/{}", storage::filename(seq, name)),
+            contents,
+        )? {
+            seq += 1;
+        }
+        Ok(seq)
+    }
+}
+
+impl StorageBackend for S3Bucket {
+    fn entries(&self) -> Result<Vec<Data>> {
+        self.stashes(self.scan()?)
+    }
+
+    fn names(&self) -> Result<Vec<String>> {
+        Ok(self.scan()?.into_iter().map(|(_, name)| name).collect())
+    }
+
+    fn stack(&self, name: &str) -> Result<Vec<Data>> {
+        let mut ids = self.scan()?;
+        ids.retain(|(_, stash_name)| stash_name == name);
+        self.stashes(ids)
+    }
+
+    fn newest(&self, name: &str) -> Result<Option<Data>> {
+        let newest = self
+            .scan()?
+            .into_iter()
+            .filter(|(_, stash_name)| stash_name == name)
+            .max();
+        Ok(self.stashes(newest.into_iter().collect())?.pop())
+    }
+
+    fn reader(&self, stash: &Data) -> Result<Box<dyn Read>> {
+        storage::decoded(&self.meta(stash)?, self.raw_reader(stash)?)
+    }
+
+    fn raw_reader(&self, stash: &Data) -> Result<Box<dyn Read>> {
+        let key = S3Bucket::contents_key(stash);
+        let response = self.send("GET", Some(&key), &[], &[], &[])?;
+        let response = check(response, &format!("downloading {key}"))?;
+        Ok(Box::new(response.into_body().into_reader()))
+    }
+
+    fn reader_at(&self, stash: &Data, offset: u64) -> Result<Box<dyn Read>> {
+        // an encoded stash has to be decoded from the start
+        if self.meta(stash)?.encoded() {
+            let mut reader = self.reader(stash)?;
+            std::io::copy(&mut (&mut reader).take(offset), &mut std::io::sink())?;
+            return Ok(reader);
+        }
+        // a range starting at the end is an error
+        if offset >= stash.size {
+            return Ok(Box::new(std::io::empty()));
+        }
+        let key = S3Bucket::contents_key(stash);
+        let range = format!("bytes={offset}-");
+        let response = self.send("GET", Some(&key), &[], &[("range", &range)], &[])?;
+        let response = check(response, &format!("downloading {key}"))?;
+        Ok(Box::new(response.into_body().into_reader()))
+    }
+
+    fn create(&self, name: &str, meta: &Meta, contents: &mut dyn Read) -> Result<Data> {
+        // the upload needs the size up front, so it's all read first
+        let mut contents = HashingReader::new(contents);
+        let mut encoded = vec![];
+        let mut writer = storage::encoded(meta, Box::new(&mut encoded))?;
+        std::io::copy(&mut contents, &mut writer)?;
+        writer.finish()?;
+        let size = contents.bytes_read();
+        let stored = Stored {
+            created: SystemTime::now(),
+            size,
+            locked: false,
+            meta: Meta {
+                size: meta.encoded().then_some(size),
+                sha256: Some(contents.sha256()),
+                ..meta.clone()
+            },
+        };
+        let seq = self.claim(self.next_seq()?, name, &encoded)?;
+        let stash = stored.data(seq, name.to_owned());
+        self.put_stored(&stash, &stored)?;
+        Ok(stash)
+    }
+
+    fn append(&self, stash: &Data) -> Result<Box<dyn Write>> {
+        // an age stream can't be continued
+        if self.meta(stash)?.encrypted {
+            return Err(eyre!(
+                "Stash {} is encrypted, it can't be appended to",
+                stash.id()
+            ));
+        }
+        let mut contents = vec![];
+        self.reader(stash)?.read_to_end(&mut contents)?;
+        Ok(Box::new(Appender {
+            store: self.clone(),
+            stash: stash.clone(),
+            contents,
+            changed: false,
+        }))
+    }
+
+    fn replace(&self, stash: &Data, contents: &mut dyn Read) -> Result<()> {
+        let mut stored = self.stored(stash)?;
+        let mut contents = HashingReader::new(contents);
+        let mut encoded = vec![];
+        let mut writer = storage::encoded(&stored.meta, Box::new(&mut encoded))?;
+        std::io::copy(&mut contents, &mut writer)?;
+        writer.finish()?;
+        // replacing an object is atomic
+        self.put(&S3Bucket::contents_key(stash), &encoded)?;
+        stored.size = contents.bytes_read();
+        stored.meta.size = stored.meta.encoded().then_some(stored.size);
+        stored.meta.sha256 = Some(contents.sha256());
+        self.put_stored(stash, &stored)
+    }
+
+    fn touch(&self, stash: &Data) -> Result<()> {
+        let stored = Stored {
+            created: SystemTime::now(),
+            ..self.stored(stash)?
+        };
+        let contents = self
+            .get(&S3Bucket::contents_key(stash))?
+            .ok_or_else(|| eyre!("Stash {} was removed in the meantime", stash.id()))?;
+        let seq = self.claim(self.next_seq()?, &stash.name, &contents)?;
+        self.put_stored(&stored.data(seq, stash.name.clone()), &stored)?;
+        self.delete(&S3Bucket::stored_key(stash))?;
+        self.delete(&S3Bucket::contents_key(stash))
+    }
+
+    fn rename(&self, stash: &Data, name: &str) -> Result<Data> {
+        let stored = self.stored(stash)?;
+        let renamed = Data {
+            name: name.to_owned(),
+            index: 0,
+            ..stash.clone()
+        };
+        self.copy(
+            &S3Bucket::contents_key(stash),
+            &S3Bucket::contents_key(&renamed),
+        )?;
+        // never listed under both names
+        self.delete(&S3Bucket::stored_key(stash))?;
+        self.put_stored(&renamed, &stored)?;
+        self.delete(&S3Bucket::contents_key(stash))?;
+        Ok(renamed)
+    }
+
+    fn set_locked(&self, stash: &Data, locked: bool) -> Result<()> {
+        let mut stored = self.stored(stash)?;
+        stored.locked = locked;
+        self.put_stored(stash, &stored)
+    }
+
+    fn meta(&self, stash: &Data) -> Result<Meta> {
+        Ok(self.stored(stash)?.meta)
+    }
+
+    fn set_meta(&self, stash: &Data, meta: &Meta) -> Result<()> {
+        let mut stored = self.stored(stash)?;
+        stored.meta = meta.clone();
+        self.put_stored(stash, &stored)
+    }
+
+    fn remove(&self, stash: &Data) -> Result<()> {
+        let trashed = Trashed {
+            removed: SystemTime::now(),
+            stored: self.stored(stash)?,
+        };
+        self.put(
+            &S3Bucket::trashed_key(stash),
+            &serde_json::to_vec(&trashed)?,
+        )?;
+        self.copy(&S3Bucket::contents_key(stash), &S3Bucket::trash_key(stash))?;
+        self.delete(&S3Bucket::stored_key(stash))?;
+        self.delete(&S3Bucket::contents_key(stash))
+    }
+
+    fn trashed(&self) -> Result<Vec<(Data, SystemTime)>> {
+        let objects = self.list("trash")?;
+        let ids: Vec<_> = objects
+            .iter()
+            .filter_map(|(key, _)| {
+                let stash = key.strip_suffix(".json")?;
+                // the contents are copied last on removal and first on restore
+                objects.iter().find(|(other, _)| other == stash)?;
+                storage::parse_filename(stash)
+            })
+            .collect();
+        let keys: Vec<_> = ids
+            .iter()
+            .map(|(seq, name)| format!("trash/{}.json", storage::filename(*seq, name)))
+            .collect();
+        let mut trashed = vec![];
+        for ((seq, name), json) in ids.into_iter().zip(self.get_all(&keys)?) {
+            if let Some(json) = json {
+                let Trashed { removed, stored } = serde_json::from_slice(&json)?;
+                trashed.push((stored.data(seq, name), removed));
+            }
+        }
+        Ok(trashed)
+    }
+
+    fn restore(&self, stash: &Data) -> Result<Data> {
+        let json = self
+            .get(&S3Bucket::trashed_key(stash))?
+            .ok_or_else(|| eyre!("Stash {} isn't in the trash anymore", stash.id()))?;
+        let Trashed { stored, .. } = serde_json::from_slice(&json)?;
+        let restored = Data {
+            index: 0,
+            ..stash.clone()
+        };
+        self.copy(
+            &S3Bucket::trash_key(stash),
+            &S3Bucket::contents_key(&restored),
+        )?;
+        self.put_stored(&restored, &stored)?;
+        self.delete(&S3Bucket::trash_key(stash))?;
+        self.delete(&S3Bucket::trashed_key(stash))?;
+        Ok(restored)
+    }
+
+    fn purge(&self, stash: &Data) -> Result<()> {
+        self.delete(&S3Bucket::trash_key(stash))?;
+        self.delete(&S3Bucket::trashed_key(stash))
+    }
+
+    fn log_event(&self, line: &str) -> Result<()> {
+        static EVENTS: AtomicU64 = AtomicU64::new(0);
+
+        // named so that listing them sorts them by time
+        let since_epoch = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?;
+        let key = format!(
+            "log/{:020}.{}.{}.{}",
+            since_epoch.as_nanos(),
+            gethostname::gethostname().to_string_lossy(),
+            std::process::id(),
+            EVENTS.fetch_add(1, Ordering::Relaxed)
+        );
+        self.put(&key, format!("{line}\n").as_bytes())
+    }
+
+    fn events_since(&self, offset: u64) -> Result<Vec<u8>> {
+        // the log is all events one after another, only those from `offset`
+        // on are fetched
+        let mut start = 0;
+        let mut skip = 0;
+        let mut keys = vec![];
+        for (key, size) in self.list("log")? {
+            if start + size <= offset {
+                start += size;
+                continue;
+            }
+            if keys.is_empty() {
+                skip = (offset - start) as usize;
+            }
+            keys.push(format!("log/{key}"));
+        }
+        let mut events = vec![];
+        for event in self.get_all(&keys)?.into_iter().flatten() {
+            events.extend(event);
+        }
+        Ok(events.split_off(skip.min(events.len())))
+    }
+}
+
+/// Fails with S3's explanation if the request wasn't successful.
+fn check(response: Response<Body>, action: &str) -> Result<Response<Body>> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let error = response.into_body().read_to_string().unwrap_or_default();
+    match regex!("<Message>(.*?)</Message>").captures(&error) {
+        Some(message) => Err(eyre!(
+            "{action} failed: {status}, {}",
+            xml_unescape(&message[1])
+        )),
+        None => Err(eyre!("{action} failed: {status}")),
+    }
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Percent-encodes everything but unreserved characters, and slashes in paths,
+/// as the signature expects.
+fn uri_encode(s: &str, path: bool) -> String {
+    s.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            b'/' if path => "/".to_owned(),
+            _ => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+/// The reverse of the url encoding of keys in listings, which encodes spaces as `+`.
+fn url_decode(s: &str) -> String {
+    let mut decoded = vec![];
+    let mut bytes = s.bytes();
+    while let Some(byte) = bytes.next() {
+        match byte {
+            b'+' => decoded.push(b' '),
+            b'%' => {
+                let hex: String = bytes.by_ref().take(2).map(char::from).collect();
+                decoded.push(u8::from_str_radix(&hex, 16).unwrap_or(b'?'));
+            }
+            _ => decoded.push(byte),
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn xml_unescape(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
@@ -0,0 +1,171 @@
+//! Handling the plaintext of secret stashes so that it doesn't end up in swap or
+//! in `/tmp`: buffers are locked into memory where the `RLIMIT_MEMLOCK` allows and
+//! overwritten once they're dropped, and the copies that `edit` and `exec` hand to
+//! other programs only exist in memory on Linux.
+
+use std::io::{Read, Seek, Write};
+use std::path::{Path, PathBuf};
+
+use eyre::Result;
+use zeroize::Zeroize;
+
+/// Bytes in memory that's locked into RAM if possible, zeroed when dropped.
+pub struct SecretBuf {
+    // never reallocated, growing moves the bytes into a new buffer so that no
+    // copy is left behind unscrubbed
+    bytes: Vec<u8>,
+}
+
+impl SecretBuf {
+    fn with_capacity(capacity: usize) -> SecretBuf {
+        let bytes = Vec::<u8>::with_capacity(capacity);
+        #[cfg(unix)]
+        // SAFETY: the range is the allocation of `bytes`. Failing to lock it, for
+        // example past the limit, just leaves it swappable.
+        unsafe {
+            libc::mlock(bytes.as_ptr().cast(), bytes.capacity());
+        }
+        SecretBuf { bytes }
+    }
+
+    pub fn read_from(reader: &mut dyn Read) -> Result<SecretBuf> {
+        let mut buf = SecretBuf::with_capacity(8 * 1024);
+        loop {
+            if buf.bytes.len() == buf.bytes.capacity() {
+                let mut grown = SecretBuf::with_capacity(2 * buf.bytes.len());
+                grown.bytes.extend_from_slice(&buf.bytes);
+                buf = grown;
+            }
+            let len = buf.bytes.len();
+            buf.bytes.resize(buf.bytes.capacity(), 0);
+            let read = reader.read(&mut buf.bytes[len..]);
+            buf.bytes.truncate(len + read.as_ref().unwrap_or(&0));
+            match read {
+                Ok(0) => return Ok(buf),
+                Ok(_) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
+
+impl std::ops::Deref for SecretBuf {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl Drop for SecretBuf {
+    fn drop(&mut self) {
+        // the spare capacity too
+        self.bytes.zeroize();
+        #[cfg(unix)]
+        // SAFETY: as for `mlock`
+        unsafe {
+            libc::munlock(self.bytes.as_ptr().cast(), self.bytes.capacity());
+        }
+    }
+}
+
+/// A copy of a stash for another program to open at `path()`. Removed when
+/// dropped.
+pub enum Copy {
+    /// Of a secret stash, see `MemFile`
+    #[cfg(target_os = "linux")]
+    Memory(MemFile),
+    /// From `crate::private_copy`, removed with the dir
+    Temp {
+        _dir: tempdir::TempDir,
+        path: PathBuf,
+    },
+}
+
+impl Copy {
+    /// Like `crate::private_copy`, but only in memory for secret stashes where
+    /// that's possible. A `mode` without write permission makes it read-only,
+    /// which is needed for the kernel to execute it when it's in memory.
+    pub fn new(
+        secret: bool,
+        prefix: &str,
+        filename: &str,
+        contents: &[u8],
+        mode: u32,
+    ) -> Result<Copy> {
+        #[cfg(target_os = "linux")]
+        if secret {
+            return Ok(Copy::Memory(MemFile::new(filename, contents, mode)?));
+        }
+        #[cfg(not(target_os = "linux"))]
+        let _ = secret;
+        let (dir, path) = crate::private_copy(prefix, filename, contents, mode)?;
+        Ok(Copy::Temp { _dir: dir, path })
+    }
+
+    pub fn path(&self) -> &Path {
+        match self {
+            #[cfg(target_os = "linux")]
+            Copy::Memory(file) => &file.path,
+            Copy::Temp { path, .. } => path,
+        }
+    }
+
+    /// The current contents, after the other program is done with it.
+    pub fn read(&self) -> Result<SecretBuf> {
+        match self {
+            #[cfg(target_os = "linux")]
+            Copy::Memory(file) => {
+                let mut file = &file.file;
+                file.rewind()?;
+                SecretBuf::read_from(&mut file)
+            }
+            Copy::Temp { path, .. } => SecretBuf::read_from(&mut fs_err::File::open(path)?),
+        }
+    }
+}
+
+/// A file that's only in memory, from `memfd_create`. It's open without
+/// close-on-exec, so that the programs started while it exists inherit it and
+/// can open it at `/proc/self/fd/<fd>`, like this process.
+///
+/// It's never on a file system, though like any memory that isn't locked it can
+/// be swapped out. What editors do on their own, such as vim's swap files, is up
+/// to their configuration.
+#[cfg(target_os = "linux")]
+pub struct MemFile {
+    file: std::fs::File,
+    path: PathBuf,
+}
+
+#[cfg(target_os = "linux")]
+impl MemFile {
+    fn new(name: &str, contents: &[u8], mode: u32) -> Result<MemFile> {
+        use std::os::unix::fs::PermissionsExt;
+        use std::os::unix::io::{AsRawFd, FromRawFd};
+
+        let name = std::ffi::CString::new(name)?;
+        // SAFETY: `name` is a valid C string, the fd is owned by `file` below
+        let fd = unsafe { libc::memfd_create(name.as_ptr(), 0) };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        let mut file = unsafe { std::fs::File::from_raw_fd(fd) };
+        file.write_all(contents)?;
+        file.set_permissions(std::fs::Permissions::from_mode(mode))?;
+        let mut path = PathBuf::from(format!("/proc/self/fd/{fd}"));
+        if mode & 0o200 == 0 {
+            // executing a file fails while something has it open for writing,
+            // which the fd from `memfd_create` is
+            let read_only = std::fs::File::open(&path)?;
+            // SAFETY: clears close-on-exec on an fd owned by `read_only`
+            if unsafe { libc::fcntl(read_only.as_raw_fd(), libc::F_SETFD, 0) } < 0 {
+                return Err(std::io::Error::last_os_error().into());
+            }
+            path = PathBuf::from(format!("/proc/self/fd/{}", read_only.as_raw_fd()));
+            file = read_only;
+        }
+        Ok(MemFile { file, path })
+    }
+}
@@ -0,0 +1,147 @@
+use eyre::{eyre, Result};
+use lazy_regex::{BytesRegex, BytesRegexBuilder};
+
+/// A sed-style `s/pattern/replacement/flags` command.
+///
+/// Like sed it works line by line: without the `g` flag only the first match
+/// in each line is replaced. `i` makes the pattern case-insensitive.
+pub struct Substitution {
+    regex: BytesRegex,
+    /// In the syntax of the regex crate, e.g. `${1}` instead of `\1`
+    replacement: String,
+    global: bool,
+}
+
+impl Substitution {
+    pub fn parse(command: &str) -> Result<Substitution> {
+        let invalid = || eyre!("expected a substitution like s/pattern/replacement/flags");
+        let mut chars = command.chars();
+        if chars.next() != Some('s') {
+            return Err(invalid());
+        }
+        let delimiter = chars.next().ok_or_else(invalid)?;
+
+        // splits at unescaped delimiters and unescapes escaped ones
+        let mut parts = vec![String::new()];
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    // an escaped delimiter is always meant literally, even if it's a regex metacharacter
+                    Some(c) if c == delimiter && parts.len() == 1 && c.is_ascii_punctuation() => {
+                        parts[0].extend(['\\', c])
+                    }
+                    Some(c) if c == delimiter => parts.last_mut().unwrap().push(c),
+                    Some(c) => parts.last_mut().unwrap().extend(['\\', c]),
+                    None => return Err(invalid()),
+                },
+                c if c == delimiter => parts.push(String::new()),
+                c => parts.last_mut().unwrap().push(c),
+            }
+        }
+        let [pattern, replacement, flags] =
+            <[String; 3]>::try_from(parts).map_err(|_| invalid())?;
+
+        let mut builder = BytesRegexBuilder::new(&pattern);
+        let mut global = false;
+        for flag in flags.chars() {
+            match flag {
+                'g' => global = true,
+                'i' => {
+                    builder.case_insensitive(true);
+                }
+                flag => return Err(eyre!("unsupported flag {flag:?}")),
+            }
+        }
+
+        Ok(Substitution {
+            regex: builder.build()?,
+            replacement: translate_replacement(&replacement),
+            global,
+        })
+    }
+
+    pub fn apply(&self, contents: &[u8]) -> Vec<u8> {
+        let lines = contents.split_inclusive(|&b| b == b'\n').map(|line| {
+            let limit = if self.global { 0 } else { 1 };
+            self.regex
+                .replacen(line, limit, self.replacement.as_bytes())
+                .into_owned()
+        });
+        lines.collect::<Vec<_>>().concat()
+    }
+}
+
+/// Turns sed's `&` and `\1` into `${0}` and `${1}`
+fn translate_replacement(replacement: &str) -> String {
+    let mut translated = String::new();
+    let mut chars = replacement.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '&' => translated.push_str("${0}"),
+            '$' => translated.push_str("$$"),
+            '\\' => match chars.next() {
+                Some(digit @ '0'..='9') => translated.push_str(&format!("${{{digit}}}")),
+                Some('n') => translated.push('\n'),
+                Some('t') => translated.push('\t'),
+                Some('$') => translated.push_str("$$"),
+                Some(c) => translated.push(c),
+                None => translated.push('\\'),
+            },
+            c => translated.push(c),
+        }
+    }
+    translated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sed(command: &str, contents: &str) -> String {
+        let substitution = Substitution::parse(command).unwrap();
+        String::from_utf8(substitution.apply(contents.as_bytes())).unwrap()
+    }
+
+    #[test]
+    fn replaces_the_first_match_per_line() {
+        assert_eq!(sed("s/a/b/", "aa\naa\n"), "ba\nba\n");
+        assert_eq!(sed("s/a/b/g", "aa\naa"), "bb\nbb");
+        assert_eq!(sed("s/x/y/", ""), "");
+        assert_eq!(sed("s/^/> /", "a\n\nb"), "> a\n> \n> b");
+    }
+
+    #[test]
+    fn flags() {
+        assert_eq!(sed("s/token/***/i", "Token TOKEN"), "*** TOKEN");
+        assert_eq!(sed("s/token/***/gi", "Token TOKEN"), "*** ***");
+        assert_eq!(sed("s/token/***/ig", "Token TOKEN"), "*** ***");
+    }
+
+    #[test]
+    fn replacements_use_sed_syntax() {
+        assert_eq!(sed("s/[0-9]+/<&>/g", "a1b22"), "a<1>b<22>");
+        assert_eq!(sed(r"s/(\w+)=(\w+)/\2=\1/", "key=value"), "value=key");
+        assert_eq!(sed("s/x/$1/", "x"), "$1");
+        assert_eq!(sed(r"s/,/\n/g", "a,b"), "a\nb");
+        assert_eq!(sed(r"s/,/\t/", "a,b"), "a\tb");
+        assert_eq!(sed(r"s/a/\&/", "a"), "&");
+    }
+
+    #[test]
+    fn delimiters() {
+        assert_eq!(sed("s|/usr|/opt|", "/usr/bin"), "/opt/bin");
+        assert_eq!(sed(r"s/\/usr/\/opt/", "/usr/bin"), "/opt/bin");
+        // escaped, a metacharacter used as the delimiter is literal in the pattern
+        assert_eq!(sed(r"s|a\|b|x|", "a|b ab"), "x ab");
+        assert_eq!(sed(r"s.a\.b.x.", "a.b acb"), "x acb");
+    }
+
+    #[test]
+    fn invalid_commands() {
+        for command in [
+            "", "s", "y/a/b/", "s/a/b", "s/a/b/g/", "s/a/b/x", "s/(/b/", "s/a/b\\",
+        ] {
+            assert!(Substitution::parse(command).is_err(), "{command:?}");
+        }
+    }
+}
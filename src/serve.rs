@@ -0,0 +1,691 @@
+//! A REST API over HTTP, for getting stashes in and out of the store with `curl`
+//! and the like.
+//!
+//! Every request needs an `Authorization: Bearer <token>` header.
+//! - `GET /stashes` → `[{id, name, index, size, created, locked}]`, newest first.
+//!   Narrowed down like `list` and `grep` by the query parameters `name` (a glob),
+//!   `regex`, `since`, `until`, `min_size`, `max_size`, `tag` (repeatable) and `q`,
+//!   a regex for the contents. `q` never matches secret or encrypted stashes.
+//! - `GET /stashes/<id>` → the contents
+//! - `POST /stashes/<name>` with the contents as body → `{id}`, pushed to the
+//!   unnamed stack if there's no name, like `stash push` with the config's
+//!   defaults and quotas. The body may be chunked, or `multipart/form-data`
+//!   with the contents as the first file, see `multipart`. It's streamed to the
+//!   store as it arrives. Bodies over `serve.max_body` from the config, 10 GiB
+//!   by default, are refused.
+//! - `DELETE /stashes/<id>` → nothing
+//!
+//! Ids and names may contain slashes, other special characters are percent-encoded.
+//!
+//! Clients over `serve.requests_per_minute` get a 429, and requests beyond
+//! `serve.max_connections` being handled at once a 503.
+//!
+//! `/dav/` is a read-only WebDAV view of the store, see `dav`.
+//!
+//! `GET /openapi.json` describes the API in OpenAPI, `crate::client` implements it.
+//!
+//! `GET /` is a page for browsing the stashes with this API, which asks for the
+//! token. It's also taken from the fragment of the link, `http://host:port/#<token>`.
+
+use std::collections::HashMap;
+use std::io::{Cursor, Read};
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use eyre::{eyre, Result};
+use serde_json::{json, Value};
+use tiny_http::{Header, Method, Request, Response};
+use utoipa::OpenApi;
+
+mod dav;
+mod multipart;
+
+use crate::compression::Encoding;
+use crate::config::Config;
+use crate::meta::Meta;
+use crate::stash_client::{Pushed, Query, Summary};
+use crate::stash_id::StashId;
+use crate::storage::{Data, StorageBackend};
+
+type Body = Box<dyn Read>;
+
+/// The page served at `/`.
+const UI: &str = include_str!("ui.html");
+
+/// Of a pushed stash, unless `serve.max_body` says otherwise. Bodies aren't kept
+/// in memory, this only keeps a client from filling up the disk.
+const MAX_BODY: u64 = 10 * 1024 * 1024 * 1024;
+/// Unless `serve.max_connections` says otherwise.
+const MAX_CONNECTIONS: usize = 16;
+
+struct Error {
+    status: u16,
+    message: String,
+}
+
+impl From<eyre::Report> for Error {
+    fn from(report: eyre::Report) -> Self {
+        Error {
+            status: 500,
+            message: report.to_string(),
+        }
+    }
+}
+
+fn error(status: u16, message: impl Into<String>) -> Error {
+    Error {
+        status,
+        message: message.into(),
+    }
+}
+
+/// Handles requests until the process is stopped. The link to the page only has
+/// the token in it if it was `generated`, configured ones shouldn't end up in
+/// terminal scrollback or logs.
+pub fn serve(
+    store: &dyn StorageBackend,
+    config: &Config,
+    listen: &str,
+    token: &str,
+    generated: bool,
+) -> Result<()> {
+    let server =
+        tiny_http::Server::http(listen).map_err(|e| eyre!("couldn't listen on {listen}: {e}"))?;
+    let fragment = match generated {
+        true => format!("#{token}"),
+        false => String::new(),
+    };
+    eprintln!("Listening on http://{listen}, browse the stashes at http://{listen}/{fragment}");
+
+    let max_connections = config.serve.max_connections.unwrap_or(MAX_CONNECTIONS);
+    let active = AtomicUsize::new(0);
+    let mut rate_limit = config.serve.requests_per_minute.map(RateLimit::new);
+    std::thread::scope(|scope| {
+        for request in server.incoming_requests() {
+            let client = request.remote_addr().map(|addr| addr.ip());
+            let retry_after = rate_limit
+                .as_mut()
+                .zip(client)
+                .and_then(|(rate_limit, client)| rate_limit.check(client));
+            if let Some(retry_after) = retry_after {
+                let response = respond_with(
+                    429,
+                    "text/plain; charset=utf-8",
+                    "too many requests\n".to_owned(),
+                )
+                .with_header(header("Retry-After", &retry_after.as_secs().to_string()));
+                send(request, response);
+                continue;
+            }
+            if active.load(Ordering::SeqCst) >= max_connections {
+                let body = "too many requests at once, try again later\n".to_owned();
+                send(
+                    request,
+                    respond_with(503, "text/plain; charset=utf-8", body),
+                );
+                continue;
+            }
+            let slot = Slot::take(&active);
+            scope.spawn(move || {
+                let _slot = slot;
+                handle_request(store, config, token, request);
+            });
+        }
+    });
+    Ok(())
+}
+
+/// One of the `max_connections` requests handled at once, given back when it's
+/// dropped, even if handling the request panicked.
+struct Slot<'a>(&'a AtomicUsize);
+
+impl<'a> Slot<'a> {
+    fn take(active: &'a AtomicUsize) -> Slot<'a> {
+        active.fetch_add(1, Ordering::SeqCst);
+        Slot(active)
+    }
+}
+
+impl Drop for Slot<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+fn handle_request(store: &dyn StorageBackend, config: &Config, token: &str, mut request: Request) {
+    // the page and the spec have no stashes in them, they need the token for those
+    let public = match (request.method(), path(request.url())) {
+        (Method::Get, "/") => Some(respond_with(200, "text/html; charset=utf-8", UI.to_owned())),
+        (Method::Get, "/openapi.json") => Some(respond_with(
+            200,
+            "application/json",
+            Api::openapi()
+                .to_pretty_json()
+                .expect("a serializable spec"),
+        )),
+        _ => None,
+    };
+    let is_dav = dav::is_dav(request.url());
+    let response = match public {
+        Some(response) => Ok(response),
+        None if authorized(&request, token) && is_dav => dav::handle(store, &request),
+        None if authorized(&request, token) => handle(store, config, &mut request),
+        None => Err(error(401, "missing or wrong bearer token")),
+    };
+    let response = response.unwrap_or_else(|Error { status, message }| {
+        let response = respond_with(status, "text/plain; charset=utf-8", format!("{message}\n"));
+        // file managers only ask for credentials when told to, browsers would ask
+        // on the page's own requests too
+        match status == 401 && is_dav {
+            true => response.with_header(header("WWW-Authenticate", "Basic realm=\"stash\"")),
+            false => response,
+        }
+    });
+    send(request, response);
+}
+
+/// Logs the request before responding to it.
+fn send(request: Request, response: Response<Body>) {
+    eprintln!(
+        "{} {} {}",
+        request.method(),
+        request.url(),
+        response.status_code().0
+    );
+    if let Err(e) = request.respond(response) {
+        eprintln!("Couldn't send the response: {e}");
+    }
+}
+
+/// How many requests each client made in the current minute.
+struct RateLimit {
+    per_minute: u32,
+    clients: HashMap<IpAddr, (Instant, u32)>,
+}
+
+impl RateLimit {
+    fn new(per_minute: u32) -> RateLimit {
+        RateLimit {
+            per_minute,
+            clients: HashMap::new(),
+        }
+    }
+
+    /// Counts a request of `client`, and if it's one too many, returns how long
+    /// until the client may make requests again.
+    fn check(&mut self, client: IpAddr) -> Option<Duration> {
+        const MINUTE: Duration = Duration::from_secs(60);
+        let now = Instant::now();
+        // forget the clients whose minute is up so this doesn't grow forever
+        self.clients
+            .retain(|_, (start, _)| now.duration_since(*start) < MINUTE);
+        let (start, count) = self.clients.entry(client).or_insert((now, 0));
+        if *count >= self.per_minute {
+            return Some(
+                MINUTE
+                    .saturating_sub(now.duration_since(*start))
+                    .max(Duration::from_secs(1)),
+            );
+        }
+        *count += 1;
+        None
+    }
+}
+
+/// With the bearer token, or with it as the password of basic auth for WebDAV.
+fn authorized(request: &Request, token: &str) -> bool {
+    use base64::Engine;
+
+    let bearer = format!("Bearer {token}");
+    request
+        .headers()
+        .iter()
+        .filter(|header| header.field.equiv("Authorization"))
+        .any(|header| {
+            let value = header.value.as_str();
+            let basic_password = value
+                .strip_prefix("Basic ")
+                .and_then(|credentials| {
+                    base64::engine::general_purpose::STANDARD
+                        .decode(credentials)
+                        .ok()
+                })
+                .and_then(|credentials| String::from_utf8(credentials).ok())
+                .and_then(|credentials| Some(credentials.split_once(':')?.1.to_owned()));
+            same(value, &bearer) || basic_password.is_some_and(|password| same(&password, token))
+        })
+}
+
+/// Compares in constant time, so the token can't be guessed a byte at a time
+/// from how long it takes to be rejected.
+fn same(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+fn handle(
+    store: &dyn StorageBackend,
+    config: &Config,
+    request: &mut Request,
+) -> Result<Response<Body>, Error> {
+    let url = request.url().to_owned();
+    let path = path(&url);
+    let rest = match path.strip_prefix("/stashes") {
+        Some("") => None,
+        Some(rest) if rest.starts_with('/') => Some(percent_decode(&rest[1..])?),
+        _ => return Err(error(404, format!("no such endpoint {path}"))),
+    };
+
+    match (request.method(), rest) {
+        (Method::Get, None) => {
+            let query = url.split_once('?').map_or("", |(_, query)| query);
+            list(store, &parse_query(query)?)
+        }
+        (Method::Get, Some(id)) => show(store, &id),
+        (Method::Post, name) => push(store, config, &name.unwrap_or_default(), request),
+        (Method::Delete, Some(id)) => delete(store, &id, if_match(request)),
+        _ => Err(error(
+            405,
+            format!("{} isn't allowed here", request.method()),
+        )),
+    }
+}
+
+/// The API as `GET /openapi.json` describes it.
+#[derive(utoipa::OpenApi)]
+#[openapi(
+    info(title = "stash", description = "The REST API of `stash serve`"),
+    paths(list, show, push, delete),
+    modifiers(&BearerToken),
+    security(("token" = []))
+)]
+struct Api;
+
+struct BearerToken;
+
+impl utoipa::Modify for BearerToken {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+
+        let scheme = HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build();
+        openapi
+            .components
+            .get_or_insert_with(Default::default)
+            .add_security_scheme("token", SecurityScheme::Http(scheme));
+    }
+}
+
+/// List the stashes, newest first
+#[utoipa::path(
+    get,
+    path = "/stashes",
+    params(Query),
+    responses(
+        (status = 200, description = "The matching stashes", body = [Summary]),
+        (status = 400, description = "An invalid query", body = String),
+    )
+)]
+fn list(store: &dyn StorageBackend, query: &Query) -> Result<Response<Body>, Error> {
+    let stashes = matching(store, query)?
+        .iter()
+        .map(Summary::new)
+        .collect::<Result<Vec<_>>>()?;
+    Ok(json_response(
+        200,
+        serde_json::to_value(stashes).map_err(eyre::Report::from)?,
+    ))
+}
+
+/// Get the contents of a stash
+#[utoipa::path(
+    get,
+    path = "/stashes/{id}",
+    params(("id" = String, Path, description = "`name:index`, or just the name for the newest one")),
+    responses(
+        (status = 200, description = "The contents", body = String, content_type = "application/octet-stream",
+            headers(("ETag" = String, description = "Of this stash, for `DELETE` to make sure it deletes the same one"))),
+        (status = 403, description = "The stash is secret or encrypted", body = String),
+        (status = 404, description = "There is no such stash", body = String),
+    )
+)]
+fn show(store: &dyn StorageBackend, id: &str) -> Result<Response<Body>, Error> {
+    let stash = get(store, id)?;
+    let meta = store.meta(&stash)?;
+    // `show` wouldn't print these outside of a terminal either, or it would
+    // ask for the passphrase on the server's terminal
+    if crate::withheld(store, &stash, false)? || meta.encrypted {
+        return Err(error(
+            403,
+            format!(
+                "Stash {} is secret or encrypted, it's only shown by `stash show`",
+                stash.id()
+            ),
+        ));
+    }
+    let content_type = meta
+        .download
+        .and_then(|download| download.content_type)
+        .unwrap_or_else(|| "application/octet-stream".to_owned());
+    Ok(Response::empty(200)
+        .with_data(store.reader(&stash)?, None)
+        .with_header(header("Content-Type", &content_type))
+        .with_header(header("ETag", &etag(&stash))))
+}
+
+/// Push the body as the newest stash of a name
+#[utoipa::path(
+    post,
+    path = "/stashes/{name}",
+    params(("name" = String, Path, description = "Left out for the unnamed stack")),
+    request_body(
+        content = String,
+        content_type = "application/octet-stream",
+        description = "The contents, possibly chunked, or a `multipart/form-data` body with them as the first file"
+    ),
+    responses(
+        (status = 201, description = "The stash was pushed", body = Pushed),
+        (status = 400, description = "The multipart body has no file or is malformed, or the server is configured to encrypt but has no `$STASH_PASSPHRASE`", body = String),
+        (status = 413, description = "The body is larger than `serve.max_body`", body = String),
+    )
+)]
+fn push(
+    store: &dyn StorageBackend,
+    config: &Config,
+    name: &str,
+    request: &mut Request,
+) -> Result<Response<Body>, Error> {
+    let max_body = config.serve.max_body.unwrap_or(MAX_BODY);
+    let too_large = || error(413, format!("the body may be at most {max_body} bytes"));
+    if request
+        .body_length()
+        .is_some_and(|length| length as u64 > max_body)
+    {
+        return Err(too_large());
+    }
+    let boundary = request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv("Content-Type"))
+        .and_then(|header| multipart::boundary(header.value.as_str()));
+    let mut meta = Meta {
+        encoding: config.compress.then_some(Encoding::Zstd),
+        encrypted: config.encrypt,
+        expires: crate::expiry(None, config.expire.as_deref())?,
+        ..Meta::captured()
+    };
+    // asking for the passphrase would wait on the server's terminal
+    if meta.encrypted {
+        crate::crypto::ensure_unattended().map_err(|e| error(400, e.to_string()))?;
+    }
+    let mut body = Limited {
+        reader: request.as_reader(),
+        left: max_body,
+        exceeded: false,
+    };
+    let pushed = match boundary {
+        None => crate::push(store, name, &meta, &mut body).map_err(Error::from),
+        Some(boundary) => {
+            let mut parts = multipart::Multipart::new(&mut body, &boundary);
+            loop {
+                match parts.next_part() {
+                    Ok(Some(part)) if part.filename.is_some() => {
+                        meta.filename = part.filename;
+                        break crate::push(store, name, &meta, &mut parts).map_err(Error::from);
+                    }
+                    Ok(Some(_)) => continue,
+                    Ok(None) => return Err(error(400, "there's no file in the multipart body")),
+                    Err(e) => break Err(error(400, format!("invalid multipart body: {e}"))),
+                }
+            }
+        }
+    };
+    // whatever went wrong while reading it
+    if body.exceeded {
+        return Err(too_large());
+    }
+    let stash = pushed?;
+    crate::quota::enforce(store, config, &stash, None)?;
+    let pushed = Pushed {
+        id: stash.id().to_string(),
+    };
+    Ok(json_response(201, json!(pushed)))
+}
+
+/// Reads at most `left` bytes, failing after that so that `exceeded` can tell
+/// the body was too large, rather than ending early like `take`.
+struct Limited<R> {
+    reader: R,
+    left: u64,
+    exceeded: bool,
+}
+
+impl<R: Read> Read for Limited<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        // one byte more to notice going over
+        let len = buf
+            .len()
+            .min(usize::try_from(self.left + 1).unwrap_or(usize::MAX));
+        let read = self.reader.read(&mut buf[..len])?;
+        match self.left.checked_sub(read as u64) {
+            Some(left) => {
+                self.left = left;
+                Ok(read)
+            }
+            None => {
+                self.exceeded = true;
+                Err(std::io::Error::other("the body is too large"))
+            }
+        }
+    }
+}
+
+/// Delete a stash, leaving it in the trash
+#[utoipa::path(
+    delete,
+    path = "/stashes/{id}",
+    params(
+        ("id" = String, Path, description = "`name:index`, or just the name for the newest one"),
+        ("If-Match" = Option<String>, Header, description = "The `ETag` that `GET` responded with, to only delete that stash"),
+    ),
+    responses(
+        (status = 204, description = "The stash was deleted"),
+        (status = 404, description = "There is no such stash", body = String),
+        (status = 409, description = "The stash is locked", body = String),
+        (status = 412, description = "The id is now another stash than the one of the `ETag`", body = String),
+    )
+)]
+fn delete(
+    store: &dyn StorageBackend,
+    id: &str,
+    if_match: Option<String>,
+) -> Result<Response<Body>, Error> {
+    let stash = get(store, id)?;
+    if if_match.is_some_and(|if_match| if_match != etag(&stash)) {
+        return Err(error(
+            412,
+            format!(
+                "Stash {} is another stash by now, it wasn't deleted",
+                stash.id()
+            ),
+        ));
+    }
+    if stash.locked {
+        return Err(error(409, format!("Stash {} is locked", stash.id())));
+    }
+    crate::delete_stash(store, Some(stash))?;
+    Ok(Response::empty(204).with_data(Box::new(std::io::empty()) as Body, Some(0)))
+}
+
+/// The stashes matching the query of `GET /stashes`, newest first.
+fn matching(store: &dyn StorageBackend, query: &Query) -> Result<Vec<Data>, Error> {
+    let invalid = |key: &str, e: &dyn std::fmt::Display| error(400, format!("{key}: {e}"));
+    let parse_time = |key, value: &Option<String>| {
+        value
+            .as_deref()
+            .map(|value| crate::parse_time(value).map_err(|e| invalid(key, &e)))
+            .transpose()
+    };
+    let parse_size = |key, value: &Option<String>| {
+        value
+            .as_deref()
+            .map(|value| crate::parse_size(value).map_err(|e| invalid(key, &e)))
+            .transpose()
+    };
+    let filter = crate::ListFilter {
+        pattern: query
+            .name
+            .as_deref()
+            .map(|name| glob::Pattern::new(name).map_err(|e| invalid("name", &e)))
+            .transpose()?,
+        regex: query
+            .regex
+            .as_deref()
+            .map(|regex| regex::Regex::new(regex).map_err(|e| invalid("regex", &e)))
+            .transpose()?,
+        since: parse_time("since", &query.since)?,
+        until: parse_time("until", &query.until)?,
+        min_size: parse_size("min_size", &query.min_size)?,
+        max_size: parse_size("max_size", &query.max_size)?,
+        tags: query.tag.clone(),
+        ..Default::default()
+    };
+    let contents = query
+        .q
+        .as_deref()
+        .map(|q| regex::bytes::Regex::new(q).map_err(|e| invalid("q", &e)))
+        .transpose()?;
+
+    let stashes = filter.apply(store, Data::detect(store)?)?;
+    let Some(regex) = contents else {
+        return Ok(stashes);
+    };
+    let mut matching = vec![];
+    for stash in stashes {
+        // like `show`, these aren't read on behalf of a client
+        if crate::withheld(store, &stash, false)? || store.meta(&stash)?.encrypted {
+            continue;
+        }
+        if crate::grep::matches(store, &stash, &regex)? {
+            matching.push(stash);
+        }
+    }
+    Ok(matching)
+}
+
+fn parse_query(query: &str) -> Result<Query, Error> {
+    let mut parsed = Query::default();
+    for (key, value) in query_pairs(query)? {
+        let field = match key.as_str() {
+            "name" => &mut parsed.name,
+            "regex" => &mut parsed.regex,
+            "since" => &mut parsed.since,
+            "until" => &mut parsed.until,
+            "min_size" => &mut parsed.min_size,
+            "max_size" => &mut parsed.max_size,
+            "q" => &mut parsed.q,
+            "tag" => {
+                parsed.tag.push(value);
+                continue;
+            }
+            _ => return Err(error(400, format!("unknown query parameter {key}"))),
+        };
+        *field = Some(value);
+    }
+    Ok(parsed)
+}
+
+/// The key-value pairs of a query string, decoded.
+fn query_pairs(query: &str) -> Result<Vec<(String, String)>, Error> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            // forms encode spaces as `+`
+            let decode = |s: &str| percent_decode(&s.replace('+', " "));
+            Ok((decode(key)?, decode(value)?))
+        })
+        .collect()
+}
+
+/// The url without the query.
+fn path(url: &str) -> &str {
+    url.split_once('?').map_or(url, |(path, _)| path)
+}
+
+fn get(store: &dyn StorageBackend, id: &str) -> Result<Data, Error> {
+    let id: StashId = id.parse().unwrap();
+    Data::get(store, &id)?.ok_or_else(|| error(404, format!("Stash {id} does not exist")))
+}
+
+/// Tells stashes apart even once pushes and pops have moved them to other ids,
+/// as the sequence number is never reused.
+fn etag(stash: &Data) -> String {
+    format!("\"{}\"", stash.seq)
+}
+
+fn if_match(request: &Request) -> Option<String> {
+    request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv("If-Match"))
+        .map(|header| header.value.as_str().trim().to_owned())
+}
+
+fn json_response(status: u16, value: Value) -> Response<Body> {
+    respond_with(status, "application/json", format!("{value}\n"))
+}
+
+fn respond_with(status: u16, content_type: &str, body: String) -> Response<Body> {
+    let length = body.len();
+    Response::empty(status)
+        .with_data(
+            Box::new(Cursor::new(body.into_bytes())) as Body,
+            Some(length),
+        )
+        .with_header(header("Content-Type", content_type))
+}
+
+fn header(field: &str, value: &str) -> Header {
+    Header::from_bytes(field.as_bytes(), value.as_bytes()).expect("a valid header")
+}
+
+/// Keeps slashes, like ids and names are expected in paths, everything special is
+/// escaped.
+pub fn percent_encode(id: &str) -> String {
+    id.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' | b':' => {
+                char::from(byte).to_string()
+            }
+            byte => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+fn percent_decode(s: &str) -> Result<String, Error> {
+    let mut decoded = vec![];
+    let mut bytes = s.bytes();
+    while let Some(byte) = bytes.next() {
+        if byte != b'%' {
+            decoded.push(byte);
+            continue;
+        }
+        let hex: String = bytes.by_ref().take(2).map(char::from).collect();
+        let byte = u8::from_str_radix(&hex, 16)
+            .map_err(|_| error(400, format!("invalid percent-encoding in {s}")))?;
+        decoded.push(byte);
+    }
+    String::from_utf8(decoded).map_err(|_| error(400, format!("{s} isn't valid UTF-8")))
+}
+
+/// A random token for when none is configured.
+pub fn generate_token() -> Result<String> {
+    let bytes: [u8; 16] = crate::random_bytes()
+        .map_err(|e| eyre!("couldn't generate a token, pass one with --token: {e}"))?;
+    Ok(bytes.iter().map(|byte| format!("{byte:02x}")).collect())
+}
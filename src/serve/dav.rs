@@ -0,0 +1,185 @@
+//! A read-only WebDAV view of the store under `/dav/`, for file managers and
+//! editors. Names are collections, `logs/build` is `build` in `logs`, and hold
+//! their stashes as resources named by index, `0` for the newest. Unnamed
+//! stashes are at the top.
+//!
+//! WebDAV clients tend to only do basic auth, so the token is also taken as its
+//! password, with any user.
+
+use std::fmt::Write;
+
+use tiny_http::{Request, Response};
+
+use super::{error, header, percent_decode, respond_with, Body, Error};
+use crate::storage::{Data, StorageBackend};
+
+pub const PREFIX: &str = "/dav";
+
+/// What a path under `/dav` is.
+enum Entry<'a> {
+    Collection(String),
+    Stash(&'a Data),
+}
+
+pub fn handle(store: &dyn StorageBackend, request: &Request) -> Result<Response<Body>, Error> {
+    let path = super::path(request.url());
+    let path = percent_decode(path.strip_prefix(PREFIX).unwrap_or(path))?;
+    let path = path.trim_matches('/');
+    match request.method().as_str() {
+        "OPTIONS" => Ok(
+            respond_with(200, "text/plain; charset=utf-8", String::new())
+                .with_header(header("DAV", "1"))
+                .with_header(header("Allow", "OPTIONS, PROPFIND, GET, HEAD")),
+        ),
+        "PROPFIND" => {
+            let depth = request
+                .headers()
+                .iter()
+                .find(|header| header.field.equiv("Depth"))
+                .map(|header| header.value.as_str().to_owned());
+            let stashes = Data::detect(store)?;
+            let entry = find(&stashes, path)?;
+            // `infinity` would mean the whole store, that's cut down to what's needed
+            // to browse it
+            let children = match &entry {
+                Entry::Collection(name) if depth.as_deref() != Some("0") => {
+                    children(&stashes, name)
+                }
+                _ => vec![],
+            };
+            let mut entries = vec![(path.to_owned(), entry)];
+            entries.extend(children);
+            let mut xml = String::from(
+                "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<D:multistatus xmlns:D=\"DAV:\">\n",
+            );
+            for (path, entry) in &entries {
+                properties(&mut xml, path, entry)?;
+            }
+            xml.push_str("</D:multistatus>\n");
+            Ok(respond_with(207, "application/xml; charset=utf-8", xml))
+        }
+        "GET" | "HEAD" => match find(&Data::detect(store)?, path)? {
+            Entry::Stash(stash) => super::show(store, &stash.id().to_string()),
+            Entry::Collection(_) => Err(error(405, format!("/{path} is a collection"))),
+        },
+        method => Err(error(
+            405,
+            format!("{method} isn't allowed in the WebDAV view"),
+        )),
+    }
+}
+
+/// The stash or collection at `path`, which is `<name>/<index>` for stashes.
+fn find<'a>(stashes: &'a [Data], path: &str) -> Result<Entry<'a>, Error> {
+    let (name, last) = path.rsplit_once('/').unwrap_or(("", path));
+    if let Ok(index) = last.parse::<usize>() {
+        let stash = stashes
+            .iter()
+            .find(|stash| stash.name == name && stash.index == index);
+        if let Some(stash) = stash {
+            return Ok(Entry::Stash(stash));
+        }
+    }
+    let is_collection = path.is_empty()
+        || stashes.iter().any(|stash| {
+            stash.name == path
+                || stash
+                    .name
+                    .strip_prefix(path)
+                    .is_some_and(|rest| rest.starts_with('/'))
+        });
+    match is_collection {
+        true => Ok(Entry::Collection(path.to_owned())),
+        false => Err(error(404, format!("/{path} does not exist"))),
+    }
+}
+
+/// The stashes and collections directly in the collection `name`, by path.
+fn children<'a>(stashes: &'a [Data], name: &str) -> Vec<(String, Entry<'a>)> {
+    let join = |child: &str| match name {
+        "" => child.to_owned(),
+        _ => format!("{name}/{child}"),
+    };
+    let mut collections = std::collections::BTreeSet::new();
+    let mut children = vec![];
+    for stash in stashes {
+        if stash.name == name {
+            children.push((join(&stash.index.to_string()), Entry::Stash(stash)));
+            continue;
+        }
+        let rest = match name {
+            "" => Some(stash.name.as_str()),
+            _ => stash
+                .name
+                .strip_prefix(name)
+                .and_then(|rest| rest.strip_prefix('/')),
+        };
+        if let Some(rest) = rest {
+            let child = rest.split('/').next().unwrap_or(rest);
+            collections.insert(join(child));
+        }
+    }
+    children.extend(
+        collections
+            .into_iter()
+            .map(|path| (path.clone(), Entry::Collection(path))),
+    );
+    children
+}
+
+/// Appends the `<D:response>` describing the entry at `path`.
+fn properties(xml: &mut String, path: &str, entry: &Entry) -> Result<(), Error> {
+    let mut href = format!("{PREFIX}/{}", super::percent_encode(path));
+    let name = match path.rsplit('/').next() {
+        Some("") | None => "stash",
+        Some(name) => name,
+    };
+    let mut props = format!("<D:displayname>{}</D:displayname>", escape(name));
+    match entry {
+        Entry::Collection(_) => {
+            if !href.ends_with('/') {
+                href.push('/');
+            }
+            props.push_str("<D:resourcetype><D:collection/></D:resourcetype>");
+        }
+        Entry::Stash(stash) => {
+            let _ = write!(
+                props,
+                "<D:resourcetype/><D:getcontentlength>{}</D:getcontentlength>\
+                 <D:creationdate>{}</D:creationdate>\
+                 <D:getlastmodified>{}</D:getlastmodified>",
+                stash.size,
+                crate::format_time(stash.created)?,
+                http_date(stash.created)?,
+            );
+        }
+    }
+    let _ = writeln!(
+        xml,
+        "<D:response><D:href>{}</D:href><D:propstat><D:prop>{props}</D:prop>\
+         <D:status>HTTP/1.1 200 OK</D:status></D:propstat></D:response>",
+        escape(&href)
+    );
+    Ok(())
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Like `Sun, 06 Nov 1994 08:49:37 GMT`.
+fn http_date(timestamp: std::time::SystemTime) -> eyre::Result<String> {
+    let format = time::macros::format_description!(
+        "[weekday repr:short], [day] [month repr:short] [year] [hour]:[minute]:[second] GMT"
+    );
+    Ok(time::OffsetDateTime::from(timestamp).format(&format)?)
+}
+
+/// Whether the url is in the WebDAV view.
+pub fn is_dav(url: &str) -> bool {
+    let path = super::path(url);
+    path == PREFIX || path.starts_with(&format!("{PREFIX}/"))
+}
@@ -0,0 +1,153 @@
+//! Reading `multipart/form-data` bodies as they arrive, for pushing files from
+//! HTML forms or `curl -F file=@big.log`. Only as much of the body as it takes to
+//! find the boundaries is kept in memory.
+
+use std::io::{Error, ErrorKind, Read};
+
+/// How many bytes are read from the body at once.
+const READ_SIZE: usize = 64 * 1024;
+/// Longer header lines are refused rather than buffered.
+const MAX_LINE: usize = 8 * 1024;
+
+/// The boundary of a `multipart/form-data` content type, `None` for other
+/// content types.
+pub fn boundary(content_type: &str) -> Option<String> {
+    let (mime, params) = content_type.split_once(';')?;
+    if !mime.trim().eq_ignore_ascii_case("multipart/form-data") {
+        return None;
+    }
+    params.split(';').find_map(|param| {
+        let (key, value) = param.split_once('=')?;
+        match key.trim().eq_ignore_ascii_case("boundary") {
+            true => Some(value.trim().trim_matches('"').to_owned()),
+            false => None,
+        }
+    })
+}
+
+/// The parts of a multipart body, one after the other. Reading it reads the
+/// body of the current part.
+pub struct Multipart<'a> {
+    body: &'a mut dyn Read,
+    /// `\r\n--<boundary>`, which ends every part
+    delimiter: Vec<u8>,
+    /// What's been read from `body` but not handed out yet
+    buf: Vec<u8>,
+}
+
+/// What the headers of a part tell about it.
+pub struct Part {
+    /// From its `Content-Disposition`, set for uploaded files
+    pub filename: Option<String>,
+}
+
+impl<'a> Multipart<'a> {
+    pub fn new(body: &'a mut dyn Read, boundary: &str) -> Multipart<'a> {
+        Multipart {
+            body,
+            delimiter: format!("\r\n--{boundary}").into_bytes(),
+            // so that the first boundary, at the very start, is a delimiter as well
+            buf: b"\r\n".to_vec(),
+        }
+    }
+
+    /// Skips the rest of the current part, or the preamble before the first one,
+    /// and reads the headers of the next part. `None` after the last one.
+    pub fn next_part(&mut self) -> std::io::Result<Option<Part>> {
+        std::io::copy(self, &mut std::io::sink())?;
+        self.buf.drain(..self.delimiter.len());
+        self.fill(2)?;
+        if self.buf.starts_with(b"--") {
+            return Ok(None);
+        }
+        // the rest of the boundary's line
+        self.read_line()?;
+        let mut part = Part { filename: None };
+        loop {
+            let line = self.read_line()?;
+            if line.is_empty() {
+                return Ok(Some(part));
+            }
+            let line = String::from_utf8_lossy(&line);
+            if let Some((name, value)) = line.split_once(':') {
+                if name.trim().eq_ignore_ascii_case("content-disposition") {
+                    part.filename = filename(value);
+                }
+            }
+        }
+    }
+
+    /// Reads from the body until `buf` holds at least `len` bytes. Fails if it
+    /// ends before, which it only may after the closing boundary.
+    fn fill(&mut self, len: usize) -> std::io::Result<()> {
+        let mut chunk = vec![0; READ_SIZE];
+        while self.buf.len() < len {
+            let read = self.body.read(&mut chunk)?;
+            if read == 0 {
+                return Err(Error::new(
+                    ErrorKind::UnexpectedEof,
+                    "the multipart body ends without its closing boundary",
+                ));
+            }
+            self.buf.extend_from_slice(&chunk[..read]);
+        }
+        Ok(())
+    }
+
+    /// The next line, without the `\r\n`.
+    fn read_line(&mut self) -> std::io::Result<Vec<u8>> {
+        loop {
+            if let Some(end) = find(&self.buf, b"\r\n") {
+                let line = self.buf[..end].to_vec();
+                self.buf.drain(..end + 2);
+                return Ok(line);
+            }
+            if self.buf.len() > MAX_LINE {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "a multipart header line is too long",
+                ));
+            }
+            self.fill(self.buf.len() + 1)?;
+        }
+    }
+}
+
+impl Read for Multipart<'_> {
+    /// Returns 0 at the end of the current part.
+    fn read(&mut self, out: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            // everything up to here is contents
+            let end = match find(&self.buf, &self.delimiter) {
+                Some(end) => end,
+                // the end may hold the start of the delimiter
+                None => self.buf.len().saturating_sub(self.delimiter.len() - 1),
+            };
+            if end > 0 || self.buf.starts_with(&self.delimiter) {
+                let len = end.min(out.len());
+                out[..len].copy_from_slice(&self.buf[..len]);
+                self.buf.drain(..len);
+                return Ok(len);
+            }
+            self.fill(self.buf.len() + 1)?;
+        }
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// The `filename` of a `Content-Disposition` value like
+/// `form-data; name="file"; filename="build.log"`.
+fn filename(disposition: &str) -> Option<String> {
+    disposition.split(';').find_map(|param| {
+        let (key, value) = param.split_once('=')?;
+        match key.trim() == "filename" {
+            true => Some(value.trim().trim_matches('"').to_owned()),
+            false => None,
+        }
+    })
+}
@@ -0,0 +1,98 @@
+//! What `stash shell-init` prints for the shell to `eval`: completions, and with
+//! `--capture` the hooks that record the output of every command, see
+//! `capture.bash` and `capture.zsh`, for `stash last-output`.
+
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+
+use camino::Utf8Path as Path;
+use eyre::{eyre, Result};
+
+use crate::meta::Meta;
+use crate::storage::{Data, StorageBackend};
+
+/// How much of the end of a command's output `capture` keeps.
+const MAX_CAPTURE: usize = 1024 * 1024;
+
+#[derive(clap::ArgEnum, Debug, Clone, Copy)]
+pub enum Shell {
+    Bash,
+    Zsh,
+}
+
+pub fn init(shell: Shell, capture: bool, command: &mut clap::Command) -> Result<()> {
+    let mut stdout = std::io::stdout().lock();
+    let (generator, hooks) = match shell {
+        Shell::Bash => (clap_complete::Shell::Bash, include_str!("capture.bash")),
+        Shell::Zsh => (clap_complete::Shell::Zsh, include_str!("capture.zsh")),
+    };
+    clap_complete::generate(generator, command, "stash", &mut stdout);
+    if capture {
+        stdout.write_all(hooks.as_bytes())?;
+    }
+    Ok(())
+}
+
+/// Copies stdin to stdout and then leaves the last `MAX_CAPTURE` bytes of it and
+/// the command line it's the output of in `dir`, for `last_output`.
+pub fn capture(dir: &Path, command: &str) -> Result<()> {
+    // Ctrl-C is for the command, what it printed until then should be kept
+    #[cfg(unix)]
+    // SAFETY: no handler is installed, it's only ignored
+    unsafe {
+        libc::signal(libc::SIGINT, libc::SIG_IGN);
+    }
+    let mut stdin = std::io::stdin().lock();
+    let mut stdout = std::io::stdout().lock();
+    let mut kept = VecDeque::new();
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let read = match stdin.read(&mut buf) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+        // the terminal going away mustn't break the command's output
+        let _ = stdout.write_all(&buf[..read]).and_then(|()| stdout.flush());
+        kept.extend(&buf[..read]);
+        let excess = kept.len().saturating_sub(MAX_CAPTURE);
+        kept.drain(..excess);
+    }
+
+    fs_err::create_dir_all(dir)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs_err::set_permissions(dir, std::fs::Permissions::from_mode(0o700))?;
+    }
+    // whole, so that `last-output` never sees half of it
+    for (file, contents) in [
+        ("output", kept.make_contiguous() as &[u8]),
+        ("command", command.as_bytes()),
+    ] {
+        let tmp = dir.join(format!("{file}.tmp"));
+        fs_err::write(&tmp, contents)?;
+        fs_err::rename(&tmp, dir.join(file))?;
+    }
+    Ok(())
+}
+
+/// Pushes the output of the last command `capture` recorded in `dir`, with the
+/// command line as its message.
+pub fn last_output(store: &dyn StorageBackend, dir: &Path, name: &str) -> Result<Data> {
+    let output = match fs_err::read(dir.join("output")) {
+        Ok(output) => output,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(eyre!(
+                "no command's output has been recorded in this shell yet"
+            ))
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let meta = Meta {
+        message: fs_err::read_to_string(dir.join("command")).ok(),
+        ..Meta::captured()
+    };
+    crate::push(store, name, &meta, &mut output.as_slice())
+}
@@ -0,0 +1,205 @@
+//! A client for the REST API of `stash serve`, which describes it in OpenAPI at
+//! `GET /openapi.json`. The CLI uses it for `--remote`.
+
+use std::io::Read;
+
+use eyre::{eyre, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::storage::Data;
+
+/// A stash as `GET /stashes` lists it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct Summary {
+    /// `name:index`, or just the index for unnamed stashes
+    pub id: String,
+    pub name: String,
+    /// Position in the stack of its name, 0 is the newest
+    pub index: usize,
+    /// In bytes
+    pub size: u64,
+    /// When it was pushed, in RFC3339
+    pub created: String,
+    pub locked: bool,
+}
+
+impl Summary {
+    pub(crate) fn new(stash: &Data) -> Result<Summary> {
+        Ok(Summary {
+            id: stash.id().to_string(),
+            name: stash.name.clone(),
+            index: stash.index,
+            size: stash.size,
+            created: crate::format_time(stash.created)?,
+            locked: stash.locked,
+        })
+    }
+}
+
+/// What `POST /stashes/<name>` responds with.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct Pushed {
+    /// Of the new stash
+    pub id: String,
+}
+
+/// Narrows down `GET /stashes`, like the options of `stash list` and `stash grep`.
+/// Every given condition must hold.
+#[derive(Debug, Default, Clone, Serialize, Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct Query {
+    /// Name of the stashes, or a glob pattern for it
+    pub name: Option<String>,
+    /// A regex their names have to match
+    pub regex: Option<String>,
+    /// Created after this time, given as RFC3339, a date like 2024-05-01 or an
+    /// age like 30m, 12h, 7d
+    pub since: Option<String>,
+    /// Created before this time, in the same formats as `since`
+    pub until: Option<String>,
+    /// In bytes or with a K, M or G suffix
+    pub min_size: Option<String>,
+    /// In the same format as `min_size`
+    pub max_size: Option<String>,
+    /// Tags they all need to have
+    #[serde(default)]
+    pub tag: Vec<String>,
+    /// A regex for a line of their contents, never matches secret or encrypted stashes
+    pub q: Option<String>,
+}
+
+impl Query {
+    fn pairs(&self) -> Vec<(&str, &str)> {
+        let optional = [
+            ("name", &self.name),
+            ("regex", &self.regex),
+            ("since", &self.since),
+            ("until", &self.until),
+            ("min_size", &self.min_size),
+            ("max_size", &self.max_size),
+            ("q", &self.q),
+        ];
+        let mut pairs: Vec<_> = optional
+            .into_iter()
+            .filter_map(|(key, value)| Some((key, value.as_deref()?)))
+            .collect();
+        pairs.extend(self.tag.iter().map(|tag| ("tag", tag.as_str())));
+        pairs
+    }
+}
+
+/// The contents of a stash, as `Client::get` streams them.
+pub struct Contents {
+    /// The `ETag` of the stash, for `Client::delete`
+    pub version: Option<String>,
+    reader: Box<dyn Read + Send>,
+}
+
+impl Read for Contents {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.reader.read(buf)
+    }
+}
+
+/// Talks to a `stash serve` at `url`, like `http://127.0.0.1:7877`.
+pub struct Client {
+    url: String,
+    token: String,
+    agent: ureq::Agent,
+}
+
+impl Client {
+    pub fn new(url: &str, token: &str) -> Client {
+        Client {
+            url: url.trim_end_matches('/').to_owned(),
+            token: token.to_owned(),
+            // errors carry the server's message in the body, that's read below
+            agent: ureq::Agent::config_builder()
+                .http_status_as_error(false)
+                .build()
+                .into(),
+        }
+    }
+
+    /// The stashes matching `query`, newest first.
+    pub fn list(&self, query: &Query) -> Result<Vec<Summary>> {
+        let response = self
+            .agent
+            .get(self.stashes_url(None))
+            .header("Authorization", self.authorization())
+            .query_pairs(query.pairs())
+            .call()?;
+        Ok(checked(response)?.body_mut().read_json()?)
+    }
+
+    /// The contents of the stash.
+    pub fn get(&self, id: &str) -> Result<Contents> {
+        let response = checked(
+            self.agent
+                .get(self.stashes_url(Some(id)))
+                .header("Authorization", self.authorization())
+                .call()?,
+        )?;
+        let version = response
+            .headers()
+            .get("ETag")
+            .and_then(|etag| etag.to_str().ok())
+            .map(str::to_owned);
+        Ok(Contents {
+            version,
+            reader: Box::new(response.into_body().into_reader()),
+        })
+    }
+
+    /// Pushes `contents` as the newest stash of `name` and returns its id.
+    pub fn push(&self, name: &str, contents: &mut dyn Read) -> Result<String> {
+        let response = self
+            .agent
+            .post(self.stashes_url(Some(name)))
+            .header("Authorization", self.authorization())
+            .send(ureq::SendBody::from_reader(contents))?;
+        let pushed: Pushed = checked(response)?.body_mut().read_json()?;
+        Ok(pushed.id)
+    }
+
+    /// Deletes the stash, which leaves it in the server's trash. With the
+    /// `version` of `Contents`, only if the id still is that stash, so that
+    /// what's deleted is what was fetched even if someone pushed in between.
+    pub fn delete(&self, id: &str, version: Option<&str>) -> Result<()> {
+        let mut request = self
+            .agent
+            .delete(self.stashes_url(Some(id)))
+            .header("Authorization", self.authorization());
+        if let Some(version) = version {
+            request = request.header("If-Match", version);
+        }
+        checked(request.call()?)?;
+        Ok(())
+    }
+
+    fn stashes_url(&self, id: Option<&str>) -> String {
+        match id {
+            Some(id) => format!("{}/stashes/{}", self.url, crate::serve::percent_encode(id)),
+            None => format!("{}/stashes", self.url),
+        }
+    }
+
+    fn authorization(&self) -> String {
+        format!("Bearer {}", self.token)
+    }
+}
+
+/// The response if it's a success, else the server's message as an error.
+fn checked(
+    mut response: ureq::http::Response<ureq::Body>,
+) -> Result<ureq::http::Response<ureq::Body>> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let message = response.body_mut().read_to_string().unwrap_or_default();
+    Err(eyre!(
+        "the server responded with {status}: {}",
+        message.trim()
+    ))
+}
@@ -0,0 +1,113 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Identifies a stash by its name and its position in that name's stack.
+///
+/// Written as `name:index`, where index 0 is the newest stash of that name and
+/// may be left out. A bare number addresses the stack of unnamed stashes.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StashId {
+    pub name: String,
+    pub index: usize,
+}
+
+impl StashId {
+    pub fn newest(name: &str) -> StashId {
+        StashId {
+            name: name.to_owned(),
+            index: 0,
+        }
+    }
+}
+
+fn parse_index(s: &str) -> Option<usize> {
+    match !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit()) {
+        true => s.parse().ok(),
+        false => None,
+    }
+}
+
+impl FromStr for StashId {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some((name, index)) = s.rsplit_once(':') {
+            if let Some(index) = parse_index(index) {
+                return Ok(StashId {
+                    name: name.to_owned(),
+                    index,
+                });
+            }
+        }
+        if let Some(index) = parse_index(s) {
+            return Ok(StashId {
+                name: String::new(),
+                index,
+            });
+        }
+        Ok(StashId::newest(s))
+    }
+}
+
+impl fmt::Display for StashId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name.is_empty() {
+            true => write!(f, "{}", self.index),
+            false => write!(f, "{}:{}", self.name, self.index),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(name: &str, index: usize) -> StashId {
+        StashId {
+            name: name.to_owned(),
+            index,
+        }
+    }
+
+    fn parse(s: &str) -> StashId {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn names_and_indices() {
+        assert_eq!(parse("logs"), id("logs", 0));
+        assert_eq!(parse("logs:0"), id("logs", 0));
+        assert_eq!(parse("logs:12"), id("logs", 12));
+        assert_eq!(parse("logs/build:1"), id("logs/build", 1));
+        // only the last colon separates the index
+        assert_eq!(parse("host:8080:1"), id("host:8080", 1));
+    }
+
+    #[test]
+    fn unnamed_stashes() {
+        assert_eq!(parse(""), id("", 0));
+        assert_eq!(parse("0"), id("", 0));
+        assert_eq!(parse("3"), id("", 3));
+        assert_eq!(parse(":3"), id("", 3));
+    }
+
+    #[test]
+    fn anything_else_is_a_name() {
+        assert_eq!(parse("logs:"), id("logs:", 0));
+        assert_eq!(parse("logs:x"), id("logs:x", 0));
+        assert_eq!(parse("logs:-1"), id("logs:-1", 0));
+        assert_eq!(parse("logs:+1"), id("logs:+1", 0));
+        assert_eq!(parse("logs: 1"), id("logs: 1", 0));
+        assert_eq!(parse("-1"), id("-1", 0));
+        let too_large = format!("logs:{}0", usize::MAX);
+        assert_eq!(parse(&too_large), id(&too_large, 0));
+    }
+
+    #[test]
+    fn displays_as_parsed() {
+        for s in ["logs:0", "logs:12", "host:8080:1", "3"] {
+            assert_eq!(parse(s).to_string(), s);
+        }
+        assert_eq!(parse("logs").to_string(), "logs:0");
+    }
+}
@@ -0,0 +1,98 @@
+use std::collections::BTreeMap;
+use std::time::SystemTime;
+
+use eyre::Result;
+
+use crate::storage::{Data, StorageBackend};
+
+/// Stashes of one name.
+struct Stack {
+    count: usize,
+    size: u64,
+    newest: SystemTime,
+    oldest: SystemTime,
+}
+
+/// Prints how many stashes there are and how much space they take, in total and
+/// by name, followed by the `top` largest stashes.
+pub fn print(store: &dyn StorageBackend, top: usize) -> Result<()> {
+    let stashes = Data::detect(store)?;
+    let total: u64 = stashes.iter().map(|stash| stash.size).sum();
+    println!("{} stash(es), {}", stashes.len(), crate::human_size(total));
+    let trashed = store.trashed()?;
+    if !trashed.is_empty() {
+        let trash_size = trashed.iter().map(|(stash, _)| stash.size).sum();
+        println!(
+            "{} in the trash, {}",
+            trashed.len(),
+            crate::human_size(trash_size)
+        );
+    }
+    if stashes.is_empty() {
+        return Ok(());
+    }
+
+    let mut stacks = BTreeMap::new();
+    for stash in &stashes {
+        let stack = stacks.entry(stash.name.as_str()).or_insert(Stack {
+            count: 0,
+            size: 0,
+            newest: stash.created,
+            oldest: stash.created,
+        });
+        stack.count += 1;
+        stack.size += stash.size;
+        stack.newest = stack.newest.max(stash.created);
+        stack.oldest = stack.oldest.min(stash.created);
+    }
+    let rows: Vec<_> = stacks
+        .iter()
+        .map(|(name, stack)| {
+            [
+                match name.is_empty() {
+                    true => "(unnamed)".to_owned(),
+                    false => name.to_string(),
+                },
+                stack.count.to_string(),
+                crate::human_size(stack.size),
+                crate::format_age(stack.newest),
+                crate::format_age(stack.oldest),
+            ]
+        })
+        .collect();
+    let header = ["name", "count", "size", "newest", "oldest"].map(str::to_owned);
+    let mut widths = header.clone().map(|column| column.len());
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    println!();
+    for row in std::iter::once(&header).chain(&rows) {
+        let [name, count, size, newest, oldest] = row;
+        let [w0, w1, w2, w3, _] = widths;
+        println!("{name:w0$}  {count:>w1$}  {size:>w2$}  {newest:w3$}  {oldest}");
+    }
+
+    let mut largest: Vec<_> = stashes.iter().collect();
+    largest.sort_by_key(|stash| std::cmp::Reverse(stash.size));
+    largest.truncate(top);
+    if largest.is_empty() {
+        return Ok(());
+    }
+    let id_width = largest
+        .iter()
+        .map(|stash| stash.id().to_string().len())
+        .max()
+        .unwrap_or(0);
+    println!();
+    println!("largest");
+    for stash in largest {
+        println!(
+            "{:id_width$}  {:>10}",
+            stash.id().to_string(),
+            crate::human_size(stash.size)
+        );
+    }
+    Ok(())
+}
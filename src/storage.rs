@@ -0,0 +1,1165 @@
+use std::io::{Read, Seek, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime};
+
+use camino::Utf8PathBuf as PathBuf;
+use eyre::{eyre, Result};
+use fs_err::PathExt;
+use serde::{Deserialize, Serialize};
+
+use crate::checksum::HashingReader;
+use crate::interrupt::TempFile;
+use crate::meta::Meta;
+use crate::stash_id::StashId;
+
+/// A stash as seen by a storage backend.
+#[derive(Debug, Clone)]
+pub struct Data {
+    pub name: String,
+    /// Position in the stack of stashes with the same name, 0 being the newest.
+    /// Backends leave this at 0, it's filled in by `Data::detect`.
+    pub index: usize,
+    /// Monotonically increasing across the whole store. Later pushes get higher
+    /// numbers, so this decides the order of stashes, not the timestamps.
+    pub seq: u64,
+    pub created: SystemTime,
+    /// Size of the contents in bytes, as they are read, not as they are stored
+    pub size: u64,
+    /// Locked stashes must not be modified or deleted.
+    pub locked: bool,
+    /// When the stash expires, see `Meta::expires`
+    pub expires: Option<SystemTime>,
+}
+
+impl Data {
+    /// Expired stashes are treated as if they were gone. Locked ones don't expire.
+    pub fn expired(&self) -> bool {
+        !self.locked
+            && self
+                .expires
+                .is_some_and(|expires| expires <= SystemTime::now())
+    }
+
+    pub fn id(&self) -> StashId {
+        StashId {
+            name: self.name.clone(),
+            index: self.index,
+        }
+    }
+
+    // this is also the internal stash_id
+    pub fn filename(&self) -> String {
+        filename(self.seq, &self.name)
+    }
+}
+
+pub fn filename(seq: u64, name: &str) -> String {
+    format!("{seq}_{}", encode_name(name))
+}
+
+/// Inverse of `filename`.
+pub fn parse_filename(filename: &str) -> Option<(u64, String)> {
+    let (seq, name) = filename.split_once('_')?;
+    match seq.bytes().all(|b| b.is_ascii_digit()) {
+        true => Some((seq.parse().ok()?, decode_name(name))),
+        false => None,
+    }
+}
+
+// Names may be hierarchical (`logs/build`), so slashes are escaped to keep
+// every stash a single file.
+pub fn encode_name(name: &str) -> String {
+    name.replace('%', "%25").replace('/', "%2F")
+}
+
+fn decode_name(encoded: &str) -> String {
+    encoded.replace("%2F", "/").replace("%25", "%")
+}
+
+/// Everything a subcommand needs from the place stashes are kept in.
+///
+/// Subcommands only talk to this trait, so adding a new place to keep stashes
+/// means implementing it once instead of touching every subcommand.
+pub trait StorageBackend: Send + Sync {
+    /// All stashes, in no particular order.
+    fn entries(&self) -> Result<Vec<Data>>;
+    /// The name of every stash, in no particular order.
+    /// Cheaper than `entries` as nothing has to be looked up per stash.
+    fn names(&self) -> Result<Vec<String>>;
+    /// All stashes in the stack `name`, in no particular order.
+    fn stack(&self, name: &str) -> Result<Vec<Data>> {
+        let mut stashes = self.entries()?;
+        stashes.retain(|stash| stash.name == name);
+        Ok(stashes)
+    }
+    /// The top of the stack `name`.
+    fn newest(&self, name: &str) -> Result<Option<Data>> {
+        Ok(self
+            .entries()?
+            .into_iter()
+            .filter(|stash| stash.name == name)
+            .max_by_key(|stash| stash.seq))
+    }
+    /// The contents, decoded if the stash is stored with an encoding.
+    fn reader(&self, stash: &Data) -> Result<Box<dyn Read>>;
+    /// The contents as they are stored.
+    fn raw_reader(&self, stash: &Data) -> Result<Box<dyn Read>>;
+    /// Like `reader`, but starting `offset` bytes into the stash.
+    fn reader_at(&self, stash: &Data, offset: u64) -> Result<Box<dyn Read>> {
+        let mut reader = self.reader(stash)?;
+        std::io::copy(&mut (&mut reader).take(offset), &mut std::io::sink())?;
+        Ok(reader)
+    }
+    /// Creates a stash with the given metadata on top of the stack `name`, holding
+    /// what `contents` yields, encoded according to `meta.encoding`.
+    /// The stash only becomes visible once `contents` is exhausted, so a failed or
+    /// interrupted push leaves nothing behind.
+    fn create(&self, name: &str, meta: &Meta, contents: &mut dyn Read) -> Result<Data>;
+    fn append(&self, stash: &Data) -> Result<Box<dyn Write>>;
+    /// Atomically swaps the contents of a stash for what `contents` yields.
+    /// Readers either see the old or the new contents, never a mix.
+    fn replace(&self, stash: &Data, contents: &mut dyn Read) -> Result<()>;
+    /// Moves an existing stash to the top of its stack, keeping its contents.
+    /// It counts as pushed now, `created` included.
+    fn touch(&self, stash: &Data) -> Result<()>;
+    /// Moves a stash to the stack `name`, where it keeps its place in the order
+    /// of pushes. Contents and timestamps are left alone.
+    fn rename(&self, stash: &Data, name: &str) -> Result<Data>;
+    fn set_locked(&self, stash: &Data, locked: bool) -> Result<()>;
+    /// The stash's metadata, or the default if it has none.
+    fn meta(&self, stash: &Data) -> Result<Meta>;
+    /// The stash's tags, for backends that can tell them without all of `meta`.
+    fn tags(&self, stash: &Data) -> Result<Vec<String>> {
+        Ok(self.meta(stash)?.tags)
+    }
+    fn set_meta(&self, stash: &Data, meta: &Meta) -> Result<()>;
+    /// Moves a stash to the trash, from where `restore` can bring it back.
+    fn remove(&self, stash: &Data) -> Result<()>;
+    /// The stashes in the trash and when they were removed, in no particular order.
+    fn trashed(&self) -> Result<Vec<(Data, SystemTime)>>;
+    /// Brings a stash back from the trash, to its old place in its stack.
+    fn restore(&self, stash: &Data) -> Result<Data>;
+    /// Deletes a stash in the trash for good.
+    fn purge(&self, stash: &Data) -> Result<()>;
+    /// Purges what has been in the trash for longer than `max_age`.
+    fn expire_trash(&self, max_age: Duration) -> Result<()> {
+        for (stash, removed) in self.trashed()? {
+            if removed.elapsed().is_ok_and(|age| age > max_age) {
+                self.purge(&stash)?;
+            }
+        }
+        Ok(())
+    }
+    /// Appends a line to the log of changes to the store.
+    fn log_event(&self, line: &str) -> Result<()>;
+    /// The event log from byte `offset` onwards.
+    fn events_since(&self, offset: u64) -> Result<Vec<u8>>;
+    /// Rebuilds whatever the backend keeps to find stashes quickly from the
+    /// stashes themselves.
+    fn reindex(&self) -> Result<()> {
+        Ok(())
+    }
+    /// Problems with how the stashes are stored, one line each.
+    /// With `repair`, they're fixed as far as that's possible without losing data.
+    fn fsck(&self, _repair: bool) -> Result<Vec<String>> {
+        Ok(vec![])
+    }
+}
+
+/// What `encoded` returns. Encoders only write their last bytes when they're
+/// finished, which `finish` reports the errors of. Dropping finishes them too,
+/// but can only warn if that fails.
+pub trait Finish: Write {
+    fn finish(self: Box<Self>) -> std::io::Result<()>;
+}
+
+impl<F: Finish + ?Sized> Finish for Box<F> {
+    fn finish(self: Box<Self>) -> std::io::Result<()> {
+        (*self).finish()
+    }
+}
+
+/// The file of a stash that's stored as it is.
+struct Plain<W>(W);
+
+impl<W: Write> Write for Plain<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl<W: Write> Finish for Plain<W> {
+    fn finish(mut self: Box<Self>) -> std::io::Result<()> {
+        self.0.flush()
+    }
+}
+
+/// Wraps the writer of a stash's file so that what's written is stored as `meta` says.
+pub fn encoded<'a>(meta: &Meta, file: Box<dyn Write + 'a>) -> Result<Box<dyn Finish + 'a>> {
+    let mut writer: Box<dyn Finish + 'a> = Box::new(Plain(file));
+    if meta.encrypted {
+        writer = crate::crypto::encryptor(writer)?;
+    }
+    if let Some(encoding) = meta.encoding {
+        writer = encoding.encoder(writer)?;
+    }
+    Ok(writer)
+}
+
+/// Options for creating a stash's file, only readable by the user if it's secret.
+fn file_options(meta: &Meta) -> fs_err::OpenOptions {
+    let mut options = fs_err::OpenOptions::new();
+    #[cfg(unix)]
+    if meta.secret {
+        use fs_err::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    #[cfg(not(unix))]
+    let _ = meta;
+    options
+}
+
+/// The reverse of `encoded`.
+pub fn decoded<'a>(meta: &Meta, file: Box<dyn Read + 'a>) -> Result<Box<dyn Read + 'a>> {
+    let mut reader = file;
+    if meta.encrypted {
+        reader = crate::crypto::decryptor(reader)?;
+    }
+    if let Some(encoding) = meta.encoding {
+        reader = encoding.decoder(reader)?;
+    }
+    Ok(reader)
+}
+
+/// Stashes as plain files in a directory on the local filesystem.
+/// Their metadata lives in json files of the same name in the `meta` subdirectory,
+/// half-written files in the `tmp` subdirectory and the event log in `log/events.ndjson`.
+///
+/// So that pushing, showing and popping the top of a stack doesn't have to look at
+/// every file, `heads` holds the next free sequence number and, per name, the
+/// sequence number of its newest stash.
+///
+/// Removed stashes are moved to `trash`, with their metadata and the time of
+/// removal in a json file next to them.
+///
+/// Listing stashes doesn't have to stat every file either: `index/stashes.json`
+/// keeps what `entries` returns and the tags. It's checked against the directory
+/// listing and rebuilt from the files if it's missing or out of date.
+#[derive(Clone)]
+pub struct LocalFs {
+    root: PathBuf,
+}
+
+/// What's kept of a stash's metadata while it's in the trash.
+#[derive(Serialize, Deserialize)]
+struct Trashed {
+    removed: SystemTime,
+    meta: Meta,
+}
+
+/// What the index keeps per stash. The position in the stack isn't kept, it's
+/// derived from the order of the sequence numbers.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct IndexEntry {
+    seq: u64,
+    name: String,
+    created: SystemTime,
+    size: u64,
+    locked: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expires: Option<SystemTime>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+}
+
+impl IndexEntry {
+    fn data(&self) -> Data {
+        Data {
+            name: self.name.clone(),
+            index: 0,
+            seq: self.seq,
+            created: self.created,
+            size: self.size,
+            locked: self.locked,
+            expires: self.expires,
+        }
+    }
+}
+
+/// Keeps the index and the checksum up to date with a stash that is being
+/// appended to, once it's complete.
+struct IndexedWriter {
+    store: LocalFs,
+    stash: Data,
+    writer: Option<Box<dyn Finish>>,
+}
+
+impl Write for IndexedWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.writer.as_mut().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.as_mut().unwrap().flush()
+    }
+}
+
+impl Drop for IndexedWriter {
+    fn drop(&mut self) {
+        if let Err(e) = self.writer.take().unwrap().finish() {
+            eprintln!("Warning: couldn't finish writing the stash: {e}");
+        }
+        if let Err(e) = self.store.rehash(&self.stash) {
+            eprintln!("Warning: couldn't update the checksum: {e}");
+        }
+        if let Err(e) = self
+            .store
+            .refresh_index(Some(&self.stash), Some(&self.stash))
+        {
+            eprintln!("Warning: couldn't update the index: {e}");
+        }
+    }
+}
+
+impl LocalFs {
+    pub fn new(root: PathBuf) -> Result<Self> {
+        fs_err::create_dir_all(root.join("meta"))?;
+        fs_err::create_dir_all(root.join("tmp"))?;
+        fs_err::create_dir_all(root.join("log"))?;
+        fs_err::create_dir_all(root.join("heads"))?;
+        fs_err::create_dir_all(root.join("index"))?;
+        fs_err::create_dir_all(root.join("trash"))?;
+        Ok(LocalFs { root })
+    }
+
+    /// Opens the store in `root` like `new`, first renaming the stashes of a store
+    /// from before sequence numbers, which are files named just like the stash,
+    /// to `<seq>_<name>`, numbered oldest first. Renaming keeps their creation
+    /// times. Only for data dirs such a store can be in, it would rename any file.
+    pub fn upgrade(root: PathBuf) -> Result<Self> {
+        // every store since sequence numbers were introduced has a meta dir
+        let legacy = root.is_dir() && !root.join("meta").exists();
+        let store = LocalFs::new(root)?;
+        if legacy {
+            store.migrate_legacy()?;
+        }
+        Ok(store)
+    }
+
+    fn migrate_legacy(&self) -> Result<()> {
+        let mut legacy = vec![];
+        for entry in self.root.as_std_path().fs_err_read_dir()? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            if metadata.is_dir() {
+                continue;
+            }
+            let created = metadata.created().or_else(|_| metadata.modified())?;
+            let name = entry
+                .file_name()
+                .into_string()
+                .map_err(|name| eyre!("non-utf8 stash name {name:?} in the data dir"))?;
+            legacy.push((created, name));
+        }
+        if legacy.is_empty() {
+            return Ok(());
+        }
+        legacy.sort();
+
+        let count = legacy.len();
+        for (seq, (_, name)) in legacy.into_iter().enumerate() {
+            fs_err::rename(
+                self.root.join(&name),
+                self.root.join(filename(seq as u64, &name)),
+            )?;
+        }
+        fs_err::write(self.root.join("heads").join("next_seq"), count.to_string())?;
+        eprintln!("Migrated {count} stashes to the current format of the data dir");
+        Ok(())
+    }
+
+    fn index_path(&self) -> PathBuf {
+        self.root.join("index").join("stashes.json")
+    }
+
+    /// Held by whoever changes the index, so concurrent changes don't get lost.
+    fn lock_index(&self) -> Result<fs_err::File> {
+        let lock = fs_err::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(self.root.join("index").join("lock"))?;
+        lock.file().lock()?;
+        Ok(lock)
+    }
+
+    /// `None` if there is no index or it can't be read.
+    fn read_index(&self) -> Option<Vec<IndexEntry>> {
+        let json = fs_err::read_to_string(self.index_path()).ok()?;
+        serde_json::from_str(&json).ok()
+    }
+
+    fn write_index(&self, index: &[IndexEntry]) -> Result<()> {
+        let tmp = TempFile::new(
+            self.root
+                .join("tmp")
+                .join(format!("index.{}", std::process::id())),
+        );
+        fs_err::write(tmp.path(), serde_json::to_string(index)?)?;
+        fs_err::rename(tmp.path(), self.index_path())?;
+        Ok(())
+    }
+
+    fn index_entry(&self, seq: u64, name: &str) -> Result<Option<IndexEntry>> {
+        let Some(stash) = self.stat(seq, name)? else {
+            return Ok(None);
+        };
+        Ok(Some(IndexEntry {
+            tags: self.meta(&stash)?.tags,
+            seq,
+            name: stash.name,
+            created: stash.created,
+            size: stash.size,
+            locked: stash.locked,
+            expires: stash.expires,
+        }))
+    }
+
+    /// Drops `old` from the index and adds `current` as it is on disk now.
+    /// A missing index is left alone, it's rebuilt the next time it's needed.
+    fn refresh_index(&self, old: Option<&Data>, current: Option<&Data>) -> Result<()> {
+        let _lock = self.lock_index()?;
+        let Some(mut index) = self.read_index() else {
+            return Ok(());
+        };
+        if let Some(old) = old {
+            index.retain(|entry| entry.seq != old.seq);
+        }
+        if let Some(current) = current {
+            index.retain(|entry| entry.seq != current.seq);
+            index.extend(self.index_entry(current.seq, &current.name)?);
+        }
+        self.write_index(&index)
+    }
+
+    /// The index if it lists exactly the stashes in the directory, else a new one
+    /// built from the files, reusing what's still valid of the old one.
+    fn up_to_date_index(&self) -> Result<Vec<IndexEntry>> {
+        let mut scanned = self.scan()?;
+        scanned.sort();
+        let matches = |index: &[IndexEntry]| {
+            index.len() == scanned.len()
+                && index.iter().all(|entry| {
+                    scanned
+                        .binary_search(&(entry.seq, entry.name.clone()))
+                        .is_ok()
+                })
+        };
+        if let Some(index) = self.read_index().filter(|index| matches(index)) {
+            return Ok(index);
+        }
+
+        let _lock = self.lock_index()?;
+        let old = self.read_index().unwrap_or_default();
+        let mut index = vec![];
+        for (seq, name) in scanned {
+            match old
+                .iter()
+                .find(|entry| entry.seq == seq && entry.name == name)
+            {
+                Some(entry) => index.push(entry.clone()),
+                // removed concurrently if there is no file anymore
+                None => index.extend(self.index_entry(seq, &name)?),
+            }
+        }
+        self.write_index(&index)?;
+        Ok(index)
+    }
+
+    /// Records the checksum of the stash's current contents.
+    /// A hash can't be continued from its result, so this reads all of them.
+    fn rehash(&self, stash: &Data) -> Result<()> {
+        let mut meta = self.meta(stash)?;
+        let mut contents = HashingReader::new(self.reader(stash)?);
+        std::io::copy(&mut contents, &mut std::io::sink())?;
+        meta.size = meta.encoded().then_some(contents.bytes_read());
+        meta.sha256 = Some(contents.sha256());
+        self.set_meta(stash, &meta)
+    }
+
+    /// Moves a file `fsck` can't make sense of out of the way, into `quarantine`.
+    fn quarantine(&self, path: &PathBuf) -> Result<()> {
+        let quarantine = self.root.join("quarantine");
+        fs_err::create_dir_all(&quarantine)?;
+        fs_err::rename(path, quarantine.join(path.file_name().unwrap_or_default()))?;
+        Ok(())
+    }
+
+    /// The files in one of the subdirectories, by name.
+    fn list_dir(&self, dir: &str) -> Result<Vec<(String, PathBuf)>> {
+        let mut files = vec![];
+        for entry in self.root.join(dir).as_std_path().fs_err_read_dir()? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                continue;
+            }
+            let filename = entry.file_name().to_string_lossy().into_owned();
+            files.push((filename.clone(), self.root.join(dir).join(filename)));
+        }
+        Ok(files)
+    }
+
+    /// Makes the store only accessible to the user, for secret stashes.
+    fn restrict_root(&self) -> Result<()> {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs_err::set_permissions(&self.root, std::fs::Permissions::from_mode(0o700))?;
+        }
+        Ok(())
+    }
+
+    fn path(&self, stash: &Data) -> PathBuf {
+        self.root.join(stash.filename())
+    }
+
+    fn event_log_path(&self) -> PathBuf {
+        self.root.join("log").join("events.ndjson")
+    }
+
+    fn meta_path(&self, stash: &Data) -> PathBuf {
+        self.root
+            .join("meta")
+            .join(format!("{}.json", stash.filename()))
+    }
+
+    fn meta_of(&self, seq: u64, name: &str) -> Result<Meta> {
+        let path = self
+            .root
+            .join("meta")
+            .join(format!("{}.json", filename(seq, name)));
+        match fs_err::read_to_string(path) {
+            Ok(json) => Ok(serde_json::from_str(&json)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Meta::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn trash_path(&self, stash: &Data) -> PathBuf {
+        self.root.join("trash").join(stash.filename())
+    }
+
+    fn trash_meta_path(&self, stash: &Data) -> PathBuf {
+        self.root
+            .join("trash")
+            .join(format!("{}.json", stash.filename()))
+    }
+
+    fn head_path(&self, name: &str) -> PathBuf {
+        self.root
+            .join("heads")
+            .join(format!("{}.head", encode_name(name)))
+    }
+
+    /// Sequence number and name of every stash, from the directory listing alone.
+    fn scan(&self) -> Result<Vec<(u64, String)>> {
+        let mut stashes = vec![];
+        for entry in self.root.as_std_path().fs_err_read_dir()? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                continue;
+            }
+            let filename = entry.file_name();
+            let filename = filename.to_string_lossy();
+            match parse_filename(&filename) {
+                Some(stash) => stashes.push(stash),
+                None => eprintln!(
+                    "Warning: ignoring unrecognized file in the data dir: {filename}, see `stash fsck`"
+                ),
+            }
+        }
+        Ok(stashes)
+    }
+
+    fn data(seq: u64, name: String, metadata: &std::fs::Metadata, meta: &Meta) -> Result<Data> {
+        Ok(Data {
+            name,
+            index: 0,
+            seq,
+            created: metadata.created().or_else(|_| metadata.modified())?,
+            size: meta.size.unwrap_or(metadata.len()),
+            locked: metadata.permissions().readonly(),
+            expires: meta.expiry(),
+        })
+    }
+
+    fn stat(&self, seq: u64, name: &str) -> Result<Option<Data>> {
+        let metadata = match fs_err::metadata(self.root.join(filename(seq, name))) {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let meta = self.meta_of(seq, name)?;
+        Ok(Some(LocalFs::data(seq, name.to_owned(), &metadata, &meta)?))
+    }
+
+    fn next_seq(&self) -> Result<u64> {
+        use std::io::{Seek, SeekFrom};
+
+        let mut counter = fs_err::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(self.root.join("heads").join("next_seq"))?;
+        // held until `counter` is dropped, so concurrent pushes get different numbers
+        counter.file().lock()?;
+        let mut next = String::new();
+        counter.read_to_string(&mut next)?;
+        let seq = match next.trim().parse() {
+            Ok(seq) => seq,
+            // a store from before the counter existed
+            Err(_) => self
+                .scan()?
+                .iter()
+                .map(|(seq, _)| seq + 1)
+                .max()
+                .unwrap_or(0),
+        };
+        counter.set_len(0)?;
+        counter.seek(SeekFrom::Start(0))?;
+        write!(counter, "{}", seq + 1)?;
+        Ok(seq)
+    }
+
+    fn set_head(&self, stash: &Data) -> Result<()> {
+        fs_err::write(self.head_path(&stash.name), stash.seq.to_string())?;
+        Ok(())
+    }
+
+    /// Called when `stash` leaves its stack.
+    // `newest` would notice the stale head too, this just saves it the lookup
+    fn forget_head(&self, stash: &Data) {
+        let head = fs_err::read_to_string(self.head_path(&stash.name)).unwrap_or_default();
+        if head.trim() == stash.seq.to_string() {
+            let _ = fs_err::remove_file(self.head_path(&stash.name));
+        }
+    }
+}
+
+impl StorageBackend for LocalFs {
+    fn entries(&self) -> Result<Vec<Data>> {
+        Ok(self
+            .up_to_date_index()?
+            .iter()
+            .map(IndexEntry::data)
+            .collect())
+    }
+
+    fn names(&self) -> Result<Vec<String>> {
+        Ok(self.scan()?.into_iter().map(|(_, name)| name).collect())
+    }
+
+    fn stack(&self, name: &str) -> Result<Vec<Data>> {
+        // every change goes through the index, so it's trusted without listing
+        // the directory. Stashes added by hand show up once `entries` or
+        // `reindex` has caught up with them.
+        let index = match self.read_index() {
+            Some(index) => index,
+            None => self.up_to_date_index()?,
+        };
+        let mut stashes = vec![];
+        for entry in index.iter().filter(|entry| entry.name == name) {
+            // the file may have been removed by hand since
+            stashes.extend(self.stat(entry.seq, name)?);
+        }
+        Ok(stashes)
+    }
+
+    fn newest(&self, name: &str) -> Result<Option<Data>> {
+        let head = fs_err::read_to_string(self.head_path(name))
+            .ok()
+            .and_then(|head| head.trim().parse().ok());
+        if let Some(stash) = head.map(|seq| self.stat(seq, name)).transpose()?.flatten() {
+            return Ok(Some(stash));
+        }
+
+        // no head yet, or the newest stash was removed since
+        let newest = self
+            .scan()?
+            .into_iter()
+            .filter(|(_, stash_name)| stash_name == name)
+            .map(|(seq, _)| seq)
+            .max();
+        let Some(stash) = newest
+            .map(|seq| self.stat(seq, name))
+            .transpose()?
+            .flatten()
+        else {
+            return Ok(None);
+        };
+        self.set_head(&stash)?;
+        Ok(Some(stash))
+    }
+
+    fn reader(&self, stash: &Data) -> Result<Box<dyn Read>> {
+        decoded(&self.meta(stash)?, self.raw_reader(stash)?)
+    }
+
+    fn raw_reader(&self, stash: &Data) -> Result<Box<dyn Read>> {
+        Ok(Box::new(fs_err::File::open(self.path(stash))?))
+    }
+
+    fn reader_at(&self, stash: &Data, offset: u64) -> Result<Box<dyn Read>> {
+        // an encoded stash has to be decoded from the start
+        if self.meta(stash)?.encoded() {
+            let mut reader = self.reader(stash)?;
+            std::io::copy(&mut (&mut reader).take(offset), &mut std::io::sink())?;
+            return Ok(reader);
+        }
+        let mut file = fs_err::File::open(self.path(stash))?;
+        file.seek(std::io::SeekFrom::Start(offset))?;
+        Ok(Box::new(file))
+    }
+
+    fn create(&self, name: &str, meta: &Meta, contents: &mut dyn Read) -> Result<Data> {
+        static PUSHES: AtomicU64 = AtomicU64::new(0);
+
+        if meta.secret {
+            self.restrict_root()?;
+        }
+        // several pushes may run in one process, e.g. for `wrap`
+        let tmp = TempFile::new(self.root.join("tmp").join(format!(
+            "push.{}.{}",
+            std::process::id(),
+            PUSHES.fetch_add(1, Ordering::Relaxed)
+        )));
+        let mut tmp_file = file_options(meta)
+            .write(true)
+            .create_new(true)
+            .open(tmp.path())?;
+        let mut contents = HashingReader::new(contents);
+        let size = {
+            let mut writer = encoded(meta, Box::new(&mut tmp_file))?;
+            let size = std::io::copy(&mut contents, &mut writer)?;
+            // else a truncated file would become the stash
+            writer.finish()?;
+            size
+        };
+        tmp_file.sync_all()?;
+        let meta = &Meta {
+            size: meta.encoded().then_some(size),
+            sha256: Some(contents.sha256()),
+            ..meta.clone()
+        };
+
+        let mut seq = self.next_seq()?;
+        // left over files may occupy a number, in which case we take the next one
+        let stash = loop {
+            let stash = Data {
+                name: name.to_owned(),
+                index: 0,
+                seq,
+                created: SystemTime::now(),
+                size,
+                locked: false,
+                expires: meta.expiry(),
+            };
+            if self.path(&stash).exists() {
+                seq += 1;
+                continue;
+            }
+            // the metadata goes first, the stash shouldn't be seen without it
+            self.set_meta(&stash, meta)?;
+            // unlike a rename, a link doesn't replace an existing file
+            match fs_err::hard_link(tmp.path(), self.path(&stash)) {
+                Ok(()) => break stash,
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => seq += 1,
+                Err(e) => return Err(e.into()),
+            }
+        };
+        drop(tmp);
+        self.set_head(&stash)?;
+        self.refresh_index(None, Some(&stash))?;
+        Ok(stash)
+    }
+
+    fn append(&self, stash: &Data) -> Result<Box<dyn Write>> {
+        let file = fs_err::OpenOptions::new()
+            .append(true)
+            .open(self.path(stash))?;
+        let meta = self.meta(stash)?;
+        // an age stream can't be continued
+        if meta.encrypted {
+            return Err(eyre!(
+                "Stash {} is encrypted, it can't be appended to",
+                stash.id()
+            ));
+        }
+        Ok(Box::new(IndexedWriter {
+            store: self.clone(),
+            stash: stash.clone(),
+            writer: Some(encoded(&meta, Box::new(file))?),
+        }))
+    }
+
+    fn replace(&self, stash: &Data, contents: &mut dyn Read) -> Result<()> {
+        let tmp = TempFile::new(self.root.join("tmp").join(format!(
+            "{}.{}",
+            stash.filename(),
+            std::process::id()
+        )));
+        let mut meta = self.meta(stash)?;
+        let mut tmp_file = file_options(&meta)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(tmp.path())?;
+        let mut contents = HashingReader::new(contents);
+        let mut writer = encoded(&meta, Box::new(&mut tmp_file))?;
+        std::io::copy(&mut contents, &mut writer)?;
+        writer.finish()?;
+        tmp_file.sync_all()?;
+        fs_err::rename(tmp.path(), self.path(stash))?;
+        meta.size = meta.encoded().then_some(contents.bytes_read());
+        meta.sha256 = Some(contents.sha256());
+        self.set_meta(stash, &meta)?;
+        self.refresh_index(Some(stash), Some(stash))
+    }
+
+    fn touch(&self, stash: &Data) -> Result<()> {
+        let touched = Data {
+            name: stash.name.clone(),
+            seq: self.next_seq()?,
+            ..*stash
+        };
+        // copied rather than renamed, as `created` is the file's creation time,
+        // which can't be set
+        let tmp = TempFile::new(self.root.join("tmp").join(format!(
+            "{}.{}",
+            touched.filename(),
+            std::process::id()
+        )));
+        fs_err::copy(self.path(stash), tmp.path())?;
+        let copy = fs_err::OpenOptions::new().append(true).open(tmp.path())?;
+        // where there's no creation time, it's the modification time
+        copy.file().set_modified(SystemTime::now())?;
+        copy.sync_all()?;
+        fs_err::rename(tmp.path(), self.path(&touched))?;
+        fs_err::remove_file(self.path(stash))?;
+        if self.meta_path(stash).exists() {
+            fs_err::rename(self.meta_path(stash), self.meta_path(&touched))?;
+        }
+        self.set_head(&touched)?;
+        self.refresh_index(Some(stash), Some(&touched))
+    }
+
+    fn rename(&self, stash: &Data, name: &str) -> Result<Data> {
+        let renamed = Data {
+            name: name.to_owned(),
+            index: 0,
+            ..stash.clone()
+        };
+        fs_err::rename(self.path(stash), self.path(&renamed))?;
+        if self.meta_path(stash).exists() {
+            fs_err::rename(self.meta_path(stash), self.meta_path(&renamed))?;
+        }
+        self.forget_head(stash);
+        let head = fs_err::read_to_string(self.head_path(name)).ok();
+        if head.and_then(|head| head.trim().parse().ok()) <= Some(renamed.seq) {
+            self.set_head(&renamed)?;
+        }
+        self.refresh_index(Some(stash), Some(&renamed))?;
+        Ok(renamed)
+    }
+
+    // the lock is the file's read-only flag, which also keeps other programs from writing to it
+    fn set_locked(&self, stash: &Data, locked: bool) -> Result<()> {
+        let path = self.path(stash);
+        let mut permissions = fs_err::metadata(&path)?.permissions();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = permissions.mode();
+            permissions.set_mode(match locked {
+                true => mode & !0o222,
+                false => mode | 0o200,
+            });
+        }
+        #[cfg(not(unix))]
+        permissions.set_readonly(locked);
+        fs_err::set_permissions(path, permissions)?;
+        self.refresh_index(Some(stash), Some(stash))
+    }
+
+    fn meta(&self, stash: &Data) -> Result<Meta> {
+        self.meta_of(stash.seq, &stash.name)
+    }
+
+    fn set_meta(&self, stash: &Data, meta: &Meta) -> Result<()> {
+        fs_err::write(self.meta_path(stash), serde_json::to_string(meta)?)?;
+        // only the tags and expiry are in the index
+        let _lock = self.lock_index()?;
+        if let Some(mut index) = self.read_index() {
+            if let Some(entry) = index.iter_mut().find(|entry| entry.seq == stash.seq) {
+                entry.tags = meta.tags.clone();
+                entry.expires = meta.expiry();
+                self.write_index(&index)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn tags(&self, stash: &Data) -> Result<Vec<String>> {
+        let entry = self
+            .read_index()
+            .and_then(|index| index.into_iter().find(|entry| entry.seq == stash.seq));
+        match entry {
+            Some(entry) => Ok(entry.tags),
+            None => Ok(self.meta(stash)?.tags),
+        }
+    }
+
+    fn remove(&self, stash: &Data) -> Result<()> {
+        let trashed = Trashed {
+            removed: SystemTime::now(),
+            meta: self.meta(stash)?,
+        };
+        fs_err::write(
+            self.trash_meta_path(stash),
+            serde_json::to_string(&trashed)?,
+        )?;
+        fs_err::rename(self.path(stash), self.trash_path(stash))?;
+        self.forget_head(stash);
+        self.refresh_index(Some(stash), None)?;
+        match fs_err::remove_file(self.meta_path(stash)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn trashed(&self) -> Result<Vec<(Data, SystemTime)>> {
+        let mut trashed = vec![];
+        for entry in self.root.join("trash").as_std_path().fs_err_read_dir()? {
+            let entry = entry?;
+            let filename = entry.file_name();
+            let filename = filename.to_string_lossy();
+            let Some(stash_filename) = filename.strip_suffix(".json") else {
+                continue;
+            };
+            let Some((seq, name)) = parse_filename(stash_filename) else {
+                continue;
+            };
+            // the contents are moved last on removal and first on restore
+            let metadata = match fs_err::metadata(self.root.join("trash").join(stash_filename)) {
+                Ok(metadata) => metadata,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+            let Trashed { removed, meta } =
+                serde_json::from_str(&fs_err::read_to_string(entry.path())?)?;
+            trashed.push((LocalFs::data(seq, name, &metadata, &meta)?, removed));
+        }
+        Ok(trashed)
+    }
+
+    fn restore(&self, stash: &Data) -> Result<Data> {
+        let Trashed { meta, .. } =
+            serde_json::from_str(&fs_err::read_to_string(self.trash_meta_path(stash))?)?;
+        let restored = Data {
+            index: 0,
+            ..stash.clone()
+        };
+        self.set_meta(&restored, &meta)?;
+        fs_err::rename(self.trash_path(stash), self.path(&restored))?;
+        fs_err::remove_file(self.trash_meta_path(stash))?;
+        let head = fs_err::read_to_string(self.head_path(&restored.name)).ok();
+        if head.and_then(|head| head.trim().parse().ok()) <= Some(restored.seq) {
+            self.set_head(&restored)?;
+        }
+        self.refresh_index(None, Some(&restored))?;
+        Ok(restored)
+    }
+
+    fn purge(&self, stash: &Data) -> Result<()> {
+        fs_err::remove_file(self.trash_path(stash))?;
+        fs_err::remove_file(self.trash_meta_path(stash))?;
+        Ok(())
+    }
+
+    fn log_event(&self, line: &str) -> Result<()> {
+        let mut log = fs_err::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.event_log_path())?;
+        // a single write, so concurrent writers don't interleave within a line
+        log.write_all(format!("{line}\n").as_bytes())?;
+        Ok(())
+    }
+
+    fn events_since(&self, offset: u64) -> Result<Vec<u8>> {
+        use std::io::{Seek, SeekFrom};
+
+        let mut log = match fs_err::File::open(self.event_log_path()) {
+            Ok(log) => log,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e.into()),
+        };
+        log.seek(SeekFrom::Start(offset))?;
+        let mut events = vec![];
+        log.read_to_end(&mut events)?;
+        Ok(events)
+    }
+
+    fn reindex(&self) -> Result<()> {
+        let _lock = self.lock_index()?;
+        let mut index = vec![];
+        for (seq, name) in self.scan()? {
+            index.extend(self.index_entry(seq, &name)?);
+        }
+        self.write_index(&index)
+    }
+
+    fn fsck(&self, repair: bool) -> Result<Vec<String>> {
+        let mut problems = vec![];
+        let mut report = |problem: String, fix: &str| match repair {
+            true => problems.push(format!("{problem}, {fix}")),
+            false => problems.push(problem),
+        };
+
+        let mut stashes = std::collections::BTreeMap::<u64, Vec<String>>::new();
+        for (filename, path) in self.list_dir("")? {
+            match parse_filename(&filename) {
+                Some((seq, name)) => stashes.entry(seq).or_default().push(name),
+                None => {
+                    if repair {
+                        self.quarantine(&path)?;
+                    }
+                    report(
+                        format!("unrecognized file {filename}"),
+                        "moved to quarantine",
+                    );
+                }
+            }
+        }
+        let is_stash = |seq: u64, name: &str| {
+            stashes
+                .get(&seq)
+                .is_some_and(|names| names.iter().any(|n| n == name))
+        };
+
+        for (filename, path) in self.list_dir("meta")? {
+            let Some((seq, name)) = filename.strip_suffix(".json").and_then(parse_filename) else {
+                if repair {
+                    self.quarantine(&path)?;
+                }
+                report(
+                    format!("unrecognized file meta/{filename}"),
+                    "moved to quarantine",
+                );
+                continue;
+            };
+            if !is_stash(seq, &name) {
+                if repair {
+                    self.quarantine(&path)?;
+                }
+                report(
+                    format!("metadata without a stash: meta/{filename}"),
+                    "moved to quarantine",
+                );
+                continue;
+            }
+            match serde_json::from_str::<Meta>(&fs_err::read_to_string(&path)?) {
+                Err(e) => {
+                    if repair {
+                        self.quarantine(&path)?;
+                    }
+                    report(
+                        format!("unreadable metadata meta/{filename}: {e}"),
+                        "moved to quarantine",
+                    );
+                }
+                // compressed before sizes were recorded, encrypted ones would need
+                // the passphrase
+                Ok(meta) if meta.encoding.is_some() && !meta.encrypted && meta.size.is_none() => {
+                    if repair {
+                        if let Some(stash) = self.stat(seq, &name)? {
+                            self.rehash(&stash)?;
+                        }
+                    }
+                    report(
+                        format!(
+                            "uncompressed size of {} not recorded",
+                            filename.trim_end_matches(".json")
+                        ),
+                        "recorded",
+                    );
+                }
+                Ok(_) => {}
+            }
+        }
+
+        for (seq, names) in &stashes {
+            // all but the first are moved to the top of their stacks, which reads
+            // their metadata, so that's checked first
+            for name in names.iter().skip(1) {
+                if repair {
+                    if let Some(stash) = self.stat(*seq, name)? {
+                        self.touch(&stash)?;
+                    }
+                }
+                report(
+                    format!(
+                        "{} shares its sequence number with {}",
+                        filename(*seq, name),
+                        filename(*seq, &names[0])
+                    ),
+                    "given a new one",
+                );
+            }
+        }
+
+        let trash = self.list_dir("trash")?;
+        let in_trash = |filename: &str| trash.iter().any(|(other, _)| other == filename);
+        for (filename, path) in &trash {
+            let problem = match filename.strip_suffix(".json") {
+                Some(stash) if !in_trash(stash) => {
+                    format!("metadata without a stash: trash/{filename}")
+                }
+                Some(_) => match serde_json::from_str::<Trashed>(&fs_err::read_to_string(path)?) {
+                    Ok(_) => continue,
+                    Err(e) => format!("unreadable metadata trash/{filename}: {e}"),
+                },
+                None if !in_trash(&format!("{filename}.json")) => {
+                    format!("stash without metadata: trash/{filename}")
+                }
+                None => continue,
+            };
+            if repair {
+                self.quarantine(path)?;
+            }
+            report(problem, "moved to quarantine");
+        }
+
+        // a push that is still running gets an hour before its file counts as left over
+        let stale = SystemTime::now() - Duration::from_secs(60 * 60);
+        for (filename, path) in self.list_dir("tmp")? {
+            if fs_err::metadata(&path)?.modified()? > stale {
+                continue;
+            }
+            if repair {
+                fs_err::remove_file(&path)?;
+            }
+            report(format!("left over temp file tmp/{filename}"), "deleted");
+        }
+
+        if repair {
+            self.reindex()?;
+        }
+        Ok(problems)
+    }
+}
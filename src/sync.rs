@@ -0,0 +1,194 @@
+//! Syncing with the store of another machine, by running `stash export` and
+//! `stash import` there over ssh.
+
+use std::collections::BTreeMap;
+use std::process::{Command, Stdio};
+
+use eyre::{eyre, Result};
+use serde::Deserialize;
+
+use crate::meta::Meta;
+use crate::storage::{Data, StorageBackend};
+
+/// Copies the stashes of the given names (or all of them) that one side doesn't
+/// have yet to it: first from `host` to this store, then the other way, unless
+/// `pull` or `push` restricts it to one direction. Stashes are told apart by name
+/// and checksum as in `import`, and only those the receiving side doesn't have are
+/// transferred: pulling hands the checksums here to `export` on `host`, pushing
+/// first asks `host` for its list. Syncing twice copies nothing the second time.
+///
+/// `remote_command` is how `stash` is run on `host`. Secret stashes are only
+/// copied with `force`, on both sides.
+pub fn sync(
+    store: &dyn StorageBackend,
+    host: &str,
+    remote_command: &str,
+    names: &[String],
+    (pull, push): (bool, bool),
+    force: bool,
+) -> Result<()> {
+    let quoted: Vec<_> = names.iter().map(|name| quote(name)).collect();
+    let flags = match force {
+        true => " --force",
+        false => "",
+    };
+    if pull || !push {
+        eprintln!("Pulling from {host}");
+        let known = crate::export::checksums(store)?;
+        let mut ssh = Command::new("ssh")
+            .arg("--")
+            .arg(host)
+            .arg(format!(
+                "{remote_command} export --skip-known{flags} {}",
+                quoted.join(" ")
+            ))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| eyre!("failed to run ssh: {e}"))?;
+        // it's read in full before anything is exported
+        let mut stdin = ssh.stdin.take().unwrap();
+        let sent = serde_json::to_writer(&mut stdin, &known);
+        drop(stdin);
+        let imported = crate::export::import(store, ssh.stdout.take().unwrap());
+        let status = ssh.wait()?;
+        // the remote side's error is more telling than the truncated archive
+        if !status.success() {
+            return Err(eyre!("`stash export` on {host} failed, {status}"));
+        }
+        sent?;
+        imported?;
+    }
+    if push || !pull {
+        eprintln!("Pushing to {host}");
+        let known = list(host, remote_command)?
+            .into_iter()
+            .filter_map(|stash| Some((stash.name, stash.meta.sha256?)))
+            .collect();
+        let mut ssh = Command::new("ssh")
+            .arg("--")
+            .arg(host)
+            .arg(format!("{remote_command} import"))
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|e| eyre!("failed to run ssh: {e}"))?;
+        let mut stdin = ssh.stdin.take().unwrap();
+        let exported = crate::export::export(store, names, force, &known, &mut stdin);
+        // so the remote side sees the end of the archive
+        drop(stdin);
+        let status = ssh.wait()?;
+        if !status.success() {
+            return Err(eyre!("`stash import` on {host} failed, {status}"));
+        }
+        exported?;
+    }
+    Ok(())
+}
+
+/// A stash as `stash list --format json` prints it, as far as `verify` needs it.
+#[derive(Deserialize)]
+struct Listed {
+    id: String,
+    name: String,
+    meta: Meta,
+}
+
+/// The ids of the stashes of the given names (or all of them) by name and
+/// checksum, which is how `import` tells stashes apart, and how many have no
+/// checksum to compare.
+fn by_checksum(
+    stashes: Vec<Listed>,
+    names: &[String],
+    force: bool,
+) -> (BTreeMap<(String, String), String>, usize) {
+    let mut by_checksum = BTreeMap::new();
+    let mut unverified = 0;
+    let selected = stashes
+        .into_iter()
+        .filter(|stash| names.is_empty() || names.contains(&stash.name))
+        // they aren't synced either
+        .filter(|stash| force || !stash.meta.secret);
+    for stash in selected {
+        match stash.meta.sha256 {
+            Some(sha256) => {
+                by_checksum.insert((stash.name, sha256), stash.id);
+            }
+            None => unverified += 1,
+        }
+    }
+    (by_checksum, unverified)
+}
+
+/// Compares the stashes of the given names (or all of them) with those on `host`
+/// by the checksums recorded when they were pushed, without transferring their
+/// contents, and fails if either side has stashes the other doesn't. Stashes from
+/// before checksums can't be compared and are only counted. Secret stashes are
+/// only compared with `force`, like they're only synced with it.
+pub fn verify(
+    store: &dyn StorageBackend,
+    host: &str,
+    remote_command: &str,
+    names: &[String],
+    force: bool,
+) -> Result<()> {
+    eprintln!("Verifying against {host}");
+    let mut local = vec![];
+    for stash in Data::detect(store)? {
+        local.push(Listed {
+            id: stash.id().to_string(),
+            meta: store.meta(&stash)?,
+            name: stash.name,
+        });
+    }
+    let remote = list(host, remote_command)?;
+
+    let (local, local_unverified) = by_checksum(local, names, force);
+    let (remote, remote_unverified) = by_checksum(remote, names, force);
+    let mut differences = 0;
+    for (key, id) in &local {
+        if !remote.contains_key(key) {
+            println!("{id} is missing on {host}");
+            differences += 1;
+        }
+    }
+    for (key, id) in &remote {
+        if !local.contains_key(key) {
+            println!("{id} on {host} is missing here");
+            differences += 1;
+        }
+    }
+    let unverified = local_unverified + remote_unverified;
+    if unverified > 0 {
+        eprintln!("Skipped {unverified} stash(es) from before checksums were recorded");
+    }
+    match differences {
+        0 => {
+            eprintln!("All {} stash(es) match", local.len());
+            Ok(())
+        }
+        _ => Err(eyre!(
+            "{differences} stash(es) differ between here and {host}"
+        )),
+    }
+}
+
+/// The stashes on `host`, from `stash list --format json` there.
+fn list(host: &str, remote_command: &str) -> Result<Vec<Listed>> {
+    let output = Command::new("ssh")
+        .arg("--")
+        .arg(host)
+        .arg(format!("{remote_command} list --format json"))
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|e| eyre!("failed to run ssh: {e}"))?;
+    if !output.status.success() {
+        return Err(eyre!("`stash list` on {host} failed, {}", output.status));
+    }
+    serde_json::from_slice(&output.stdout)
+        .map_err(|e| eyre!("unexpected output of `stash list` on {host}: {e}"))
+}
+
+/// Quotes `arg` for the remote shell.
+fn quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', r"'\''"))
+}
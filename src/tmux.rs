@@ -0,0 +1,42 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use eyre::{eyre, Result};
+
+/// The contents of a tmux paste buffer, the most recent one if `buffer` is `None`.
+pub fn save_buffer(buffer: Option<&str>) -> Result<Vec<u8>> {
+    let mut command = Command::new("tmux");
+    command.arg("save-buffer");
+    if let Some(buffer) = buffer {
+        command.args(["-b", buffer]);
+    }
+    let output = command
+        .arg("-")
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|e| eyre!("couldn't run tmux: {e}"))?;
+    if !output.status.success() {
+        return Err(eyre!("tmux save-buffer failed with {}", output.status));
+    }
+    Ok(output.stdout)
+}
+
+/// Puts `contents` into a tmux paste buffer, a new one if `buffer` is `None`.
+pub fn load_buffer(buffer: Option<&str>, contents: &[u8]) -> Result<()> {
+    let mut command = Command::new("tmux");
+    command.arg("load-buffer");
+    if let Some(buffer) = buffer {
+        command.args(["-b", buffer]);
+    }
+    let mut child = command
+        .arg("-")
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|e| eyre!("couldn't run tmux: {e}"))?;
+    child.stdin.take().unwrap().write_all(contents)?;
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(eyre!("tmux load-buffer failed with {status}"));
+    }
+    Ok(())
+}
@@ -0,0 +1,50 @@
+use std::collections::BTreeMap;
+
+use crate::storage::Data;
+
+/// Stashes grouped by the `/`-separated components of their names.
+#[derive(Default)]
+struct Node<'a> {
+    stashes: Vec<&'a Data>,
+    children: BTreeMap<&'a str, Node<'a>>,
+}
+
+/// Prints the stashes as a tree, expects them in `Data::detect` order.
+pub fn print(stashes: &[Data]) {
+    let mut root = Node::default();
+    for stash in stashes {
+        let mut node = &mut root;
+        if !stash.name.is_empty() {
+            for component in stash.name.split('/') {
+                node = node.children.entry(component).or_default();
+            }
+        }
+        node.stashes.push(stash);
+    }
+
+    println!(".");
+    print_children(&root, "");
+}
+
+fn print_children(node: &Node, prefix: &str) {
+    let count = node.stashes.len() + node.children.len();
+    let branch = |i: usize| match i + 1 == count {
+        true => ("└── ", "    "),
+        false => ("├── ", "│   "),
+    };
+
+    for (i, stash) in node.stashes.iter().enumerate() {
+        println!(
+            "{prefix}{}{}  {}  {}",
+            branch(i).0,
+            stash.index,
+            crate::format_age(stash.created),
+            crate::human_size(stash.size),
+        );
+    }
+    for (i, (component, child)) in node.children.iter().enumerate() {
+        let (branch, indent) = branch(node.stashes.len() + i);
+        println!("{prefix}{branch}{component}");
+        print_children(child, &format!("{prefix}{indent}"));
+    }
+}
@@ -0,0 +1,236 @@
+use std::io::Read;
+
+use eyre::Result;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::Style;
+use ratatui::widgets::{Block, List, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+
+use crate::storage::{Data, StorageBackend};
+use crate::ShowOptions;
+
+/// How much of the selected stash the preview pane reads.
+const PREVIEW_BYTES: u64 = 64 * 1024;
+
+const HELP: &str = "j/k select  enter show  p pop  d drop  r rename  t tag  q quit";
+
+/// Lets the user browse all stashes with a preview of their contents and pop,
+/// drop, rename or tag them.
+pub fn run(store: &dyn StorageBackend) -> Result<()> {
+    let mut browser = Browser::new(store)?;
+    let mut terminal = ratatui::init();
+    let exit = browser.run(&mut terminal);
+    ratatui::restore();
+
+    // stashes are printed after leaving the alternate screen, so they stay visible
+    match exit? {
+        Exit::Quit => {}
+        Exit::Show(stash) => crate::print_stash(store, &stash, &ShowOptions::default())?,
+        Exit::Pop(stash) => {
+            crate::print_stash(store, &stash, &ShowOptions::default())?;
+            store.remove(&stash)?;
+            crate::events::record(store, crate::events::Kind::Pop, Some(&stash))?;
+        }
+    }
+    Ok(())
+}
+
+enum Exit {
+    Quit,
+    Show(Data),
+    Pop(Data),
+}
+
+enum Prompt {
+    Rename,
+    Tag,
+}
+
+struct Browser<'a> {
+    store: &'a dyn StorageBackend,
+    stashes: Vec<Data>,
+    list: ListState,
+    /// A line being typed in at the bottom and what it is for
+    prompt: Option<(Prompt, String)>,
+    /// Outcome of the last action, shown until the next key
+    status: Option<String>,
+}
+
+impl<'a> Browser<'a> {
+    fn new(store: &'a dyn StorageBackend) -> Result<Self> {
+        let mut browser = Browser {
+            store,
+            stashes: vec![],
+            list: ListState::default(),
+            prompt: None,
+            status: None,
+        };
+        browser.reload()?;
+        Ok(browser)
+    }
+
+    /// Rereads the stashes, keeping the selection at the same position.
+    fn reload(&mut self) -> Result<()> {
+        self.stashes = Data::detect(self.store)?;
+        let selected = match self.stashes.len() {
+            0 => None,
+            len => Some(self.list.selected().unwrap_or(0).min(len - 1)),
+        };
+        self.list.select(selected);
+        Ok(())
+    }
+
+    fn selected(&self) -> Option<&Data> {
+        self.list.selected().and_then(|i| self.stashes.get(i))
+    }
+
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> Result<Exit> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            self.status = None;
+
+            if let Some((_, input)) = &mut self.prompt {
+                match key.code {
+                    KeyCode::Enter => {
+                        let (prompt, input) = self.prompt.take().unwrap();
+                        if let Err(e) = self.submit(prompt, &input) {
+                            self.status = Some(e.to_string());
+                        }
+                        self.reload()?;
+                    }
+                    KeyCode::Esc => self.prompt = None,
+                    KeyCode::Backspace => {
+                        input.pop();
+                    }
+                    KeyCode::Char(c) => input.push(c),
+                    _ => {}
+                }
+                continue;
+            }
+
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(Exit::Quit),
+                KeyCode::Char('j') | KeyCode::Down => self.list.select_next(),
+                KeyCode::Char('k') | KeyCode::Up => self.list.select_previous(),
+                KeyCode::Enter => {
+                    if let Some(stash) = self.selected() {
+                        return Ok(Exit::Show(stash.clone()));
+                    }
+                }
+                KeyCode::Char('p') => {
+                    if let Some(stash) = self.selected() {
+                        match crate::ensure_unlocked(stash) {
+                            Ok(()) => return Ok(Exit::Pop(stash.clone())),
+                            Err(e) => self.status = Some(e.to_string()),
+                        }
+                    }
+                }
+                KeyCode::Char('d') => {
+                    if let Some(stash) = self.selected().cloned() {
+                        let id = stash.id();
+                        self.status = Some(match crate::delete_stash(self.store, Some(stash)) {
+                            Ok(()) => format!("Dropped {id}"),
+                            Err(e) => e.to_string(),
+                        });
+                        self.reload()?;
+                    }
+                }
+                KeyCode::Char('r') => {
+                    if let Some(stash) = self.selected() {
+                        self.prompt = Some((Prompt::Rename, stash.name.clone()));
+                    }
+                }
+                KeyCode::Char('t') if self.selected().is_some() => {
+                    self.prompt = Some((Prompt::Tag, String::new()));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn submit(&mut self, prompt: Prompt, input: &str) -> Result<()> {
+        let Some(stash) = self.selected().cloned() else {
+            return Ok(());
+        };
+        match prompt {
+            Prompt::Rename => crate::rename_stash(self.store, &stash, input)?,
+            Prompt::Tag => {
+                let mut meta = self.store.meta(&stash)?;
+                meta.change_tags(&input.split_whitespace().collect::<Vec<_>>())?;
+                self.store.set_meta(&stash, &meta)?;
+            }
+        }
+        self.status = Some(format!("Updated {}", stash.id()));
+        Ok(())
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, bottom] =
+            Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.area());
+        let [list_area, preview_area] =
+            Layout::horizontal([Constraint::Percentage(35), Constraint::Percentage(65)])
+                .areas(main);
+
+        let items = self.stashes.iter().map(|stash| {
+            format!(
+                "{}  {}{}",
+                stash.id(),
+                crate::format_age(stash.created),
+                if stash.locked { "  (locked)" } else { "" }
+            )
+        });
+        let list = List::new(items)
+            .block(Block::bordered().title("Stashes"))
+            .highlight_style(Style::new().reversed());
+        frame.render_stateful_widget(list, list_area, &mut self.list);
+
+        let (title, preview) = match self.selected() {
+            Some(stash) => (
+                stash.id().to_string(),
+                preview(self.store, stash).unwrap_or_else(|e| e.to_string()),
+            ),
+            None => (String::new(), "No stashes".to_owned()),
+        };
+        frame.render_widget(
+            Paragraph::new(preview).block(Block::bordered().title(title)),
+            preview_area,
+        );
+
+        let line = match (&self.prompt, &self.status) {
+            (Some((Prompt::Rename, input)), _) => format!("New name: {input}"),
+            (Some((Prompt::Tag, input)), _) => format!("Tags (+add -remove): {input}"),
+            (None, Some(status)) => status.clone(),
+            (None, None) => HELP.to_owned(),
+        };
+        frame.render_widget(Paragraph::new(line), bottom);
+    }
+}
+
+/// The beginning of the stash as text, with anything that could mess with the
+/// terminal replaced.
+fn preview(store: &dyn StorageBackend, stash: &Data) -> Result<String> {
+    // asking for the passphrase would mess up the screen
+    if store.meta(stash)?.encrypted {
+        return Ok("(encrypted)".to_owned());
+    }
+    let mut contents = vec![];
+    store
+        .reader(stash)?
+        .take(PREVIEW_BYTES)
+        .read_to_end(&mut contents)?;
+    Ok(String::from_utf8_lossy(&contents)
+        .replace('\t', "    ")
+        .chars()
+        .map(|c| match c.is_control() && c != '\n' {
+            true => '\u{fffd}',
+            false => c,
+        })
+        .collect())
+}
@@ -0,0 +1,95 @@
+use std::collections::HashMap;
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::{Duration, Instant};
+
+use camino::{Utf8Path as Path, Utf8PathBuf as PathBuf};
+use eyre::{eyre, Result};
+use notify::{EventKind, RecursiveMode, Watcher};
+
+use crate::meta::Meta;
+use crate::storage::StorageBackend;
+
+/// How long a file must go without changes before it's considered complete.
+const QUIET_PERIOD: Duration = Duration::from_secs(1);
+
+pub enum AfterPush {
+    Keep,
+    Delete,
+    MoveTo(PathBuf),
+}
+
+/// Pushes every file that is created in (or moved into) `dir` as a new stash
+/// of `name`, until interrupted.
+pub fn watch_dir(
+    store: &dyn StorageBackend,
+    dir: &Path,
+    name: &str,
+    after_push: &AfterPush,
+) -> Result<()> {
+    if let AfterPush::MoveTo(target) = after_push {
+        fs_err::create_dir_all(target)?;
+    }
+    let (tx, rx) = std::sync::mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+    watcher.watch(dir.as_std_path(), RecursiveMode::NonRecursive)?;
+    eprintln!("Watching {dir}, press Ctrl-C to stop");
+
+    // files are only pushed once they've stopped changing, so we don't stash them half-written
+    let mut pending = HashMap::new();
+    loop {
+        match rx.recv_timeout(QUIET_PERIOD) {
+            Ok(event) => {
+                let event = event?;
+                if let EventKind::Create(_) | EventKind::Modify(_) = event.kind {
+                    for path in event.paths {
+                        pending.insert(path, Instant::now());
+                    }
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return Err(eyre!("file watcher stopped")),
+        }
+
+        let mut settled = pending
+            .iter()
+            .filter(|(_, changed)| changed.elapsed() >= QUIET_PERIOD)
+            .map(|(path, changed)| (path.clone(), *changed))
+            .collect::<Vec<_>>();
+        settled.sort_by_key(|&(_, changed)| changed);
+        for (path, _) in settled {
+            pending.remove(&path);
+            let Ok(path) = PathBuf::from_path_buf(path) else {
+                eprintln!("Skipping file with non-utf8 path");
+                continue;
+            };
+            if !path.is_file() {
+                continue;
+            }
+            if let Err(e) = stash_file(store, &path, name, after_push) {
+                eprintln!("Couldn't stash {path}: {e}");
+            }
+        }
+    }
+}
+
+fn stash_file(
+    store: &dyn StorageBackend,
+    path: &Path,
+    name: &str,
+    after_push: &AfterPush,
+) -> Result<()> {
+    let filename = path.file_name().unwrap_or_default();
+    let meta = Meta {
+        filename: Some(filename.to_owned()),
+        ..Meta::captured()
+    };
+    let stash = crate::push(store, name, &meta, &mut fs_err::File::open(path)?)?;
+    eprintln!("Stashed {filename} as {}", stash.id());
+
+    match after_push {
+        AfterPush::Keep => {}
+        AfterPush::Delete => fs_err::remove_file(path)?,
+        AfterPush::MoveTo(target) => fs_err::rename(path, target.join(filename))?,
+    }
+    Ok(())
+}
@@ -0,0 +1,331 @@
+//! One-shot transfer of a stash to another machine, `send` on one end and
+//! `receive` on the other, over a direct TCP connection.
+//!
+//! The code `send` prints is a short password and the address to connect to,
+//! like `7-copper-lantern@192.168.1.20:41234`. Both ends derive a key from the
+//! password with SPAKE2, so someone listening in learns nothing and someone
+//! guessing gets one try: with the wrong code, the sender gives up without sending
+//! anything. The stash is then sent age-encrypted with that key.
+//!
+//! In a terminal, `send` also advertises itself on the local network over mDNS,
+//! code included, so that `receive --discover` can find it without anyone typing
+//! the code. Anyone on the network could then connect, which is why `send` asks
+//! before sending to an address that connected.
+
+use std::io::{BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::{Duration, Instant};
+
+use age::secrecy::SecretString;
+use eyre::{eyre, Result};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use spake2::{Ed25519Group, Identity, Password, Spake2};
+
+use crate::meta::Meta;
+use crate::storage::{Data, StorageBackend};
+
+/// How long either end waits for the other once connected.
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The mDNS service type `send` advertises itself as.
+const SERVICE: &str = "_stash-send._tcp.local.";
+/// How long `receive --discover` listens for senders.
+const DISCOVERY_TIME: Duration = Duration::from_secs(3);
+
+/// Bound into the key exchange, so the keys are only good for this.
+const APP_ID: &[u8] = b"stash send";
+
+/// Picked from for the password, 8 bits each.
+const WORDS: &str = "
+    acid acorn actor adult agent alarm album alley amber angle ankle anvil apple april apron arena
+    argue arrow atlas attic audio autumn award bacon badge bagel baker banjo baron basin basket beach
+    beard bench berry blade blank blaze bloom board bonus boost brain brass bread brick bride brook
+    brush bucket buddy bugle cabin cable camel canal candy canoe cargo carpet castle cedar chain chalk
+    charm cheese cherry chess chief cider cinema civic clamp cliff clock cloud coast cobra cocoa comet
+    coral couch crane crate cream crown crumb cube curry daisy dance delta denim depot diary dingo
+    disco dizzy dock dolphin donut dough dozen draft dragon drama dream drift drum eagle easel eclipse
+    elbow ember empty engine error essay ethic fable fairy falcon fancy feast fence ferry fever fiber
+    fiddle field flame flute focus forest fossil fox frost fruit gadget galaxy garden garlic gecko
+    ghost giant ginger glass globe glove goose grape gravel guitar habit hammer harbor hazel helmet
+    hero honey hotel husky igloo index ink island ivory jacket jaguar jelly jewel jungle kayak kettle
+    kidney kite koala label ladder lagoon lamp laser lemon lever lilac llama lobster locket lunar magnet
+    mango maple marble meadow melon metal meteor mimic mint mirror mocha moose mosaic motor mural
+    museum napkin nectar needle nickel noble noodle nutmeg oasis ocean olive omega onion opera orbit
+    otter oxygen paddle palace panda paper parrot pasta peach pearl pepper piano pickle pilot pine
+    pixel pizza planet plaza plum poem polar pony poppy potato prism pulse puppet quartz quilt rabbit
+    radar raven salmon saturn scarf
+";
+
+/// What's sent ahead of the contents.
+#[derive(Serialize, Deserialize)]
+struct Header {
+    name: String,
+    meta: Meta,
+}
+
+/// Waits for `receive` to connect and sends it the stash. If `advertise`d, only
+/// once the user agrees to send it to the address that connected.
+pub fn send(store: &dyn StorageBackend, stash: &Data, port: u16, advertise: bool) -> Result<()> {
+    let listener = TcpListener::bind(("0.0.0.0", port))?;
+    let password = password()?;
+    let (ip, port) = (local_ip(), listener.local_addr()?.port());
+    eprintln!("On the other machine, run:\n\n    stash receive {password}@{ip}:{port}\n");
+    let advertisement = match advertise {
+        true => {
+            eprintln!("or `stash receive --discover` on the same network\n");
+            Some(Advertisement::new(stash, &password, &ip, port)?)
+        }
+        false => None,
+    };
+
+    let (stream, peer) = listener.accept()?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    let mut stream = BufReader::new(stream);
+    let key = exchange_key(&mut stream, &password)?;
+    let confirmation = read_message(&mut stream)?;
+    if !confirmation_is(&key, b"receiver", &confirmation) {
+        return Err(eyre!(
+            "The other machine used the wrong code, nothing was sent. Run `stash send` again for a new one"
+        ));
+    }
+    if advertisement.is_some() {
+        let agreed = dialoguer::Confirm::new()
+            .with_prompt(format!("Send {} to {}?", stash.id(), peer.ip()))
+            .default(false)
+            .interact()?;
+        if !agreed {
+            return Err(eyre!("Nothing was sent"));
+        }
+    }
+    write_message(stream.get_mut(), &confirm(&key, b"sender"))?;
+
+    let mut meta = store.meta(stash)?;
+    // the contents are sent decoded, `receive` stores them as it would a push
+    meta.encrypted = false;
+    let header = serde_json::to_vec(&Header {
+        name: stash.name.clone(),
+        meta,
+    })?;
+    let mut recipient = age::scrypt::Recipient::new(passphrase(&key));
+    // the key is random, there's nothing to make guessing harder for
+    recipient.set_work_factor(1);
+    let encryptor = age::Encryptor::with_recipients(std::iter::once(&recipient as _))?;
+    let mut encrypted = encryptor.wrap_output(stream.get_mut())?;
+    encrypted.write_all(&(header.len() as u32).to_be_bytes())?;
+    encrypted.write_all(&header)?;
+    std::io::copy(&mut store.reader(stash)?, &mut encrypted)?;
+    encrypted.finish()?.flush()?;
+    eprintln!("Sent {}", stash.id());
+    Ok(())
+}
+
+/// Connects to `send` with the code it printed and pushes the stash it sends,
+/// under its name in the other store unless given one.
+pub fn receive(store: &dyn StorageBackend, code: &str, name: Option<&str>) -> Result<Data> {
+    let (password, address) = code
+        .rsplit_once('@')
+        .ok_or_else(|| eyre!("expected a code like 7-copper-lantern@host:port, got {code}"))?;
+    let stream = TcpStream::connect(address)
+        .map_err(|e| eyre!("couldn't connect to {address}, is `stash send` still running? {e}"))?;
+    let peer = stream.peer_addr()?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    let mut stream = BufReader::new(stream);
+    let key = exchange_key(&mut stream, password)?;
+    write_message(stream.get_mut(), &confirm(&key, b"receiver"))?;
+    // the sender hangs up on a wrong code
+    let confirmation = read_message(&mut stream).unwrap_or_default();
+    if !confirmation_is(&key, b"sender", &confirmation) {
+        return Err(eyre!(
+            "Wrong code, check it and have the other machine send again"
+        ));
+    }
+
+    let decryptor = age::Decryptor::new(stream)?;
+    let mut identity = age::scrypt::Identity::new(passphrase(&key));
+    identity.set_max_work_factor(1);
+    let mut decrypted = decryptor
+        .decrypt(std::iter::once(&identity as &dyn age::Identity))
+        .map_err(|e| eyre!("couldn't decrypt what was sent: {e}"))?;
+    let mut length = [0; 4];
+    decrypted.read_exact(&mut length)?;
+    let mut header = vec![0; u32::from_be_bytes(length) as usize];
+    decrypted.read_exact(&mut header)?;
+    let Header {
+        name: sent_name,
+        meta: sent,
+    } = serde_json::from_slice(&header)?;
+    // only what describes the contents, the sender can claim anything about
+    // where they came from, and `origin` is where `pop --restore` would write
+    let meta = Meta {
+        message: sent.message,
+        tags: sent.tags,
+        filename: sent.filename,
+        lang: sent.lang,
+        archive: sent.archive,
+        format: sent.format,
+        secret: sent.secret,
+        git: sent.git,
+        notes: vec![crate::meta::Note {
+            time: crate::format_time(std::time::SystemTime::now())?,
+            text: format!("Received from {peer}"),
+        }],
+        ..Meta::captured()
+    };
+    // if the connection breaks off, decrypting fails before the stash is complete
+    // and nothing is pushed
+    crate::push(store, name.unwrap_or(&sent_name), &meta, &mut decrypted)
+}
+
+/// `send` as announced over mDNS, until it's dropped.
+struct Advertisement {
+    daemon: mdns_sd::ServiceDaemon,
+    fullname: String,
+}
+
+impl Advertisement {
+    fn new(stash: &Data, password: &str, ip: &str, port: u16) -> Result<Advertisement> {
+        let daemon = mdns_sd::ServiceDaemon::new()?;
+        let host = gethostname::gethostname().to_string_lossy().into_owned();
+        let properties = [
+            ("id", stash.id().to_string()),
+            ("size", stash.size.to_string()),
+            ("code", password.to_owned()),
+        ];
+        let service = mdns_sd::ServiceInfo::new(
+            SERVICE,
+            &format!("{host} {port}"),
+            &format!("{host}.local."),
+            ip,
+            port,
+            &properties[..],
+        )?;
+        let fullname = service.get_fullname().to_owned();
+        daemon.register(service)?;
+        Ok(Advertisement { daemon, fullname })
+    }
+}
+
+impl Drop for Advertisement {
+    fn drop(&mut self) {
+        // so that receivers forget about it now rather than when it expires
+        if let Ok(unregistered) = self.daemon.unregister(&self.fullname) {
+            let _ = unregistered.recv_timeout(Duration::from_secs(1));
+        }
+        let _ = self.daemon.shutdown();
+    }
+}
+
+/// A `send` found on the local network.
+pub struct Sender {
+    /// What `receive` takes
+    pub code: String,
+    /// Of the stash, in the sender's store
+    pub id: String,
+    pub size: u64,
+    pub host: String,
+}
+
+/// The senders advertising themselves on the local network.
+pub fn discover() -> Result<Vec<Sender>> {
+    let daemon = mdns_sd::ServiceDaemon::new()?;
+    let events = daemon.browse(SERVICE)?;
+    let deadline = Instant::now() + DISCOVERY_TIME;
+    let mut senders: Vec<Sender> = vec![];
+    while let Ok(event) = events.recv_deadline(deadline) {
+        let mdns_sd::ServiceEvent::ServiceResolved(service) = event else {
+            continue;
+        };
+        let password = service.get_property_val_str("code");
+        let address = service.get_addresses_v4().into_iter().next();
+        let (Some(password), Some(address)) = (password, address) else {
+            continue;
+        };
+        let code = format!("{password}@{address}:{}", service.get_port());
+        if senders.iter().any(|sender| sender.code == code) {
+            continue;
+        }
+        senders.push(Sender {
+            code,
+            id: service.get_property_val_str("id").unwrap_or("?").to_owned(),
+            size: service
+                .get_property_val_str("size")
+                .and_then(|size| size.parse().ok())
+                .unwrap_or(0),
+            host: service.get_hostname().trim_end_matches('.').to_owned(),
+        });
+    }
+    let _ = daemon.shutdown();
+    Ok(senders)
+}
+
+/// A number and two words, like `7-copper-lantern`.
+fn password() -> Result<String> {
+    let words: Vec<_> = WORDS.split_whitespace().collect();
+    let [number, first, second] = crate::random_bytes()?;
+    Ok(format!(
+        "{}-{}-{}",
+        number % 100,
+        words[first as usize % words.len()],
+        words[second as usize % words.len()]
+    ))
+}
+
+/// The address the other machine is most likely to reach this one on.
+fn local_ip() -> String {
+    // connecting a UDP socket sends nothing, it only picks the interface
+    std::net::UdpSocket::bind("0.0.0.0:0")
+        .and_then(|socket| {
+            socket.connect("192.0.2.1:9")?;
+            socket.local_addr()
+        })
+        .map_or("127.0.0.1".to_owned(), |address| address.ip().to_string())
+}
+
+fn exchange_key(stream: &mut BufReader<TcpStream>, password: &str) -> Result<Vec<u8>> {
+    let (spake, message) = Spake2::<Ed25519Group>::start_symmetric(
+        &Password::new(password.as_bytes()),
+        &Identity::new(APP_ID),
+    );
+    write_message(stream.get_mut(), &message)?;
+    let other = read_message(stream)?;
+    spake
+        .finish(&other)
+        .map_err(|e| eyre!("the key exchange failed: {e:?}"))
+}
+
+/// Proves knowledge of the key without giving it away.
+fn confirm(key: &[u8], role: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any length");
+    mac.update(role);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn confirmation_is(key: &[u8], role: &[u8], confirmation: &[u8]) -> bool {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any length");
+    mac.update(role);
+    mac.verify_slice(confirmation).is_ok()
+}
+
+fn passphrase(key: &[u8]) -> SecretString {
+    SecretString::from(
+        key.iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect::<String>(),
+    )
+}
+
+fn write_message(stream: &mut TcpStream, message: &[u8]) -> Result<()> {
+    stream.write_all(&[message.len() as u8])?;
+    stream.write_all(message)?;
+    Ok(())
+}
+
+fn read_message(stream: &mut impl Read) -> Result<Vec<u8>> {
+    let mut length = [0];
+    stream.read_exact(&mut length)?;
+    let mut message = vec![0; length[0] as usize];
+    stream.read_exact(&mut message)?;
+    Ok(message)
+}
@@ -0,0 +1,70 @@
+use std::io::{Read, Write};
+use std::process::{Command, ExitStatus, Stdio};
+
+use eyre::{eyre, Result};
+
+use crate::meta::Meta;
+use crate::storage::StorageBackend;
+
+/// Runs `command` with our stdin and stdout passed through, while stashing what
+/// went in as `name/input` and what came out as `name/output`.
+pub fn wrap(store: &dyn StorageBackend, name: &str, command: &[String]) -> Result<ExitStatus> {
+    let (program, args) = command
+        .split_first()
+        .ok_or_else(|| eyre!("no command to wrap"))?;
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    let child_stdin = child.stdin.take().unwrap();
+    let child_stdout = child.stdout.take().unwrap();
+
+    std::thread::scope(|scope| -> Result<()> {
+        // stdin is fed from a thread, the child may only start writing output
+        // once it has read all of its input
+        let input = scope.spawn(move || -> Result<()> {
+            let mut tee = Tee {
+                reader: std::io::stdin().lock(),
+                copy: Some(child_stdin),
+            };
+            crate::push(store, &format!("{name}/input"), &Meta::captured(), &mut tee)?;
+            // `tee` is dropped here, closing the child's stdin
+            Ok(())
+        });
+        let mut tee = Tee {
+            reader: child_stdout,
+            copy: Some(std::io::stdout().lock()),
+        };
+        crate::push(
+            store,
+            &format!("{name}/output"),
+            &Meta::captured(),
+            &mut tee,
+        )?;
+        input.join().unwrap()
+    })?;
+
+    Ok(child.wait()?)
+}
+
+/// Passes everything read from `reader` on to `copy`.
+/// If the other end stops reading, the rest is still read, just not copied.
+struct Tee<R, W> {
+    reader: R,
+    copy: Option<W>,
+}
+
+impl<R: Read, W: Write> Read for Tee<R, W> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.reader.read(buf)?;
+        if let Some(copy) = &mut self.copy {
+            // whoever is downstream shouldn't wait on our buffer
+            match copy.write_all(&buf[..n]).and_then(|_| copy.flush()) {
+                Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => self.copy = None,
+                result => result?,
+            }
+        }
+        Ok(n)
+    }
+}
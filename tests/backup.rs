@@ -0,0 +1,32 @@
+mod common;
+
+use common::Store;
+
+#[test]
+fn backups_are_of_one_profile() {
+    let store = Store::new("backup-profiles");
+    let backups = store.dir.join("backups");
+    let backups = backups.to_str().unwrap();
+    store.push("default-stash", "kept");
+    let output = store.run(&["--profile", "work", "push", "work-stash"], b"work");
+    assert!(output.status.success(), "{output:?}");
+
+    let output = store.run(&["backup", "--to", backups], b"");
+    assert!(output.status.success(), "{output:?}");
+    store.push("default-stash", "after the backup");
+    let output = store.run(&["--profile", "work", "push", "work-stash"], b"work, later");
+    assert!(output.status.success(), "{output:?}");
+
+    let output = store.run(&["restore-backup", backups], b"");
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(store.pop("default-stash"), "kept");
+    assert!(!store
+        .run(&["exists", "default-stash"], b"")
+        .status
+        .success());
+    // the other profile is neither in the backup nor touched by restoring it
+    let output = store.run(&["--profile", "work", "pop", "work-stash"], b"");
+    assert_eq!(output.stdout, b"work, later");
+    let output = store.run(&["profile", "list"], b"");
+    assert_eq!(output.stdout, b"* default\n  work\n");
+}
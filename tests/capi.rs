@@ -0,0 +1,52 @@
+#![cfg(feature = "capi")]
+
+mod common;
+
+use std::ffi::{CStr, CString};
+
+use common::Store;
+use stash::capi::*;
+
+#[test]
+fn pushes_lists_and_pops_through_the_c_api() {
+    let store = Store::new("capi");
+    // the one test in this crate, so nothing else reads the environment meanwhile
+    std::env::set_var("XDG_CONFIG_HOME", store.dir.join("config"));
+    let data_dir = CString::new(store.data_dir().to_str().unwrap()).unwrap();
+    let name = CString::new("logs").unwrap();
+    unsafe {
+        let handle = stash_open(data_dir.as_ptr());
+        assert!(!handle.is_null());
+
+        let mut id = std::ptr::null_mut();
+        let contents = b"hello";
+        let pushed = stash_push(handle, name.as_ptr(), contents.as_ptr(), 5, &mut id);
+        assert_eq!(pushed, 0);
+        assert_eq!(CStr::from_ptr(id).to_str().unwrap(), "logs:0");
+        stash_free_string(id);
+
+        let list = stash_list(handle);
+        let summaries: serde_json::Value =
+            serde_json::from_str(CStr::from_ptr(list).to_str().unwrap()).unwrap();
+        assert_eq!(summaries[0]["id"], "logs:0");
+        assert_eq!(summaries[0]["size"], 5);
+        stash_free_string(list);
+
+        let (mut data, mut len) = (std::ptr::null_mut(), 0);
+        assert_eq!(stash_pop(handle, name.as_ptr(), &mut data, &mut len), 0);
+        assert_eq!(std::slice::from_raw_parts(data, len), b"hello");
+        stash_free_bytes(data, len);
+        assert_eq!(stash_pop(handle, name.as_ptr(), &mut data, &mut len), 1);
+
+        let empty = CString::new("").unwrap();
+        let pushed = stash_push(handle, std::ptr::null(), empty.as_ptr().cast(), 0, &mut id);
+        assert_eq!(pushed, -1);
+        let error = CStr::from_ptr(stash_last_error()).to_str().unwrap();
+        assert!(error.contains("NULL"), "{error}");
+
+        stash_close(handle);
+    }
+    let events = store.run(&["events"], b"");
+    let events = String::from_utf8(events.stdout).unwrap();
+    assert!(events.contains(r#""event":"pop""#), "{events}");
+}
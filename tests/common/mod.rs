@@ -0,0 +1,144 @@
+//! A store to run the binary against in integration tests.
+
+// not every test crate uses all of it
+#![allow(dead_code)]
+
+use std::io::Write;
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::process::{Child, Command, Output, Stdio};
+
+/// A data dir of its own for each test, removed at the end.
+pub struct Store {
+    pub dir: PathBuf,
+}
+
+impl Store {
+    pub fn new(test: &str) -> Store {
+        let dir = std::env::temp_dir().join(format!("stash-test-{test}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        Store { dir }
+    }
+
+    /// Where the stashes are kept, the default data dir.
+    pub fn data_dir(&self) -> PathBuf {
+        self.dir.join("data").join("stash")
+    }
+
+    pub fn command(&self, args: &[&str]) -> Command {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_stash"));
+        command
+            .args(args)
+            .env("XDG_DATA_HOME", self.dir.join("data"))
+            .env("XDG_CONFIG_HOME", self.dir.join("config"));
+        command
+    }
+
+    pub fn run(&self, args: &[&str], stdin: &[u8]) -> Output {
+        let mut child = self
+            .command(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(stdin).unwrap();
+        child.wait_with_output().unwrap()
+    }
+
+    pub fn push(&self, name: &str, contents: &str) {
+        assert!(self
+            .run(&["push", name], contents.as_bytes())
+            .status
+            .success());
+    }
+
+    pub fn pop(&self, id: &str) -> String {
+        let output = self.run(&["pop", id], b"");
+        assert!(output.status.success(), "{output:?}");
+        String::from_utf8(output.stdout).unwrap()
+    }
+}
+
+/// `stash serve` on a free port, stopped when dropped.
+pub struct Server {
+    child: Child,
+    /// `http://127.0.0.1:<port>`
+    pub url: String,
+}
+
+impl Server {
+    pub const TOKEN: &'static str = "test-token";
+
+    pub fn new(store: &Store, args: &[&str]) -> Server {
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let listen = format!("127.0.0.1:{port}");
+        let server = Server {
+            child: store
+                .command(&["serve", "--listen", &listen, "--token", Server::TOKEN])
+                .args(args)
+                .stderr(Stdio::null())
+                .spawn()
+                .unwrap(),
+            url: format!("http://{listen}"),
+        };
+        for _ in 0..100 {
+            if TcpStream::connect(&listen).is_ok() {
+                return server;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(50));
+        }
+        panic!("stash serve didn't start listening on {listen}");
+    }
+
+    /// Status and body of a `GET` of `path` with the token.
+    pub fn get(&self, path: &str) -> (u16, String) {
+        let response = agent()
+            .get(format!("{}{path}", self.url))
+            .header("Authorization", format!("Bearer {}", Server::TOKEN))
+            .call()
+            .unwrap();
+        read(response)
+    }
+
+    /// Status and body of a `POST` of `body` to `path` with the token.
+    pub fn post(&self, path: &str, body: &[u8]) -> (u16, String) {
+        let response = agent()
+            .post(format!("{}{path}", self.url))
+            .header("Authorization", format!("Bearer {}", Server::TOKEN))
+            .send(body)
+            .unwrap();
+        read(response)
+    }
+}
+
+/// Doesn't turn error statuses into errors, tests check them.
+fn agent() -> ureq::Agent {
+    ureq::Agent::config_builder()
+        .http_status_as_error(false)
+        .build()
+        .into()
+}
+
+fn read(mut response: ureq::http::Response<ureq::Body>) -> (u16, String) {
+    let status = response.status().as_u16();
+    (status, response.body_mut().read_to_string().unwrap())
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+impl Drop for Store {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
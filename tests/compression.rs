@@ -0,0 +1,45 @@
+mod common;
+
+use std::io::Write;
+
+use common::Store;
+
+fn gzipped(contents: &[u8]) -> Vec<u8> {
+    let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+    encoder.write_all(contents).unwrap();
+    encoder.finish().unwrap()
+}
+
+#[test]
+fn compressed_input_is_stored_as_it_is() {
+    let store = Store::new("already-compressed");
+    let gzip = gzipped(&b"compressible ".repeat(1000));
+    let output = store.run(&["push", "--compress", "logs"], &gzip);
+    assert!(output.status.success(), "{output:?}");
+
+    let stored = std::fs::read(store.data_dir().join("0_logs")).unwrap();
+    assert_eq!(stored, gzip);
+    let output = store.run(&["show", "--meta", "logs"], b"");
+    let meta = String::from_utf8(output.stdout).unwrap();
+    assert!(meta.contains("format: gzip"), "{meta}");
+    assert!(!meta.contains("encoding"), "{meta}");
+}
+
+#[test]
+fn store_opts_out_of_compression_from_the_config() {
+    let store = Store::new("store-raw");
+    std::fs::create_dir_all(store.dir.join("config/stash")).unwrap();
+    std::fs::write(
+        store.dir.join("config/stash/config.toml"),
+        "compress = true\n",
+    )
+    .unwrap();
+    let output = store.run(&["push", "--store", "raw"], b"plain text");
+    assert!(output.status.success(), "{output:?}");
+    store.push("compressed", "plain text");
+
+    let raw = std::fs::read(store.data_dir().join("0_raw")).unwrap();
+    assert_eq!(raw, b"plain text");
+    let compressed = std::fs::read(store.data_dir().join("1_compressed")).unwrap();
+    assert!(compressed.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]));
+}
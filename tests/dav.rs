@@ -0,0 +1,101 @@
+mod common;
+
+use base64::Engine;
+use common::{Server, Store};
+
+/// Status and body of a request with basic auth, like file managers do it.
+fn request(server: &Server, method: &str, path: &str, depth: &str) -> (u16, String) {
+    let credentials =
+        base64::engine::general_purpose::STANDARD.encode(format!("anyone:{}", Server::TOKEN));
+    let request = ureq::http::Request::builder()
+        .method(method)
+        .uri(format!("{}{path}", server.url))
+        .header("Authorization", format!("Basic {credentials}"))
+        .header("Depth", depth)
+        .body(())
+        .unwrap();
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .http_status_as_error(false)
+        .allow_non_standard_methods(true)
+        .build()
+        .into();
+    let mut response = agent.run(request).unwrap();
+    let status = response.status().as_u16();
+    (status, response.body_mut().read_to_string().unwrap())
+}
+
+fn hrefs(xml: &str) -> Vec<&str> {
+    xml.split("<D:href>")
+        .skip(1)
+        .map(|rest| rest.split("</D:href>").next().unwrap())
+        .collect()
+}
+
+#[test]
+fn browses_names_as_collections() {
+    let store = Store::new("dav-browse");
+    store.push("logs", "old");
+    store.push("logs", "new");
+    store.push("logs/build", "compiling");
+    store.push("", "unnamed");
+    let server = Server::new(&store, &[]);
+
+    let (status, xml) = request(&server, "PROPFIND", "/dav/", "1");
+    assert_eq!(status, 207, "{xml}");
+    assert_eq!(hrefs(&xml), ["/dav/", "/dav/0", "/dav/logs/"]);
+    assert!(xml.contains("<D:collection/>"));
+
+    let (status, xml) = request(&server, "PROPFIND", "/dav/logs", "1");
+    assert_eq!(status, 207, "{xml}");
+    assert_eq!(
+        hrefs(&xml),
+        [
+            "/dav/logs/",
+            "/dav/logs/0",
+            "/dav/logs/1",
+            "/dav/logs/build/"
+        ]
+    );
+    assert!(xml.contains("<D:getcontentlength>3</D:getcontentlength>"));
+
+    let (_, xml) = request(&server, "PROPFIND", "/dav/logs", "0");
+    assert_eq!(hrefs(&xml), ["/dav/logs/"]);
+}
+
+#[test]
+fn fetches_stashes_as_resources() {
+    let store = Store::new("dav-fetch");
+    store.push("logs", "old");
+    store.push("logs", "new");
+    store.push("logs/build", "compiling");
+    let server = Server::new(&store, &[]);
+
+    assert_eq!(
+        request(&server, "GET", "/dav/logs/1", "0"),
+        (200, "old".to_owned())
+    );
+    assert_eq!(
+        request(&server, "GET", "/dav/logs/build/0", "0"),
+        (200, "compiling".to_owned())
+    );
+    assert_eq!(request(&server, "GET", "/dav/logs/2", "0").0, 404);
+    assert_eq!(request(&server, "GET", "/dav/logs", "0").0, 405);
+    // read-only
+    assert_eq!(request(&server, "DELETE", "/dav/logs/0", "0").0, 405);
+    assert_eq!(store.pop("logs"), "new");
+}
+
+#[test]
+fn asks_for_credentials() {
+    let store = Store::new("dav-auth");
+    let server = Server::new(&store, &[]);
+    let response = ureq::Agent::config_builder()
+        .http_status_as_error(false)
+        .build()
+        .new_agent()
+        .get(format!("{}/dav/", server.url))
+        .call()
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+    assert!(response.headers().contains_key("WWW-Authenticate"));
+}
@@ -0,0 +1,49 @@
+mod common;
+
+use common::Store;
+
+#[test]
+fn pops_an_older_stash_by_index() {
+    let store = Store::new("older");
+    for contents in ["first", "second", "third"] {
+        store.push("logs", contents);
+    }
+    assert_eq!(store.pop("logs:1"), "second");
+    // the stashes above it move down to take its place
+    assert_eq!(store.pop("logs:1"), "first");
+    assert_eq!(store.pop("logs:0"), "third");
+}
+
+#[test]
+fn pops_the_newest_stash_of_a_name() {
+    let store = Store::new("newest");
+    store.push("logs", "old");
+    store.push("logs", "new");
+    store.push("other", "unrelated");
+    assert_eq!(store.pop("logs"), "new");
+    assert_eq!(store.pop("logs"), "old");
+}
+
+#[test]
+fn pops_unnamed_stashes_by_bare_index() {
+    let store = Store::new("unnamed");
+    store.push("", "a");
+    store.push("", "b");
+    assert_eq!(store.pop("1"), "a");
+    assert_eq!(store.pop("0"), "b");
+}
+
+#[test]
+fn pops_by_index_without_listing_the_data_dir() {
+    let store = Store::new("indexed");
+    store.push("logs", "first");
+    store.push("logs", "second");
+    // builds the index
+    store.run(&["list"], b"");
+    std::fs::write(store.data_dir().join("stray"), "").unwrap();
+
+    let output = store.run(&["pop", "logs:1"], b"");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "first");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(!stderr.contains("unrecognized file"), "{stderr}");
+}
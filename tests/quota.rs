@@ -0,0 +1,74 @@
+mod common;
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+
+use common::Store;
+
+fn store_with_quota(test: &str) -> Store {
+    let store = Store::new(test);
+    std::fs::create_dir_all(store.dir.join("config/stash")).unwrap();
+    std::fs::write(
+        store.dir.join("config/stash/config.toml"),
+        "[quota.logs]\nmax_count = 2\n",
+    )
+    .unwrap();
+    store
+}
+
+fn events(store: &Store) -> String {
+    let output = store.run(&["events"], b"");
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn evicted_stashes_are_deleted_for_good() {
+    let store = store_with_quota("evict");
+    for contents in ["first", "second", "third"] {
+        store.push("logs", contents);
+    }
+    let output = store.run(&["restore", "--list"], b"");
+    assert!(output.stdout.is_empty(), "{output:?}");
+    assert!(events(&store).contains(r#""event":"evict""#));
+    assert_eq!(store.pop("logs:1"), "second");
+}
+
+#[test]
+fn strict_pushes_fail_before_stashing_anything() {
+    let store = store_with_quota("strict");
+    store.push("logs", "first");
+    store.push("logs", "second");
+    let before = events(&store);
+
+    let output = store.run(&["push", "--strict", "logs"], b"third");
+    assert!(!output.status.success(), "{output:?}");
+    assert_eq!(events(&store), before);
+    assert_eq!(store.pop("logs"), "second");
+    assert_eq!(store.pop("logs"), "first");
+}
+
+#[test]
+fn fetched_stashes_count_towards_the_quota() {
+    let store = store_with_quota("fetch");
+    store.push("logs", "first");
+    store.push("logs", "second");
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/", listener.local_addr().unwrap());
+    let server = std::thread::spawn(move || {
+        let (mut connection, _) = listener.accept().unwrap();
+        // the request ends with an empty line
+        let mut lines = BufReader::new(&connection).lines();
+        while !lines.next().unwrap().unwrap().is_empty() {}
+        connection
+            .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 7\r\nconnection: close\r\n\r\nfetched")
+            .unwrap();
+    });
+    let output = store.run(&["fetch", "--name", "logs", &url], b"");
+    server.join().unwrap();
+    assert!(output.status.success(), "{output:?}");
+
+    assert!(events(&store).contains(r#""event":"evict""#));
+    assert_eq!(store.pop("logs"), "fetched");
+    assert_eq!(store.pop("logs"), "second");
+}
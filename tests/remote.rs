@@ -0,0 +1,82 @@
+mod common;
+
+use common::{Server, Store};
+
+/// Runs `stash` with `--remote` from a store of its own, which stays empty.
+fn remote(local: &Store, server: &Server, args: &[&str], stdin: &[u8]) -> String {
+    let remote_args = ["--remote", &server.url, "--remote-token", Server::TOKEN];
+    let output = local.run(&[&remote_args[..], args].concat(), stdin);
+    assert!(output.status.success(), "{args:?}: {output:?}");
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn works_on_the_servers_stashes() {
+    let store = Store::new("remote-server");
+    let local = Store::new("remote-client");
+    store.push("logs", "first\n");
+    let server = Server::new(&store, &[]);
+
+    remote(&local, &server, &["push", "logs"], b"second\n");
+    remote(&local, &server, &["push", "notes"], b"a\nb\nc\n");
+    let ids: Vec<_> = remote(&local, &server, &["list", "-0"], b"")
+        .split_terminator('\0')
+        .map(str::to_owned)
+        .collect();
+    assert_eq!(ids, ["logs:1", "logs:0", "notes:0"]);
+    let listing = remote(&local, &server, &["list", "logs"], b"");
+    let lines: Vec<_> = listing.lines().collect();
+    assert_eq!(lines.len(), 2, "{listing}");
+    assert!(lines[1].starts_with("logs:0  "), "{listing}");
+
+    assert_eq!(remote(&local, &server, &["show", "logs:1"], b""), "first\n");
+    assert_eq!(
+        remote(&local, &server, &["show", "--tail", "1", "notes"], b""),
+        "c\n"
+    );
+    assert_eq!(remote(&local, &server, &["pop", "logs"], b""), "second\n");
+    remote(&local, &server, &["drop", "notes"], b"");
+    assert_eq!(store.pop("logs"), "first\n");
+    assert!(!store.run(&["show", "notes"], b"").status.success());
+    assert!(!local.data_dir().join("meta").exists());
+}
+
+#[test]
+fn refuses_what_the_api_cant_do() {
+    let store = Store::new("remote-unsupported");
+    let local = Store::new("remote-unsupported-client");
+    store.push("logs", "contents");
+    let server = Server::new(&store, &[]);
+    let remote_args = ["--remote", &server.url, "--remote-token", Server::TOKEN];
+    for args in [
+        &["rename", "logs", "other"][..],
+        &["push", "--tag", "x"],
+        &["show", "--hex"],
+    ] {
+        let output = local.run(&[&remote_args[..], args].concat(), b"");
+        assert!(!output.status.success(), "{args:?}: {output:?}");
+    }
+
+    let url = format!("{}/#wrong-token", server.url);
+    let output = local.run(&["--remote", &url, "list"], b"");
+    assert!(!output.status.success(), "{output:?}");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("401"));
+}
+
+#[test]
+fn only_deletes_the_stash_that_was_fetched() {
+    let store = Store::new("remote-versions");
+    store.push("logs", "first\n");
+    let server = Server::new(&store, &[]);
+    let client = stash::stash_client::Client::new(&server.url, Server::TOKEN);
+
+    let fetched = client.get("logs").unwrap().version;
+    assert!(fetched.is_some());
+    client.push("logs", &mut &b"second\n"[..]).unwrap();
+    let error = client.delete("logs", fetched.as_deref()).unwrap_err();
+    assert!(error.to_string().contains("412"), "{error}");
+
+    let fetched = client.get("logs").unwrap().version;
+    client.delete("logs", fetched.as_deref()).unwrap();
+    assert_eq!(store.pop("logs"), "first\n");
+}
@@ -0,0 +1,84 @@
+mod common;
+
+use common::Store;
+
+fn store_with_secret(test: &str) -> Store {
+    let store = Store::new(test);
+    let output = store.run(&["push", "--secret", "password"], b"hunter2");
+    assert!(output.status.success(), "{output:?}");
+    store.push("notes", "hunter2 is the password");
+    store
+}
+
+#[test]
+fn secret_stashes_are_only_printed_with_force() {
+    let store = store_with_secret("show");
+    // stdout is a pipe, not a terminal
+    let output = store.run(&["show", "password"], b"");
+    assert!(!output.status.success(), "{output:?}");
+    assert!(output.stdout.is_empty());
+
+    let output = store.run(&["show", "--force", "password"], b"");
+    assert_eq!(output.stdout, b"hunter2");
+}
+
+#[test]
+fn listings_hide_secret_contents() {
+    let store = store_with_secret("fzf-source");
+    let output = store.run(&["fzf-source"], b"");
+    let listing = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        listing.contains("password:0\tjust now\t(secret)"),
+        "{listing}"
+    );
+    assert!(listing.contains("hunter2 is the password"), "{listing}");
+}
+
+#[test]
+fn grep_and_export_skip_secret_stashes() {
+    let store = store_with_secret("skip");
+    let output = store.run(&["grep", "-l", "hunter2"], b"");
+    assert_eq!(output.stdout, b"notes:0\n");
+    let output = store.run(&["grep", "-l", "--force", "hunter2"], b"");
+    assert_eq!(output.stdout, b"notes:0\npassword:0\n");
+
+    let output = store.run(&["export", "--ndjson"], b"");
+    assert_eq!(output.stdout.split(|&b| b == b'\n').count(), 2);
+    let output = store.run(&["export", "--ndjson", "--force"], b"");
+    assert_eq!(output.stdout.split(|&b| b == b'\n').count(), 3);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn secret_stashes_are_edited_in_memory() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let store = store_with_secret("edit");
+    let editor = store.dir.join("editor");
+    std::fs::write(
+        &editor,
+        "#!/bin/sh\necho \"$1\" >> \"$(dirname \"$0\")/paths\"\nprintf hunter3 > \"$1\"\n",
+    )
+    .unwrap();
+    std::fs::set_permissions(&editor, std::fs::Permissions::from_mode(0o700)).unwrap();
+
+    for id in ["password", "notes"] {
+        let output = store
+            .command(&["edit", id])
+            .env("EDITOR", &editor)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{output:?}");
+    }
+    let paths = std::fs::read_to_string(store.dir.join("paths")).unwrap();
+    let paths: Vec<_> = paths.lines().collect();
+    assert!(paths[0].starts_with("/proc/self/fd/"), "{paths:?}");
+    // only the secret stash stays out of the temp dir
+    assert!(
+        paths[1].starts_with(std::env::temp_dir().to_str().unwrap()),
+        "{paths:?}"
+    );
+
+    let output = store.run(&["show", "--force", "password"], b"");
+    assert_eq!(output.stdout, b"hunter3");
+}
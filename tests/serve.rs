@@ -0,0 +1,222 @@
+mod common;
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+
+use common::{Server, Store};
+
+fn with_config(test: &str, config: &str) -> Store {
+    let store = Store::new(test);
+    std::fs::create_dir_all(store.dir.join("config/stash")).unwrap();
+    std::fs::write(store.dir.join("config/stash/config.toml"), config).unwrap();
+    store
+}
+
+/// The ids in a response of `GET /stashes`.
+fn ids(store: &Server, query: &str) -> Vec<String> {
+    let (status, body) = store.get(&format!("/stashes{query}"));
+    assert_eq!(status, 200, "{body}");
+    let stashes: serde_json::Value = serde_json::from_str(&body).unwrap();
+    stashes
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|stash| stash["id"].as_str().unwrap().to_owned())
+        .collect()
+}
+
+#[test]
+fn lists_stashes_matching_the_query() {
+    let store = Store::new("serve-query");
+    store.push("logs/build", "compiling\nerror: oops\n");
+    store.push("logs/test", "all good\n");
+    store.push("notes", "error handling ideas\n");
+    assert!(store
+        .run(&["push", "--tag", "ci", "logs/test"], b"error: flaky\n")
+        .status
+        .success());
+    let server = Server::new(&store, &[]);
+
+    assert_eq!(ids(&server, "").len(), 4);
+    assert_eq!(
+        ids(&server, "?name=logs/*"),
+        ["logs/test:0", "logs/test:1", "logs/build:0"]
+    );
+    assert_eq!(ids(&server, "?tag=ci"), ["logs/test:0"]);
+    assert_eq!(
+        ids(&server, "?q=%5Eerror%3A"),
+        ["logs/test:0", "logs/build:0"]
+    );
+    assert_eq!(
+        ids(&server, "?name=logs%2F*&q=error&regex=build"),
+        ["logs/build:0"]
+    );
+    assert_eq!(ids(&server, "?q=error+handling"), ["notes:0"]);
+    assert_eq!(ids(&server, "?since=1h").len(), 4);
+    assert!(ids(&server, "?until=1h").is_empty());
+    assert_eq!(ids(&server, "?min_size=15&max_size=1K").len(), 2);
+}
+
+#[test]
+fn rejects_invalid_queries() {
+    let store = Store::new("serve-invalid-query");
+    let server = Server::new(&store, &[]);
+    for query in [
+        "?since=yesterday",
+        "?since=18446744073709551615s",
+        "?q=(",
+        "?min_size=lots",
+        "?sort=name",
+    ] {
+        let (status, body) = server.get(&format!("/stashes{query}"));
+        assert_eq!(status, 400, "{query}: {body}");
+    }
+}
+
+#[test]
+fn searches_skip_secret_stashes() {
+    let store = Store::new("serve-secret-query");
+    assert!(store
+        .run(&["push", "--secret", "token"], b"hunter2\n")
+        .status
+        .success());
+    let server = Server::new(&store, &[]);
+    assert_eq!(ids(&server, "?name=token"), ["token:0"]);
+    assert!(ids(&server, "?q=hunter").is_empty());
+}
+
+#[test]
+fn refuses_bodies_over_the_configured_size() {
+    let store = with_config("serve-max-body", "[serve]\nmax_body = 10\n");
+    let server = Server::new(&store, &[]);
+    assert_eq!(server.post("/stashes/small", b"0123456789").0, 201);
+    assert_eq!(server.post("/stashes/large", b"0123456789a").0, 413);
+    assert_eq!(store.pop("small"), "0123456789");
+}
+
+#[test]
+fn limits_the_requests_per_client() {
+    let store = with_config("serve-rate-limit", "[serve]\nrequests_per_minute = 2\n");
+    let server = Server::new(&store, &[]);
+    assert_eq!(server.get("/stashes").0, 200);
+    assert_eq!(server.get("/stashes").0, 200);
+    assert_eq!(server.get("/stashes").0, 429);
+    assert_eq!(server.post("/stashes/name", b"contents").0, 429);
+}
+
+#[test]
+fn turns_away_requests_beyond_the_connection_limit() {
+    let store = with_config("serve-max-connections", "[serve]\nmax_connections = 1\n");
+    let server = Server::new(&store, &[]);
+    // stays busy waiting for the body, small ones are read before it's handled
+    let mut slow = TcpStream::connect(server.url.trim_start_matches("http://")).unwrap();
+    write!(
+        slow,
+        "POST /stashes/slow HTTP/1.1\r\nAuthorization: Bearer {}\r\nContent-Length: 10000\r\n\r\n",
+        Server::TOKEN
+    )
+    .unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(200));
+    assert_eq!(server.get("/stashes").0, 503);
+
+    slow.write_all(&[b'x'; 10000]).unwrap();
+    let mut status = String::new();
+    BufReader::new(slow).read_line(&mut status).unwrap();
+    assert!(status.contains("201"), "{status}");
+    // it's done once it has responded
+    std::thread::sleep(std::time::Duration::from_millis(50));
+    assert_eq!(server.get("/stashes").0, 200);
+    assert_eq!(store.pop("slow").len(), 10000);
+}
+
+#[test]
+fn describes_the_api_without_a_token() {
+    let store = Store::new("serve-openapi");
+    let server = Server::new(&store, &[]);
+    let response = ureq::get(format!("{}/openapi.json", server.url))
+        .call()
+        .unwrap()
+        .body_mut()
+        .read_to_string()
+        .unwrap();
+    let spec: serde_json::Value = serde_json::from_str(&response).unwrap();
+    assert!(spec["paths"]["/stashes"]["get"].is_object());
+    assert!(spec["paths"]["/stashes/{id}"]["delete"].is_object());
+    assert!(spec["components"]["schemas"]["Summary"].is_object());
+}
+
+/// `POST` of `body` to `path` with the token, with a `Content-Type` and chunked.
+fn post_chunked(server: &Server, path: &str, content_type: &str, body: &[u8]) -> u16 {
+    let response = ureq::Agent::config_builder()
+        .http_status_as_error(false)
+        .build()
+        .new_agent()
+        .post(format!("{}{path}", server.url))
+        .header("Authorization", format!("Bearer {}", Server::TOKEN))
+        .header("Content-Type", content_type)
+        .send(ureq::SendBody::from_reader(&mut &*body))
+        .unwrap();
+    response.status().as_u16()
+}
+
+#[test]
+fn pushes_the_file_of_multipart_bodies() {
+    let store = Store::new("serve-multipart");
+    let server = Server::new(&store, &[]);
+    // across several reads of the body, with what looks like the start of a boundary
+    let contents: Vec<u8> = (0..200_000)
+        .flat_map(|i: u32| format!("line {i}\r\n--bound\n").into_bytes())
+        .collect();
+    let mut body =
+        b"--boundary\r\nContent-Disposition: form-data; name=\"note\"\r\n\r\nnot this\r\n".to_vec();
+    body.extend_from_slice(
+        b"--boundary\r\nContent-Disposition: form-data; name=\"file\"; filename=\"big.log\"\r\n\
+          Content-Type: text/plain\r\n\r\n",
+    );
+    body.extend_from_slice(&contents);
+    body.extend_from_slice(b"\r\n--boundary--\r\n");
+    let content_type = "multipart/form-data; boundary=boundary";
+    assert_eq!(
+        post_chunked(&server, "/stashes/logs", content_type, &body),
+        201
+    );
+
+    let output = store.run(&["list", "--format", "json"], b"");
+    let listed: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(listed[0]["meta"]["filename"], "big.log");
+    assert!(store.pop("logs").as_bytes() == contents);
+
+    let body =
+        b"--boundary\r\nContent-Disposition: form-data; name=\"note\"\r\n\r\nx\r\n--boundary--\r\n";
+    assert_eq!(
+        post_chunked(&server, "/stashes/logs", content_type, body),
+        400
+    );
+}
+
+#[test]
+fn refuses_chunked_bodies_over_the_configured_size() {
+    let store = with_config("serve-chunked", "[serve]\nmax_body = 10\n");
+    let server = Server::new(&store, &[]);
+    let octets = "application/octet-stream";
+    assert_eq!(
+        post_chunked(&server, "/stashes/small", octets, b"0123456789"),
+        201
+    );
+    assert_eq!(
+        post_chunked(&server, "/stashes/large", octets, b"0123456789a"),
+        413
+    );
+    assert_eq!(store.pop("small"), "0123456789");
+    assert!(!store.run(&["pop", "large"], b"").status.success());
+}
+
+#[test]
+fn refuses_to_encrypt_without_a_passphrase() {
+    let store = with_config("serve-encrypt", "encrypt = true\n");
+    let server = Server::new(&store, &[]);
+    let (status, body) = server.post("/stashes/logs", b"hello\n");
+    assert_eq!(status, 400, "{body}");
+    assert!(body.contains("STASH_PASSPHRASE"), "{body}");
+    assert!(!store.run(&["show", "logs"], b"").status.success());
+}
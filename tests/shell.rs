@@ -0,0 +1,55 @@
+mod common;
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use common::Store;
+
+#[test]
+fn shell_init_prints_completions_and_opt_in_hooks() {
+    let store = Store::new("shell-init");
+    let output = store.run(&["shell-init", "bash"], b"");
+    let init = String::from_utf8(output.stdout).unwrap();
+    assert!(init.contains("complete -F"), "{init}");
+    assert!(!init.contains("STASH_CAPTURE_DIR"), "{init}");
+
+    let output = store.run(&["shell-init", "zsh", "--capture"], b"");
+    let init = String::from_utf8(output.stdout).unwrap();
+    assert!(init.contains("add-zsh-hook preexec"), "{init}");
+}
+
+#[test]
+fn last_output_pushes_the_captured_output_of_the_previous_command() {
+    let store = Store::new("last-output");
+    let bin = std::path::Path::new(env!("CARGO_BIN_EXE_stash"))
+        .parent()
+        .unwrap();
+    let rc = store.dir.join("rc");
+    std::fs::write(
+        &rc,
+        format!(
+            "export PATH={}:$PATH XDG_RUNTIME_DIR={}\neval \"$(stash shell-init bash --capture)\"\n",
+            bin.display(),
+            store.dir.display(),
+        ),
+    )
+    .unwrap();
+    let mut bash = Command::new("bash")
+        .args(["--rcfile", rc.to_str().unwrap(), "-i"])
+        .env("XDG_DATA_HOME", store.dir.join("data"))
+        .env("XDG_CONFIG_HOME", store.dir.join("config"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    bash.stdin
+        .take()
+        .unwrap()
+        .write_all(b"echo out; echo err >&2\nstash last-output logs\n")
+        .unwrap();
+    let output = bash.wait_with_output().unwrap();
+    assert!(output.status.success(), "{output:?}");
+
+    assert_eq!(store.pop("logs"), "out\nerr\n");
+}
@@ -0,0 +1,22 @@
+mod common;
+
+use common::Store;
+
+fn size(store: &Store, id: &str) -> String {
+    let output = store.run(&["size", id], b"");
+    assert!(output.status.success(), "{output:?}");
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn compressed_stashes_report_the_size_of_their_contents() {
+    let store = Store::new("compressed-size");
+    let contents = "the same line over and over\n".repeat(1000);
+    let output = store.run(&["push", "--compress", "logs"], contents.as_bytes());
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(size(&store, "logs"), format!("{}\n", contents.len()));
+
+    let output = store.run(&["push", "--append", "logs"], b"one more\n");
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(size(&store, "logs"), format!("{}\n", contents.len() + 9));
+}
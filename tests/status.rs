@@ -0,0 +1,19 @@
+mod common;
+
+use common::Store;
+
+#[test]
+fn counts_what_list_shows() {
+    let store = Store::new("status");
+    store.push("", "kept");
+    assert!(store
+        .run(&["push", "--expire", "0s"], b"expired")
+        .status
+        .success());
+    store.push("logs", "kept");
+
+    let output = store.run(&["status"], b"");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "2\n");
+    let output = store.run(&["status", "--name", "logs", "--format", "[{count}]"], b"");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "[1]\n");
+}
@@ -0,0 +1,83 @@
+mod common;
+
+use std::os::unix::fs::PermissionsExt;
+use std::process::Output;
+
+use common::Store;
+
+/// Runs `stash sync <args> host` against `remote`, through an `ssh` that runs
+/// the command locally.
+fn sync(store: &Store, remote: &Store, args: &[&str]) -> Output {
+    let bin = store.dir.join("bin");
+    std::fs::create_dir_all(&bin).unwrap();
+    std::fs::write(
+        bin.join("ssh"),
+        "#!/bin/sh\n[ \"$1\" = -- ] || exit 255\nshift 2\nexec sh -c \"$1\"\n",
+    )
+    .unwrap();
+    std::fs::set_permissions(bin.join("ssh"), std::fs::Permissions::from_mode(0o700)).unwrap();
+    let remote_command = format!(
+        "env XDG_DATA_HOME={} XDG_CONFIG_HOME={} {}",
+        remote.dir.join("data").display(),
+        remote.dir.join("config").display(),
+        env!("CARGO_BIN_EXE_stash"),
+    );
+    let path = format!("{}:{}", bin.display(), std::env::var("PATH").unwrap());
+    store
+        .command(&["sync", "--remote-command", &remote_command])
+        .args(args)
+        .arg("host")
+        .env("PATH", path)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn verify_compares_checksums_after_syncing() {
+    let store = Store::new("sync-local");
+    let remote = Store::new("sync-remote");
+    store.push("logs", "first");
+    store.push("notes", "local");
+    remote.push("logs", "remote");
+
+    let output = sync(&store, &remote, &["--verify"]);
+    assert!(output.status.success(), "{output:?}");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("All 3 stash(es) match"), "{stderr}");
+
+    // pushing only leaves the new remote stash out
+    remote.push("logs", "newer");
+    let output = sync(&store, &remote, &["--push", "--verify"]);
+    assert!(!output.status.success(), "{output:?}");
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "logs:0 on host is missing here\n"
+    );
+}
+
+#[test]
+fn only_transfers_what_the_other_side_lacks() {
+    let store = Store::new("sync-delta-local");
+    let remote = Store::new("sync-delta-remote");
+    store.push("logs", "local");
+    remote.push("logs", "remote");
+    assert!(sync(&store, &remote, &[]).status.success());
+
+    remote.push("logs", "newer");
+    let output = sync(&store, &remote, &[]);
+    assert!(output.status.success(), "{output:?}");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    // one pulled, nothing pushed, and nothing sent only to be skipped
+    assert_eq!(
+        stderr.matches("Imported 1 stash(es)").count(),
+        1,
+        "{stderr}"
+    );
+    assert_eq!(
+        stderr.matches("Imported 0 stash(es)").count(),
+        1,
+        "{stderr}"
+    );
+    assert!(!stderr.contains("Skipped"), "{stderr}");
+    assert_eq!(store.pop("logs"), "newer");
+}
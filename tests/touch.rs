@@ -0,0 +1,24 @@
+mod common;
+
+use common::Store;
+
+/// When the stash was created, as `list` tells.
+fn created(store: &Store, id: &str) -> String {
+    let output = store.run(&["list", "--format", "json"], b"");
+    let listed: Vec<serde_json::Value> = serde_json::from_slice(&output.stdout).unwrap();
+    let stash = listed.iter().find(|stash| stash["id"] == id).unwrap();
+    stash["created"].as_str().unwrap().to_owned()
+}
+
+#[test]
+fn touched_stashes_count_as_pushed_now() {
+    let store = Store::new("touch");
+    store.push("logs", "old");
+    store.push("logs", "new");
+    let pushed = created(&store, "logs:1");
+    assert!(store.run(&["touch", "logs:1"], b"").status.success());
+
+    assert_eq!(store.run(&["show", "logs:0"], b"").stdout, b"old");
+    assert_ne!(created(&store, "logs:0"), pushed);
+    assert_eq!(store.run(&["show", "logs:1"], b"").stdout, b"new");
+}
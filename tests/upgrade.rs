@@ -0,0 +1,49 @@
+mod common;
+
+use common::Store;
+
+/// Stashes used to be files named like the stash, without a sequence number.
+fn legacy_store(test: &str) -> Store {
+    let store = Store::new(test);
+    std::fs::create_dir_all(store.data_dir()).unwrap();
+    std::fs::write(store.data_dir().join("notes"), "old notes").unwrap();
+    std::fs::write(store.data_dir().join("with_underscore"), "other").unwrap();
+    store
+}
+
+#[test]
+fn migrates_stashes_from_before_sequence_numbers() {
+    let store = legacy_store("migrate");
+    let output = store.run(&["show", "notes"], b"");
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(output.stdout, b"old notes");
+    assert_eq!(store.pop("with_underscore"), "other");
+
+    // not flagged as unrecognized files afterwards
+    let output = store.run(&["fsck"], b"");
+    assert!(output.status.success(), "{output:?}");
+    assert!(output.stderr.is_empty(), "{output:?}");
+}
+
+#[test]
+fn pushes_on_top_of_migrated_stashes() {
+    let store = legacy_store("push-on-top");
+    store.push("notes", "new notes");
+    assert_eq!(store.pop("notes:1"), "old notes");
+    assert_eq!(store.pop("notes"), "new notes");
+}
+
+#[test]
+fn missing_stashes_are_errors() {
+    let store = Store::new("missing");
+    store.push("notes", "contents");
+    for args in [
+        &["show", "other"][..],
+        &["pop", "notes:1"],
+        &["pop", "--all", "other"],
+    ] {
+        let output = store.run(args, b"");
+        assert!(!output.status.success(), "{args:?}: {output:?}");
+        assert!(output.stdout.is_empty(), "{args:?}: {output:?}");
+    }
+}